@@ -7,14 +7,17 @@ use rustaria::{
 		luna::lib::{reload::Reload, stargate::Stargate},
 		Api,
 	},
-	world::{chunk::layer::BlockLayer, entity::prototype::EntityDesc},
+	world::{chunk::layer::BlockLayer, entity::prototype::EntityDesc, liquid::LiquidDesc},
 };
 
 use crate::{
 	render::{
 		atlas::Atlas,
 		world::{
-			chunk::layer::BlockLayerRendererPrototype,
+			chunk::{
+				layer::BlockLayerRendererPrototype,
+				liquid::{LiquidRenderer, LiquidRendererPrototype},
+			},
 			entity::{EntityRenderer, EntityRendererPrototype},
 		},
 	},
@@ -34,6 +37,7 @@ impl ClientApi {
 			c_carrier: ClientCarrier {
 				block_layer_renderer: Default::default(),
 				entity_renderer: Default::default(),
+				liquid_renderer: Default::default(),
 			},
 			atlas: None,
 		})
@@ -51,6 +55,9 @@ impl ClientApi {
 		reload
 			.stargate
 			.register_builder::<EntityRendererPrototype>();
+		reload
+			.stargate
+			.register_builder::<LiquidRendererPrototype>();
 
 		// reload server stuff
 		self.api.reload(&mut reload).wrap_err("Failed to reload")?;
@@ -68,10 +75,18 @@ impl ClientApi {
 			prototype.get_sprites(&mut sprites);
 		}
 
+		let liquids = reload
+			.stargate
+			.build_registry::<LiquidRendererPrototype>(&self.api.luna.lua)?;
+
 		for (_, prototype) in entities.table.iter() {
 			prototype.get_sprites(&mut sprites);
 		}
 
+		for (_, prototype) in liquids.table.iter() {
+			prototype.get_sprites(&mut sprites);
+		}
+
 		let atlas = Atlas::new(frontend, self, sprites)?;
 
 		let mut block_layer_renderer = Vec::new();
@@ -105,10 +120,25 @@ impl ClientApi {
 			}
 		}
 
+		let mut liquid_renderer = Vec::new();
+		for (id, _, _) in self.api.carrier.liquid.entries() {
+			liquid_renderer.push((id, None));
+		}
+
+		for (_, identifier, prototype) in liquids.into_entries() {
+			if let Some(id) = self.api.carrier.liquid.get_id(&identifier) {
+				let _ = replace(
+					&mut liquid_renderer[id.index()],
+					(id, Some(prototype.bake(&atlas))),
+				);
+			}
+		}
+
 		self.atlas = Some(atlas);
 		self.c_carrier = ClientCarrier {
 			block_layer_renderer: block_layer_renderer.into_iter().collect(),
 			entity_renderer: entity_renderer.into_iter().collect(),
+			liquid_renderer: liquid_renderer.into_iter().collect(),
 		};
 
 		Ok(())
@@ -124,4 +154,5 @@ impl Deref for ClientApi {
 pub struct ClientCarrier {
 	pub block_layer_renderer: IdTable<BlockLayer, Option<BlockLayerRenderer>>,
 	pub entity_renderer: IdTable<EntityDesc, Option<EntityRenderer>>,
+	pub liquid_renderer: IdTable<LiquidDesc, Option<LiquidRenderer>>,
 }
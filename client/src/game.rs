@@ -4,7 +4,9 @@ use glium::Frame;
 use rustaria::{
 	network::{new_networking, packet::ClientBoundPacket, ClientNetwork},
 	player::ServerBoundPlayerPacket,
-	world::{chunk::storage::ChunkStorage, World},
+	tracker::ServerBoundTrackerPacket,
+	ty::block_pos::BlockPos,
+	world::{biome::BiomeAmbient, chunk::storage::ChunkStorage, save::WorldSave, World},
 	Server,
 };
 
@@ -16,7 +18,10 @@ use crate::{
 
 mod network;
 pub mod player;
-mod world;
+pub mod world;
+
+/// How many chunks around the player we want loaded.
+const VIEW_DISTANCE: u32 = 4;
 
 /// This exists when a client has joined a world.
 pub struct ClientGame {
@@ -38,18 +43,23 @@ impl ClientGame {
 		let (network, server_network) = new_networking();
 		// Send join packet
 		network.send(ServerBoundPlayerPacket::Join())?;
+		network.send(ServerBoundTrackerPacket::SetViewDistance(VIEW_DISTANCE))?;
 
+		let client_world = ClientWorld::new(World::new(
+			api,
+			ChunkStorage::with_bounds(world.chunks.bounds()),
+			world.seed(),
+		)?);
+		// There are no items to place blocks with yet, so placing stays creative.
+		let mut server =
+			Server::new(api, server_network, world).wrap_err("Failed to start server")?;
+		server.set_creative(true);
 		Ok(ClientGame {
 			network,
 			player: PlayerSystem::new(api)?,
-			world: ClientWorld::new(World::new(
-				api,
-				ChunkStorage::new(world.chunks.width(), world.chunks.height()),
-			)?),
+			world: client_world,
 			renderer: WorldRenderer::new(frontend, api)?,
-			integrated: Some(
-				Server::new(api, server_network, world).wrap_err("Failed to start server")?,
-			),
+			integrated: Some(server),
 		})
 	}
 
@@ -59,6 +69,19 @@ impl ClientGame {
 
 	pub fn get_viewport(&mut self) -> Option<Viewport> { Some(self.player.get_viewport()) }
 
+	/// The sky of the biome the player stands in, darkened at night.
+	pub fn sky_color(&self, api: &ClientApi) -> [f32; 3] {
+		let biome = BlockPos::try_from(self.player.get_pos())
+			.ok()
+			.and_then(|pos| self.world.biome(pos));
+		let color = match biome {
+			Some(biome) => api.carrier.biome.get(biome).ambient.sky_color,
+			None => BiomeAmbient::default().sky_color,
+		};
+		let light = self.world.time().sky_light();
+		color.map(|channel| channel * light)
+	}
+
 	pub fn tick(
 		&mut self,
 		frontend: &Frontend,
@@ -77,12 +100,14 @@ impl ClientGame {
 				ClientBoundPacket::World(packet) => {
 					self.world.packet(api, packet, debug)?;
 				}
+				ClientBoundPacket::Tracker(packet) => {
+					self.world.tracker_packet(packet);
+				}
 			}
 		}
 		self.player
 			.tick(api, viewport, &mut self.network, &mut self.world)?;
-		self.world
-			.tick_client(api, &self.player, &mut self.network, debug)?;
+		self.world.tick_client(api, debug);
 		self.renderer
 			.tick(frontend, &self.player, &self.world, debug)?;
 		self.world.chunks.reset_dirty();
@@ -111,8 +136,17 @@ impl ClientGame {
 		Ok(())
 	}
 
-	pub fn reset(&mut self) {
+	pub fn save(&self, api: &ClientApi, save: &WorldSave) -> Result<()> {
+		if let Some(server) = &self.integrated {
+			server.save(api, save)?;
+		}
+		Ok(())
+	}
+
+	pub fn reset(&mut self) -> Result<()> {
 		self.world.chunks.reset();
 		self.renderer.reload();
+		self.network.send(ServerBoundTrackerPacket::Reset)?;
+		Ok(())
 	}
 }
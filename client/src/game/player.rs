@@ -18,6 +18,7 @@ use rustaria::{
 			system::network::EntityComponentPacket,
 			EntityWorld,
 		},
+		edit::EditOperation,
 		ServerBoundWorldPacket, World,
 	},
 };
@@ -53,12 +54,16 @@ pub struct PlayerSystem {
 
 	layer_id: Id<BlockLayer>,
 	place_block: Id<BlockDesc>,
-	remove_block: Id<BlockDesc>,
 	arrow: Id<EntityDesc>,
 }
 
 pub enum Press {
 	Use(f32, f32, Id<BlockDesc>),
+	Mine(f32, f32),
+	StopMining,
+	FloodFill(f32, f32, Id<BlockDesc>),
+	Undo,
+	Redo,
 	SpawnEntity(f32, f32, Id<EntityDesc>),
 }
 
@@ -71,7 +76,6 @@ impl PlayerSystem {
 			.unwrap();
 		let layer = api.carrier.block_layer.get(layer_id);
 		let place_block = layer.blocks.get_id(&Identifier::new("dirt")).unwrap();
-		let remove_block = layer.blocks.get_id(&Identifier::new("air")).unwrap();
 		let arrow = api
 			.carrier
 			.entity
@@ -102,7 +106,6 @@ impl PlayerSystem {
 			presses: vec![],
 			layer_id,
 			place_block,
-			remove_block,
 			arrow,
 		})
 	}
@@ -117,19 +120,17 @@ impl PlayerSystem {
 				self.cursor_y = y as f32;
 			}
 			WindowEvent::MouseButton(button, Action::Press, _) => {
-				let x = ((((self.cursor_x / frontend.dimensions.0 as f32) - 0.5) * 2.0)
-					/ frontend.aspect_ratio)
-					* self.zoom;
-				let y = ((((frontend.dimensions.1 as f32 - self.cursor_y)
-					/ frontend.dimensions.1 as f32)
-					- 0.5) * 2.0) * self.zoom;
+				let (x, y) = self.cursor_world(frontend);
 				match button {
 					MouseButton::Button1 => self.presses.push(Press::Use(x, y, self.place_block)),
-					MouseButton::Button2 => self.presses.push(Press::Use(x, y, self.remove_block)),
+					MouseButton::Button2 => self.presses.push(Press::Mine(x, y)),
 					MouseButton::Button3 => self.presses.push(Press::SpawnEntity(x, y, self.arrow)),
 					_ => {}
 				}
 			}
+			WindowEvent::MouseButton(MouseButton::Button2, Action::Release, _) => {
+				self.presses.push(Press::StopMining);
+			}
 			WindowEvent::Key(key, _, action, _) => {
 				match key {
 					Key::W => {
@@ -147,6 +148,12 @@ impl PlayerSystem {
 					Key::Space => {
 						self.jump = !matches!(action, Action::Release);
 					}
+					Key::F if matches!(action, Action::Press) => {
+						let (x, y) = self.cursor_world(frontend);
+						self.presses.push(Press::FloodFill(x, y, self.place_block));
+					}
+					Key::Z if matches!(action, Action::Press) => self.presses.push(Press::Undo),
+					Key::Y if matches!(action, Action::Press) => self.presses.push(Press::Redo),
 					_ => {}
 				}
 
@@ -206,6 +213,32 @@ impl PlayerSystem {
 								))?;
 							}
 						}
+						Press::Mine(x, y) => {
+							if let Ok(pos) = BlockPos::try_from(vec2::<_, WS>(x, y) + viewport.pos)
+							{
+								network.send(ServerBoundWorldPacket::StartMining(
+									pos,
+									self.layer_id,
+								))?;
+							}
+						}
+						Press::StopMining => {
+							network.send(ServerBoundWorldPacket::StopMining)?;
+						}
+						Press::FloodFill(x, y, block_id) => {
+							if let Ok(pos) = BlockPos::try_from(vec2::<_, WS>(x, y) + viewport.pos)
+							{
+								network.send(ServerBoundWorldPacket::Edit(
+									EditOperation::FloodFill {
+										start: pos,
+										layer_id: self.layer_id,
+										block_id,
+									},
+								))?;
+							}
+						}
+						Press::Undo => network.send(ServerBoundWorldPacket::UndoEdit)?,
+						Press::Redo => network.send(ServerBoundWorldPacket::RedoEdit)?,
 						Press::SpawnEntity(x, y, entity) => {
 							network.send(ServerBoundWorldPacket::SpawnEntity(
 								entity,
@@ -308,6 +341,17 @@ impl PlayerSystem {
 
 	pub fn get_viewport(&self) -> Viewport { Viewport::new(self.get_pos(), self.zoom) }
 
+	/// The cursor relative to the player in world units.
+	fn cursor_world(&self, frontend: &Frontend) -> (f32, f32) {
+		let x = ((((self.cursor_x / frontend.dimensions.0 as f32) - 0.5) * 2.0)
+			/ frontend.aspect_ratio)
+			* self.zoom;
+		let y = ((((frontend.dimensions.1 as f32 - self.cursor_y)
+			/ frontend.dimensions.1 as f32)
+			- 0.5) * 2.0) * self.zoom;
+		(x, y)
+	}
+
 	// If the server says a different value try to correct it without freaking the player out.
 	fn correct_offset(&mut self, entity: Entity, entity_world: &EntityWorld) {
 		let server_pos = entity_world
@@ -1,63 +1,51 @@
-use std::ops::{Deref, DerefMut};
+use std::{
+	collections::HashMap,
+	ops::{Deref, DerefMut},
+};
 
-use euclid::{size2, Rect};
 use eyre::Result;
-use fxhash::FxHashSet;
 use rustaria::{
 	api::Api,
 	debug::DebugRendererImpl,
-	network::ClientNetwork,
-	ty::chunk_pos::ChunkPos,
-	world::{
-		chunk::{Chunk, CHUNK_SIZE_F32},
-		ClientBoundWorldPacket, ServerBoundWorldPacket, World,
-	},
+	tracker::ClientBoundTrackerPacket,
+	ty::{block_pos::BlockPos, id::Id},
+	world::{chunk::layer::BlockLayer, ClientBoundWorldPacket, World},
 };
 
-use crate::{ClientApi, PlayerSystem};
+use crate::ClientApi;
 
 pub struct ClientWorld {
 	pub inner: World,
-
-	requested_chunks: FxHashSet<ChunkPos>,
+	/// The crack stage of every block somebody is mining.
+	pub cracks: HashMap<(BlockPos, Id<BlockLayer>), u8>,
 }
 
 impl ClientWorld {
-	pub fn new(inner: World) -> ClientWorld {
+	pub fn new(mut inner: World) -> ClientWorld {
+		inner.set_remote(true);
 		ClientWorld {
 			inner,
-			requested_chunks: Default::default(),
+			cracks: HashMap::new(),
 		}
 	}
 
-	pub fn tick_client(
-		&mut self,
-		api: &ClientApi,
-		player: &PlayerSystem,
-		network: &mut ClientNetwork,
-		debug: &mut impl DebugRendererImpl,
-	) -> Result<()> {
-		let pos = player.get_pos();
-		let rect = Rect::new(pos.to_point(), size2(0.0, 0.0))
-			.inflate(4.0 * CHUNK_SIZE_F32, 4.0 * CHUNK_SIZE_F32)
-			.scale(1.0 / CHUNK_SIZE_F32, 1.0 / CHUNK_SIZE_F32)
-			.round_out();
-		let y_min = rect.origin.y as i64;
-		let y_max = rect.origin.y as i64 + rect.size.height as i64;
-		let x_min = rect.origin.x as i64;
-		let x_max = rect.origin.x as i64 + rect.size.width as i64;
-		for y in y_min..y_max {
-			for x in x_min..x_max {
-				if let Ok(pos) = ChunkPos::try_from((x, y)) {
-					if !self.chunks.contains(pos) && !self.requested_chunks.contains(&pos) {
-						network.send(ServerBoundWorldPacket::RequestChunk(pos))?;
-						self.requested_chunks.insert(pos);
-					}
-				}
+	pub fn tick_client(&mut self, api: &ClientApi, debug: &mut impl DebugRendererImpl) {
+		// Keeps the sky moving smoothly between the time syncs of the server.
+		self.inner.time_mut().tick();
+		self.inner.tick(api, debug);
+		// Only the server syncs block changes.
+		self.inner.drain_block_changes().for_each(drop);
+	}
+
+	pub(crate) fn tracker_packet(&mut self, packet: ClientBoundTrackerPacket) {
+		match packet {
+			ClientBoundTrackerPacket::LoadChunk(pos, chunk) => {
+				self.inner.chunks.insert(pos, chunk);
+			}
+			ClientBoundTrackerPacket::UnloadChunk(pos) => {
+				self.inner.chunks.remove(pos);
 			}
 		}
-		self.inner.tick(api, debug);
-		Ok(())
 	}
 
 	pub(crate) fn packet(
@@ -67,12 +55,54 @@ impl ClientWorld {
 		debug: &mut impl DebugRendererImpl,
 	) -> Result<()> {
 		match packet {
-			ClientBoundWorldPacket::Chunk(chunk_pos, chunk) => {
-				self.inner.chunks.insert(chunk_pos, chunk);
-				self.requested_chunks.remove(&chunk_pos);
+			ClientBoundWorldPacket::SetBlock(pos, layer_id, block_id, state) => {
+				self.place_block_state(api, pos, layer_id, block_id, state);
+			}
+			ClientBoundWorldPacket::SetBlocks(chunk, blocks) => {
+				for (entry, layer_id, block_id, state) in blocks {
+					let pos = BlockPos::new(chunk, entry);
+					self.place_block_state(api, pos, layer_id, block_id, state);
+				}
+			}
+			ClientBoundWorldPacket::SetCrack(pos, layer_id, stage) => match stage {
+				Some(stage) => {
+					self.cracks.insert((pos, layer_id), stage);
+				}
+				None => {
+					self.cracks.remove(&(pos, layer_id));
+				}
+			},
+			ClientBoundWorldPacket::SetBlockEntity(pos, layer_id, block_entity) => {
+				if let Some(chunk) = self.inner.chunks.get_mut_untracked(pos.chunk) {
+					match block_entity {
+						Some(block_entity) => {
+							chunk.block_entities.insert((layer_id, pos.entry), block_entity);
+						}
+						None => {
+							chunk.block_entities.remove(&(layer_id, pos.entry));
+						}
+					}
+				}
 			}
-			ClientBoundWorldPacket::SetBlock(pos, layer_id, block_id) => {
-				self.place_block(api, pos, layer_id, block_id);
+			ClientBoundWorldPacket::SetLiquids(pos, liquids) => {
+				if let Some(chunk) = self.inner.chunks.get_mut_untracked(pos) {
+					chunk.liquids = liquids;
+					self.inner.chunks.mark_liquids(pos);
+				}
+			}
+			ClientBoundWorldPacket::SetLight(pos, light) => {
+				if let Some(chunk) = self.inner.chunks.get_mut_untracked(pos) {
+					chunk.light = light;
+					self.inner.chunks.mark_light(pos);
+				}
+			}
+			ClientBoundWorldPacket::SetBiomes(pos, biomes) => {
+				if let Some(chunk) = self.inner.chunks.get_mut_untracked(pos) {
+					chunk.biomes = biomes;
+				}
+			}
+			ClientBoundWorldPacket::SetTime(time) => {
+				*self.inner.time_mut() = time;
 			}
 			ClientBoundWorldPacket::SpawnEntity(entity, id) => {
 				self.inner.entities.storage.insert(api, entity, id);
@@ -80,6 +110,9 @@ impl ClientWorld {
 			ClientBoundWorldPacket::UpdateEntity(packet) => {
 				self.inner.entities.packet(&packet);
 			}
+			ClientBoundWorldPacket::RemoveEntity(entity) => {
+				self.inner.entities.storage.remove(entity);
+			}
 		}
 		Ok(())
 	}
@@ -94,8 +127,3 @@ impl Deref for ClientWorld {
 impl DerefMut for ClientWorld {
 	fn deref_mut(&mut self) -> &mut Self::Target { &mut self.inner }
 }
-
-pub enum ChunkRequestStatus {
-	Requested,
-	Received(Chunk),
-}
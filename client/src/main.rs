@@ -16,9 +16,12 @@ use glium::Surface;
 use render::ty::viewport::Viewport;
 use rustaria::{
 	debug::DebugCategory,
-	ty::{chunk_pos::ChunkPos, identifier::Identifier},
 	world::{
-		chunk::{storage::ChunkStorage, Chunk, ChunkLayer},
+		biome::BiomeAmbient,
+		chunk::storage::ChunkStorage,
+		gen::{GenSettings, WorldGenerator},
+		random,
+		save::WorldSave,
 		World,
 	},
 	TPS,
@@ -66,6 +69,7 @@ pub struct Client {
 	game: Option<ClientGame>,
 	api: ClientApi,
 	frontend: Frontend,
+	save: WorldSave,
 
 	reload_requested: bool,
 }
@@ -82,6 +86,7 @@ impl Client {
 		//debug.enable(DebugCategory::ChunkBorders);
 		//
 		Ok(Client {
+			save: WorldSave::new(run_dir.join("world")),
 			api: ClientApi::new(run_dir, vec![PathBuf::from("../plugin")])?,
 			viewport: Viewport::new(vec2(0.0, 0.0), 1.0),
 			debug,
@@ -110,6 +115,9 @@ impl Client {
 			}
 		}
 
+		if let Some(game) = &self.game {
+			game.save(&self.api, &self.save)?;
+		}
 		Ok(())
 	}
 
@@ -117,6 +125,9 @@ impl Client {
 		let start = Instant::now();
 		for event in self.frontend.poll_events() {
 			if let WindowEvent::Key(Key::O, _, _, _) = event {
+				if let Some(game) = &self.game {
+					game.save(&self.api, &self.save)?;
+				}
 				self.game = Some(self.join_world()?);
 			}
 			if let WindowEvent::Key(Key::R, _, Action::Press, _) = event {
@@ -145,7 +156,11 @@ impl Client {
 	pub fn draw(&mut self, timing: &Timing) -> Result<()> {
 		let start = Instant::now();
 		let mut frame = self.frontend.start_draw();
-		frame.clear_color(0.10, 0.10, 0.10, 1.0);
+		let [r, g, b] = match &self.game {
+			Some(game) => game.sky_color(&self.api),
+			None => BiomeAmbient::default().sky_color,
+		};
+		frame.clear_color(r, g, b, 1.0);
 
 		if let Some(world) = &mut self.game {
 			if let Some(viewport) = world.get_viewport() {
@@ -172,77 +187,21 @@ impl Client {
 	}
 
 	pub fn join_world(&self) -> Result<ClientGame> {
-		let mut storage = ChunkStorage::new(9, 9);
-
-		for y in 0..9 {
-			for x in 0..9 {
-				storage.insert(
-					ChunkPos {
-						x: x as u32,
-						y: y as u32,
-					},
-					Chunk {
-						layers: self
-							.api
-							.carrier
-							.block_layer
-							.table
-							.iter()
-							.map(|(layer_id, prototype)| {
-								let id = prototype
-									.blocks
-									.get_id(&Identifier::new("dirt"))
-									.expect("where dirt");
-								let dirt = prototype.blocks.get(id).create(id);
-
-								let id = prototype
-									.blocks
-									.get_id(&Identifier::new("air"))
-									.expect("where air");
-								let air = prototype.blocks.get(id).create(id);
-
-								(
-									layer_id,
-									if x == 2 && y == 1 {
-										let a = air;
-										let d = dirt;
-
-										ChunkLayer {
-											data: [
-												[a, a, a, a, a, a, a, a, a, a, a, a, a, a, a, a],
-												[a, d, d, d, a, d, a, a, a, a, a, a, a, a, a, a],
-												[a, a, a, a, a, a, a, a, a, a, a, a, a, a, a, a],
-												[a, d, d, d, a, d, a, a, a, a, a, a, a, a, a, a],
-												[a, d, d, d, a, d, a, a, a, a, a, a, a, a, a, a],
-												[a, d, d, d, a, d, a, a, a, a, a, a, a, a, a, a],
-												[a, a, a, a, a, a, a, a, a, a, a, a, a, a, a, a],
-												[a, a, a, a, a, a, a, a, a, a, a, a, a, a, a, a],
-												[a, a, a, a, a, a, a, a, a, a, a, a, a, a, a, a],
-												[a, a, a, a, a, a, a, a, a, a, a, a, a, a, a, a],
-												[a, a, a, a, a, a, a, a, a, a, a, a, a, a, a, a],
-												[a, a, a, a, a, a, a, a, a, a, a, a, a, a, a, a],
-												[a, a, a, a, a, a, a, a, a, a, a, a, a, a, a, a],
-												[a, a, a, a, a, a, a, a, a, a, a, a, a, a, a, a],
-												[a, a, a, a, a, a, a, a, a, a, a, a, a, a, a, a],
-												[a, a, a, a, a, a, a, a, a, a, a, a, a, a, a, a],
-											],
-										}
-									} else if x == 0 || (y > 0 && x != 2) || x > 3 {
-										ChunkLayer::new_copy(air)
-									} else {
-										ChunkLayer::new_copy(dirt)
-									},
-								)
-							})
-							.collect(),
-					},
-				);
-			}
+		if self.save.exists() {
+			let world = self
+				.save
+				.load(&self.api)
+				.wrap_err("Failed to load world save")?;
+			return ClientGame::new_integrated(&self.frontend, &self.api, world);
 		}
-		ClientGame::new_integrated(
-			&self.frontend,
+
+		let mut world = World::new(&self.api, ChunkStorage::new(32, 16), random::random_seed())?;
+		world.generator = Some(WorldGenerator::from_api(
 			&self.api,
-			World::new(&self.api, storage).unwrap(),
-		)
+			world.seed(),
+			GenSettings::default(),
+		)?);
+		world.enable_light(&self.api);
+		ClientGame::new_integrated(&self.frontend, &self.api, world)
 	}
 }
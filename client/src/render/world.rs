@@ -3,21 +3,25 @@ use glium::{program::SourceCode, Frame, Program};
 use rustaria::world::World;
 
 use crate::{
+	game::world::ClientWorld,
 	render::{
 		ty::{draw::Draw, viewport::Viewport},
-		world::entity::WorldEntityRenderer,
+		world::{crack::WorldCrackRenderer, entity::WorldEntityRenderer},
 	},
 	ClientApi, Debug, Frontend, PlayerSystem, Timing,
 };
 
 pub mod chunk;
+pub mod crack;
 pub mod entity;
 pub mod neighbor;
 
 pub struct WorldRenderer {
 	pos_color_program: Program,
+	light_program: Program,
 	chunk_renderer: WorldChunkRenderer,
 	entity_renderer: WorldEntityRenderer,
+	crack_renderer: WorldCrackRenderer,
 }
 
 impl WorldRenderer {
@@ -49,8 +53,16 @@ impl WorldRenderer {
 					fragment_shader: include_str!("../builtin/pos_tex.frag.glsl"),
 				},
 			)?,
+			light_program: frontend.create_program(SourceCode {
+				vertex_shader: include_str!("../builtin/pos_color.vert.glsl"),
+				tessellation_control_shader: None,
+				tessellation_evaluation_shader: None,
+				geometry_shader: None,
+				fragment_shader: include_str!("../builtin/pos_color.frag.glsl"),
+			})?,
 			chunk_renderer: WorldChunkRenderer::new()?,
 			entity_renderer: WorldEntityRenderer::new(frontend)?,
+			crack_renderer: WorldCrackRenderer::new(frontend)?,
 		})
 	}
 
@@ -71,7 +83,7 @@ impl WorldRenderer {
 		api: &ClientApi,
 		frontend: &Frontend,
 		player: &PlayerSystem,
-		world: &World,
+		world: &ClientWorld,
 		frame: &mut Frame,
 		viewport: &Viewport,
 		debug: &mut Debug,
@@ -85,8 +97,15 @@ impl WorldRenderer {
 			debug,
 			timing,
 		};
-		self.chunk_renderer
-			.draw(api, &world.chunks, &self.pos_color_program, &mut draw)?;
+		self.chunk_renderer.draw(
+			api,
+			&world.chunks,
+			&self.pos_color_program,
+			&self.light_program,
+			&mut draw,
+		)?;
+		self.crack_renderer
+			.draw(&world.cracks, &self.light_program, &mut draw)?;
 		self.entity_renderer.draw(
 			api,
 			player,
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 use euclid::rect;
 use eyre::Result;
@@ -12,20 +12,31 @@ use rustaria::{
 		block_pos::BlockPos,
 		chunk_pos::ChunkPos,
 		direction::{DirMap, Direction},
+		id::Id,
 		Offset,
 	},
-	world::chunk::{layer::BlockLayer, storage::ChunkStorage, CHUNK_SIZE},
+	world::chunk::{
+		layer::BlockLayer,
+		query::BlockArea,
+		storage::{ChunkChanges, ChunkStorage},
+		CHUNK_SIZE,
+	},
 };
 
 use crate::{
 	render::ty::{
-		draw::Draw, mesh_buffer::MeshDrawer, mesh_builder::MeshBuilder, vertex::PosTexVertex,
+		draw::Draw,
+		mesh_buffer::MeshDrawer,
+		mesh_builder::MeshBuilder,
+		vertex::{PosColorVertex, PosTexVertex},
 	},
 	ClientApi, Debug, Frontend,
 };
 
 pub mod block;
 pub mod layer;
+pub mod light;
+pub mod liquid;
 
 pub struct WorldChunkRenderer {
 	chunk_meshes: HashMap<ChunkPos, ChunkMesh>,
@@ -41,18 +52,32 @@ impl WorldChunkRenderer {
 	pub fn reload(&mut self) { self.chunk_meshes.clear() }
 
 	pub fn tick(&mut self, frontend: &Frontend, chunks: &ChunkStorage) -> Result<()> {
-		for pos in chunks.get_dirty() {
-			if let Some(renderer) = self.chunk_meshes.get_mut(pos) {
-				renderer.dirty = true;
+		for (pos, changes) in chunks.get_dirty() {
+			// Blocks connect to the blocks next to them, so changes on the border reach into the
+			// neighbors. Loading or unloading a chunk changes every border.
+			for dir in Direction::values() {
+				let neighbor = pos
+					.checked_offset(dir)
+					.and_then(|neighbor| self.chunk_meshes.get_mut(&neighbor));
+				if let Some(neighbor) = neighbor {
+					if changes.all {
+						neighbor.dirty.layers_all = true;
+					} else {
+						neighbor.dirty.layers.extend(changes.border_layers(dir));
+					}
+				}
+			}
+
+			if !chunks.contains(pos) {
+				// Unloaded
+				self.chunk_meshes.remove(&pos);
+				continue;
+			}
+
+			if let Some(renderer) = self.chunk_meshes.get_mut(&pos) {
+				renderer.dirty.mark(changes);
 			} else {
-				self.chunk_meshes.insert(
-					*pos,
-					ChunkMesh {
-						drawer: frontend.create_drawer()?,
-						builder: MeshBuilder::new(),
-						dirty: true,
-					},
-				);
+				self.chunk_meshes.insert(pos, ChunkMesh::new(frontend)?);
 			}
 		}
 		Ok(())
@@ -63,6 +88,7 @@ impl WorldChunkRenderer {
 		api: &ClientApi,
 		chunk: &ChunkStorage,
 		program: &Program,
+		light_program: &Program,
 		draw: &mut Draw,
 	) -> Result<()> {
 		let uniforms = uniform! {
@@ -76,53 +102,37 @@ impl WorldChunkRenderer {
 			..DrawParameters::default()
 		};
 
-		{
-			let cs = CHUNK_SIZE as f32;
-			let view = draw.viewport.rect.scale(1.0 / cs, 1.0 / cs).round_out();
-			let y_min = view.origin.y as i64;
-			let y_max = view.origin.y as i64 + view.size.height as i64;
-			let x_min = view.origin.x as i64;
-			let x_max = view.origin.x as i64 + view.size.width as i64;
-			for y in y_min..y_max {
-				for x in x_min..x_max {
-					if let Ok(pos) = ChunkPos::try_from((x, y)) {
-						draw_debug!(
-							draw.debug,
-							DebugCategory::ChunkBorders,
-							rect(x as f32 * 16.0, y as f32 * 16.0, 16.0, 16.0)
-						);
-
-						if let Some(render) = self.chunk_meshes.get_mut(&pos) {
-							render.tick(
-								api,
-								pos,
-								chunk,
-								&api.c_carrier.block_layer_renderer,
-								draw.debug,
-							)?;
-							render
-								.drawer
-								.draw(draw.frame, program, &uniforms, &draw_parameters)?;
-						} else {
-							draw_debug!(
-								draw.debug,
-								DebugCategory::Temporary,
-								rect(x as f32 * 16.0, y as f32 * 16.0, 16.0, 16.0),
-								0xff0000,
-								1.0,
-								0.5
-							);
-							self.chunk_meshes.insert(
-								pos,
-								ChunkMesh {
-									drawer: draw.frontend.create_drawer()?,
-									builder: MeshBuilder::new(),
-									dirty: true,
-								},
-							);
-						}
-					}
-				}
+		for pos in BlockArea::from_rect(draw.viewport.rect).chunks() {
+			let (x, y) = (pos.x as f32 * 16.0, pos.y as f32 * 16.0);
+			draw_debug!(draw.debug, DebugCategory::ChunkBorders, rect(x, y, 16.0, 16.0));
+
+			if let Some(render) = self.chunk_meshes.get_mut(&pos) {
+				render.tick(
+					api,
+					pos,
+					chunk,
+					&api.c_carrier.block_layer_renderer,
+					draw.debug,
+				)?;
+				render
+					.drawer
+					.draw(draw.frame, program, &uniforms, &draw_parameters)?;
+				render.light_drawer.draw(
+					draw.frame,
+					light_program,
+					&uniforms,
+					&draw_parameters,
+				)?;
+			} else {
+				draw_debug!(
+					draw.debug,
+					DebugCategory::Temporary,
+					rect(x, y, 16.0, 16.0),
+					0xff0000,
+					1.0,
+					0.5
+				);
+				self.chunk_meshes.insert(pos, ChunkMesh::new(draw.frontend)?);
 			}
 		}
 
@@ -130,13 +140,69 @@ impl WorldChunkRenderer {
 	}
 }
 
+/// What a chunk mesh has to mesh again on its next tick.
+#[derive(Default)]
+struct MeshDirty {
+	layers_all: bool,
+	layers: BTreeSet<Id<BlockLayer>>,
+	liquids: bool,
+	light: bool,
+}
+
+impl MeshDirty {
+	fn everything() -> MeshDirty {
+		MeshDirty {
+			layers_all: true,
+			layers: BTreeSet::new(),
+			liquids: true,
+			light: true,
+		}
+	}
+
+	fn mark(&mut self, changes: &ChunkChanges) {
+		if changes.all {
+			*self = MeshDirty::everything();
+			return;
+		}
+
+		self.layers.extend(changes.blocks.keys().copied());
+		self.liquids |= changes.liquids;
+		self.light |= changes.light;
+	}
+
+	fn layer(&self, layer_id: Id<BlockLayer>) -> bool {
+		self.layers_all || self.layers.contains(&layer_id)
+	}
+
+	fn is_clean(&self) -> bool {
+		!self.layers_all && self.layers.is_empty() && !self.liquids && !self.light
+	}
+}
+
 pub struct ChunkMesh {
 	drawer: MeshDrawer<PosTexVertex>,
 	builder: MeshBuilder<PosTexVertex>,
-	dirty: bool,
+	/// The mesh of every layer and of the liquids, kept so only the changed ones get meshed again.
+	layers: BTreeMap<Id<BlockLayer>, MeshBuilder<PosTexVertex>>,
+	liquids: MeshBuilder<PosTexVertex>,
+	light_drawer: MeshDrawer<PosColorVertex>,
+	light_builder: MeshBuilder<PosColorVertex>,
+	dirty: MeshDirty,
 }
 
 impl ChunkMesh {
+	pub fn new(frontend: &Frontend) -> Result<ChunkMesh> {
+		Ok(ChunkMesh {
+			drawer: frontend.create_drawer()?,
+			builder: MeshBuilder::new(),
+			layers: BTreeMap::new(),
+			liquids: MeshBuilder::new(),
+			light_drawer: frontend.create_drawer()?,
+			light_builder: MeshBuilder::new(),
+			dirty: MeshDirty::everything(),
+		})
+	}
+
 	pub fn tick(
 		&mut self,
 		api: &ClientApi,
@@ -145,45 +211,89 @@ impl ChunkMesh {
 		renderers: &IdTable<BlockLayer, Option<BlockLayerRenderer>>,
 		debug: &mut Debug,
 	) -> Result<()> {
-		if self.dirty {
-			draw_debug!(
-				debug,
-				DebugCategory::ChunkMeshing,
-				rect(
-					pos.x as f32 * CHUNK_SIZE as f32,
-					pos.y as f32 * CHUNK_SIZE as f32,
-					CHUNK_SIZE as f32,
-					CHUNK_SIZE as f32,
-				),
-				0xffffff,
-				2.0,
-				0.5
-			);
-			if let Some(chunk) = chunks.get(pos) {
-				let mut neighbors = DirMap::new([None; 4]);
-				for dir in Direction::values() {
-					if let Some(pos) = pos.checked_offset(dir) {
-						if let Some(chunk) = chunks.get(pos) {
-							neighbors[dir] = Some(chunk);
-						}
-					}
-				}
+		if self.dirty.is_clean() {
+			return Ok(());
+		}
 
-				for (id, layer) in chunk.layers.iter() {
-					if let Some(renderer) = renderers.get(id) {
-						renderer.mesh_chunk_layer(
-							pos,
-							layer,
-							neighbors.map(|_, option| option.map(|c| c.layers.get(id))),
-							&mut self.builder,
-							debug,
-						);
-					}
+		draw_debug!(
+			debug,
+			DebugCategory::ChunkMeshing,
+			rect(
+				pos.x as f32 * CHUNK_SIZE as f32,
+				pos.y as f32 * CHUNK_SIZE as f32,
+				CHUNK_SIZE as f32,
+				CHUNK_SIZE as f32,
+			),
+			0xffffff,
+			2.0,
+			0.5
+		);
+		let dirty = std::mem::take(&mut self.dirty);
+		let chunk = match chunks.get(pos) {
+			Some(chunk) => chunk,
+			None => {
+				self.layers.clear();
+				self.liquids.clear();
+				self.drawer.upload(&self.builder)?;
+				self.light_drawer.upload(&self.light_builder)?;
+				return Ok(());
+			}
+		};
+
+		let mut neighbors = DirMap::new([None; 4]);
+		for dir in Direction::values() {
+			if let Some(pos) = pos.checked_offset(dir) {
+				if let Some(chunk) = chunks.get(pos) {
+					neighbors[dir] = Some(chunk);
 				}
 			}
+		}
+
+		let mut meshed = false;
+		for (id, layer) in chunk.layers.iter() {
+			if !dirty.layer(id) {
+				continue;
+			}
+
+			if let Some(renderer) = renderers.get(id) {
+				let builder = self.layers.entry(id).or_insert_with(MeshBuilder::new);
+				builder.clear();
+				renderer.mesh_chunk_layer(
+					pos,
+					layer,
+					neighbors.map(|_, option| option.map(|c| c.layers.get(id))),
+					builder,
+					debug,
+				);
+				meshed = true;
+			}
+		}
+
+		if dirty.liquids {
+			self.liquids.clear();
+			liquid::mesh_chunk_liquids(
+				&api.c_carrier.liquid_renderer,
+				pos,
+				&chunk.liquids,
+				&mut self.liquids,
+			);
+			meshed = true;
+		}
+
+		// Liquids go on top of the blocks.
+		if meshed {
+			for builder in self.layers.values() {
+				self.builder.extend(builder);
+			}
+			self.builder.extend(&self.liquids);
 			self.drawer.upload(&self.builder)?;
 			self.builder.clear();
-			self.dirty = false;
+		}
+
+		if dirty.light {
+			light::mesh_chunk_light(pos, &chunk.light, &mut self.light_builder);
+			self.light_drawer.upload(&self.light_builder)?;
+			self.light_builder.clear();
 		}
 		Ok(())
 	}
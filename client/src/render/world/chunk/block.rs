@@ -1,13 +1,18 @@
 use std::collections::HashSet;
 
+use apollo::{Lua, Value};
 use euclid::{size2, vec2, Rect};
 use rustaria::{
-	api::{luna::table::LunaTable, prototype::Prototype},
+	api::{luna::table::LunaTable, prototype::Prototype, util::lua_table},
 	debug::{DebugCategory, DebugRendererImpl},
 	draw_debug,
 	ty::{block_pos::BlockPos, identifier::Identifier, WS},
 	util::blake3::Hasher,
-	world::chunk::ConnectionType,
+	world::chunk::{
+		block::BlockDesc,
+		state::{BlockState, PropertyValue},
+		ConnectionType,
+	},
 };
 use tracing::error_span;
 
@@ -23,18 +28,28 @@ use crate::{
 #[derive(Debug)]
 pub struct BlockRenderer {
 	pub image: Rect<f32, Atlas>,
+	/// Images for states which override the default one, indexed by state.
+	pub state_images: Vec<Rect<f32, Atlas>>,
 	pub connection_type: ConnectionType,
 }
 
 impl BlockRenderer {
+	pub fn image(&self, state: BlockState) -> Rect<f32, Atlas> {
+		self.state_images
+			.get(state.0 as usize)
+			.copied()
+			.unwrap_or(self.image)
+	}
+
 	pub fn mesh(
 		&self,
 		pos: BlockPos,
+		state: BlockState,
 		desc: &KindDesc,
 		builder: &mut MeshBuilder<PosTexVertex>,
 		debug: &mut Debug,
 	) {
-		let mut texture = self.image;
+		let mut texture = self.image(state);
 
 		let variation =
 			chunk::get_variation(pos) % ((texture.size.width / texture.size.height) as u32);
@@ -69,22 +84,86 @@ pub struct KindDesc {
 	pub(crate) rect: Rect<f32, WS>,
 }
 
+/// An image used when the block's properties match `when`.
+/// ```lua
+/// states = {
+///     { when = { stage = 3 }, image = "image/tile/sapling_grown.png" },
+///     { when = { open = true, facing = "left" }, image = "image/tile/door_open_left.png" },
+/// }
+/// ```
+/// The first matching entry wins, states that match nothing use the default `image`.
+#[derive(Debug)]
+pub struct StateImagePrototype {
+	pub when: Vec<(String, PropertyValue)>,
+	pub image: Identifier,
+}
+
+impl StateImagePrototype {
+	fn matches(&self, desc: &BlockDesc, state: BlockState) -> bool {
+		self.when
+			.iter()
+			.all(|(name, value)| desc.properties.get(state, name).as_ref() == Some(value))
+	}
+}
+
+impl apollo::FromLua for StateImagePrototype {
+	fn from_lua(lua_value: Value, _: &Lua) -> eyre::Result<Self> {
+		let table = lua_table(lua_value)?;
+		let mut when = Vec::new();
+		for pair in lua_table(table.get::<_, Value>("when")?)?.pairs::<String, PropertyValue>() {
+			when.push(pair?);
+		}
+		Ok(StateImagePrototype {
+			when,
+			image: table.get("image")?,
+		})
+	}
+}
+
 #[derive(Debug)]
 pub struct BlockRendererPrototype {
 	pub image: Identifier,
+	pub states: Vec<StateImagePrototype>,
 	pub connection_type: ConnectionType,
 }
 
 impl BlockRendererPrototype {
-	pub fn bake(&self, atlas: &Atlas) -> BlockRenderer {
-		BlockRenderer {
-			image: atlas.get(&self.image),
-			connection_type: self.connection_type,
+	pub fn bake(&self, atlas: &Atlas, desc: &BlockDesc) -> eyre::Result<BlockRenderer> {
+		for state_image in &self.states {
+			for (name, _) in &state_image.when {
+				if desc.properties.get(BlockState::DEFAULT, name).is_none() {
+					eyre::bail!("Block has no property {name}");
+				}
+			}
 		}
+
+		let image = atlas.get(&self.image);
+		let state_images = if self.states.is_empty() {
+			Vec::new()
+		} else {
+			(0..desc.properties.state_count())
+				.map(|state| {
+					let state = BlockState(state as u16);
+					self.states
+						.iter()
+						.find(|state_image| state_image.matches(desc, state))
+						.map_or(image, |state_image| atlas.get(&state_image.image))
+				})
+				.collect()
+		};
+
+		Ok(BlockRenderer {
+			image,
+			state_images,
+			connection_type: self.connection_type,
+		})
 	}
 
 	pub fn get_sprites(&self, sprites: &mut HashSet<Identifier>) {
 		sprites.insert(self.image.clone());
+		for state_image in &self.states {
+			sprites.insert(state_image.image.clone());
+		}
 	}
 }
 
@@ -97,6 +176,16 @@ impl Prototype for BlockRendererPrototype {
 		let _span = error_span!(target: "lua", "block_renderer").entered();
 		Ok(BlockRendererPrototype {
 			image: table.get("image")?,
+			states: {
+				let mut states = Vec::new();
+				if let Some(table) = table.get::<_, Option<apollo::Table>>("states")? {
+					for pair in table.pairs::<i64, StateImagePrototype>() {
+						states.push(pair?);
+					}
+				}
+				states.sort_by_key(|(i, _)| *i);
+				states.into_iter().map(|(_, state)| state).collect()
+			},
 			connection_type: table.get_ser("connection_type")?,
 		})
 	}
@@ -69,6 +69,7 @@ impl BlockLayerRenderer {
 			if let Some(renderer) = self.block_renderers.get(connection.id) {
 				renderer.mesh(
 					BlockPos::new(chunk, entry),
+					connection.state,
 					&self.kind_descs[connection_layer[entry] as u8 as usize],
 					builder,
 					debug,
@@ -114,15 +115,20 @@ impl BlockLayerRendererPrototype {
 				.id_to_ident
 				.iter()
 				.map(|(id, entry)| {
-					(
-						id,
-						self.blocks
-							.ident_to_id
-							.get(entry)
-							.map(|entry| self.blocks.get(*entry).bake(atlas)),
-					)
+					let renderer = self
+						.blocks
+						.ident_to_id
+						.get(entry)
+						.map(|renderer| {
+							self.blocks
+								.get(*renderer)
+								.bake(atlas, parent.blocks.get(id))
+								.wrap_err_with(|| format!("Failed to bake renderer for {entry}"))
+						})
+						.transpose()?;
+					Ok((id, renderer))
 				})
-				.collect(),
+				.collect::<eyre::Result<_>>()?,
 			kind_descs: kind_uvs,
 		})
 	}
@@ -0,0 +1,32 @@
+use euclid::{rect, Rect};
+use rustaria::{
+	ty::{block_layer_pos::BlockLayerPos, block_pos::BlockPos, chunk_pos::ChunkPos, WS},
+	world::{
+		chunk::CHUNK_SIZE,
+		light::{ChunkLight, MAX_LIGHT},
+	},
+};
+
+use crate::render::ty::{mesh_builder::MeshBuilder, vertex::PosColorVertex};
+
+/// Covers every block that is not fully lit with a black quad, darker the less light it gets.
+pub fn mesh_chunk_light(
+	chunk: ChunkPos,
+	light: &ChunkLight,
+	builder: &mut MeshBuilder<PosColorVertex>,
+) {
+	for y in 0..CHUNK_SIZE as u8 {
+		for x in 0..CHUNK_SIZE as u8 {
+			let entry = BlockLayerPos::new(x, y);
+			let level = light.get(entry);
+			if level >= MAX_LIGHT {
+				continue;
+			}
+
+			let pos = BlockPos::new(chunk, entry);
+			let darkness = 1.0 - level as f32 / MAX_LIGHT as f32;
+			let quad: Rect<f32, WS> = rect(pos.x() as f32, pos.y() as f32, 1.0, 1.0);
+			builder.push_quad((quad, [0.0, 0.0, 0.0, darkness]));
+		}
+	}
+}
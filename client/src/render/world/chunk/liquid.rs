@@ -0,0 +1,80 @@
+use std::collections::HashSet;
+
+use euclid::{rect, Rect};
+use rustaria::{
+	api::{id_table::IdTable, luna::table::LunaTable, prototype::Prototype},
+	ty::{block_pos::BlockPos, chunk_pos::ChunkPos, identifier::Identifier, WS},
+	world::{
+		chunk::ChunkLayer,
+		liquid::{LiquidCell, LiquidDesc, MAX_LIQUID},
+	},
+};
+use tracing::error_span;
+
+use crate::render::{
+	atlas::Atlas,
+	ty::{mesh_builder::MeshBuilder, vertex::PosTexVertex},
+};
+
+pub struct LiquidRenderer {
+	pub image: Rect<f32, Atlas>,
+}
+
+impl LiquidRenderer {
+	/// A quad filling the bottom of the block as far as the cell is full.
+	pub fn mesh(&self, pos: BlockPos, amount: u8, builder: &mut MeshBuilder<PosTexVertex>) {
+		let fill = amount as f32 / MAX_LIQUID as f32;
+		let mut texture = self.image;
+		texture.size.height *= fill;
+		let quad: Rect<f32, WS> = rect(pos.x() as f32, pos.y() as f32, 1.0, fill);
+		builder.push_quad((quad, texture));
+	}
+}
+
+pub fn mesh_chunk_liquids(
+	renderers: &IdTable<LiquidDesc, Option<LiquidRenderer>>,
+	chunk: ChunkPos,
+	liquids: &ChunkLayer<LiquidCell>,
+	builder: &mut MeshBuilder<PosTexVertex>,
+) {
+	if liquids.is_uniform() && liquids.palette()[0] == LiquidCell::EMPTY {
+		return;
+	}
+
+	liquids.entries(|entry, cell| {
+		if let Some(liquid) = cell.liquid {
+			if let Some(renderer) = renderers.get(liquid) {
+				renderer.mesh(BlockPos::new(chunk, entry), cell.amount, builder);
+			}
+		}
+	});
+}
+
+pub struct LiquidRendererPrototype {
+	pub image: Identifier,
+}
+
+impl LiquidRendererPrototype {
+	pub fn bake(&self, atlas: &Atlas) -> LiquidRenderer {
+		LiquidRenderer {
+			image: atlas.get(&self.image),
+		}
+	}
+
+	pub fn get_sprites(&self, sprites: &mut HashSet<Identifier>) {
+		sprites.insert(self.image.clone());
+	}
+}
+
+impl Prototype for LiquidRendererPrototype {
+	type Output = LiquidRenderer;
+
+	fn get_name() -> &'static str { "liquid_renderer" }
+
+	fn from_lua(table: LunaTable) -> eyre::Result<Self> {
+		let _span = error_span!(target: "lua", "liquid_renderer").entered();
+		Ok(LiquidRendererPrototype {
+			image: table.get("image")?,
+		})
+	}
+}
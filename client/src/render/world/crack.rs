@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+
+use euclid::{rect, Rect};
+use eyre::Result;
+use glium::{uniform, Blend, DrawParameters, Program};
+use rustaria::{
+	ty::{block_pos::BlockPos, id::Id, WS},
+	world::{chunk::layer::BlockLayer, mining::CRACK_STAGES},
+};
+
+use crate::{
+	render::ty::{
+		draw::Draw, mesh_buffer::MeshDrawer, mesh_builder::MeshBuilder, vertex::PosColorVertex,
+	},
+	Frontend,
+};
+
+/// Darkens blocks that are being mined, more the closer they are to breaking.
+pub struct WorldCrackRenderer {
+	drawer: MeshDrawer<PosColorVertex>,
+}
+
+impl WorldCrackRenderer {
+	pub fn new(frontend: &Frontend) -> Result<WorldCrackRenderer> {
+		Ok(WorldCrackRenderer {
+			drawer: frontend.create_drawer()?,
+		})
+	}
+
+	pub fn draw(
+		&mut self,
+		cracks: &HashMap<(BlockPos, Id<BlockLayer>), u8>,
+		program: &Program,
+		draw: &mut Draw,
+	) -> Result<()> {
+		let mut builder = MeshBuilder::new();
+		for ((pos, _), stage) in cracks {
+			let darkness = (*stage as f32 + 1.0) / CRACK_STAGES as f32 * 0.6;
+			let quad: Rect<f32, WS> = rect(pos.x() as f32, pos.y() as f32, 1.0, 1.0);
+			builder.push_quad((quad, [0.0, 0.0, 0.0, darkness]));
+		}
+		self.drawer.upload(&builder)?;
+
+		let uniforms = uniform! {
+			screen_ratio: draw.frontend.aspect_ratio,
+			player_pos: draw.viewport.pos.to_array(),
+			zoom: draw.viewport.zoom,
+		};
+		let draw_parameters = DrawParameters {
+			blend: Blend::alpha_blending(),
+			..DrawParameters::default()
+		};
+
+		self.drawer
+			.draw(draw.frame, program, &uniforms, &draw_parameters)?;
+		Ok(())
+	}
+}
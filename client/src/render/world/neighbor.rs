@@ -10,34 +10,34 @@ use rustaria::{
 
 #[derive(Clone)]
 pub(crate) struct NeighborMatrixBuilder {
-	matrix: ChunkLayer<DirMap<ConnectionType>>,
+	matrix: [[DirMap<ConnectionType>; CHUNK_SIZE]; CHUNK_SIZE],
 	layer: ChunkLayer<ConnectionType>,
 }
 
 impl NeighborMatrixBuilder {
 	pub fn new(layer: ChunkLayer<ConnectionType>) -> NeighborMatrixBuilder {
 		NeighborMatrixBuilder {
-			matrix: ChunkLayer {
-				data: [[DirMap::new([ConnectionType::Isolated; 4]); 16]; 16],
-			},
+			matrix: [[DirMap::new([ConnectionType::Isolated; 4]); CHUNK_SIZE]; CHUNK_SIZE],
 			layer,
 		}
 	}
 
+	fn connection(&self, x: usize, y: usize) -> ConnectionType {
+		self.layer[BlockLayerPos::new(x as u8, y as u8)]
+	}
+
 	pub fn compile_internal(&mut self) {
 		for y in 0..CHUNK_SIZE {
 			for x in 0..CHUNK_SIZE {
-				if self.layer.data[y][x] == ConnectionType::Connected {
-					if y != CHUNK_SIZE - 1 && ConnectionType::Connected == self.layer.data[y + 1][x]
-					{
-						self.matrix.data[y][x][Direction::Up] = ConnectionType::Connected;
-						self.matrix.data[y + 1][x][Direction::Down] = ConnectionType::Connected;
+				if self.connection(x, y) == ConnectionType::Connected {
+					if y != CHUNK_SIZE - 1 && ConnectionType::Connected == self.connection(x, y + 1) {
+						self.matrix[y][x][Direction::Up] = ConnectionType::Connected;
+						self.matrix[y + 1][x][Direction::Down] = ConnectionType::Connected;
 					}
 
-					if x != CHUNK_SIZE - 1 && ConnectionType::Connected == self.layer.data[y][x + 1]
-					{
-						self.matrix.data[y][x][Direction::Right] = ConnectionType::Connected;
-						self.matrix.data[y][x + 1][Direction::Left] = ConnectionType::Connected;
+					if x != CHUNK_SIZE - 1 && ConnectionType::Connected == self.connection(x + 1, y) {
+						self.matrix[y][x][Direction::Right] = ConnectionType::Connected;
+						self.matrix[y][x + 1][Direction::Left] = ConnectionType::Connected;
 					}
 				}
 			}
@@ -53,7 +53,7 @@ impl NeighborMatrixBuilder {
 		for y in y_offset..=x_length + y_offset {
 			for x in x_offset..=y_length + x_offset {
 				let neighbor_sub_pos = BlockLayerPos::new(x, y).euclid_offset(dir.offset());
-				let tile = self.layer.data[y as usize][x as usize];
+				let tile = self.connection(x as usize, y as usize);
 				let neighbor_tile = neighbor[neighbor_sub_pos];
 				let ty = if tile == ConnectionType::Connected
 					&& neighbor_tile == ConnectionType::Connected
@@ -63,18 +63,15 @@ impl NeighborMatrixBuilder {
 					ConnectionType::Isolated
 				};
 
-				self.matrix.data[y as usize][x as usize][dir] = ty;
+				self.matrix[y as usize][x as usize][dir] = ty;
 			}
 		}
 	}
 
 	pub fn export(self) -> ChunkLayer<SpriteConnectionKind> {
-		ChunkLayer {
-			data: self
-				.matrix
-				.data
-				.map(|value| value.map(SpriteConnectionKind::new)),
-		}
+		ChunkLayer::from_fn(|pos| {
+			SpriteConnectionKind::new(self.matrix[pos.y() as usize][pos.x() as usize])
+		})
 	}
 }
 
@@ -93,7 +90,7 @@ impl DerefMut for NeighborCell {
 	fn deref_mut(&mut self) -> &mut Self::Target { &mut self.edges }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 #[repr(u8)]
 pub(crate) enum SpriteConnectionKind {
 	Solid = 0,
@@ -17,6 +17,11 @@ if reload.client then
             }
         }
     }
+    reload.stargate.liquid_renderer:register {
+        ["water"] = { image = "image/liquid/water.png" },
+        ["lava"] = { image = "image/liquid/lava.png" },
+        ["honey"] = { image = "image/liquid/honey.png" }
+    }
     reload.stargate.block_layer_renderer:register {
         ["tile"] = {
             get_rect = connected_blocks["tile"].get_rect,
@@ -37,6 +42,14 @@ if reload.client then
                 ["corrupt_grass"] = {
                     image = "image/tile/corrupt_grass.png",
                     connection_type = "Connected",
+                },
+                ["copper_ore"] = {
+                    image = "image/tile/copper_ore.png",
+                    connection_type = "Connected",
+                },
+                ["iron_ore"] = {
+                    image = "image/tile/iron_ore.png",
+                    connection_type = "Connected",
                 }
             }
         },
@@ -63,15 +76,38 @@ reload.stargate.block_layer:register {
             },
             ["stone"] = {
                 collision = true,
+                hardness = 1.5,
             },
             ["grass"] = {
-                collision = true
+                collision = true,
+                random_tick = {
+                    chance = 0.5,
+                    -- Grass dies when something covers it.
+                    callback = function(world, x, y)
+                        local above = world:get_block("tile", x, y + 1)
+                        if above ~= nil and above ~= "rustaria:air" then
+                            world:set_block("tile", x, y, "dirt")
+                        end
+                    end
+                }
+            },
+            ["copper_ore"] = {
+                collision = true,
+                hardness = 2.0,
+            },
+            ["iron_ore"] = {
+                collision = true,
+                hardness = 3.0,
+                -- Hands still break it, just a lot slower and without drops.
+                tool = { kind = "pickaxe", level = 1 },
             },
             ["corrupt_grass"] = {
                 collision = true,
-                spread = {
+                random_tick = {
                     chance = 10.0,
-                    convert_table = {
+                    -- Creeps a lot faster once the corruption took over.
+                    biomes = { ["corruption"] = 3.0 },
+                    spread = {
                         ["dirt"] = "corrupt_grass"
                     }
                 }
@@ -94,6 +130,18 @@ reload.stargate.block_layer:register {
         }
     }
 }
+reload.stargate.liquid:register {
+    ["water"] = {},
+    ["honey"] = {
+        viscosity = 8
+    },
+    ["lava"] = {
+        viscosity = 4,
+        reactions = {
+            ["water"] = { layer = "tile", block = "stone" }
+        }
+    }
+}
 reload.stargate.entity:register {
     ["player"] = {
         position = { 24.0, 20.0 },
@@ -137,4 +185,40 @@ reload.stargate.entity:register {
             amount = 1.0
         }
     }
-}
\ No newline at end of file
+}
+reload.stargate.biome:register {
+    ["forest"] = {
+        temperature = 0.5,
+        humidity = 0.5,
+        surface = "grass",
+        filler = "dirt",
+        underground = "stone",
+        ambient = { sky_color = { 0.45, 0.65, 0.9 } }
+    },
+    ["corruption"] = {
+        generate = false,
+        surface = "corrupt_grass",
+        ambient = { sky_color = { 0.3, 0.2, 0.4 } },
+        convert = { layer = "tile", blocks = { ["corrupt_grass"] = 12 } }
+    }
+}
+reload.stargate.world_gen:register {
+    ["corruption"] = {
+        -- Turns a few stretches of the surface into corrupt grass, which then spreads on its own.
+        generate = function(view, rng)
+            if not rng:chance(0.05) then
+                return
+            end
+
+            for x = 0, view.size - 1 do
+                for y = view.size - 1, 0, -1 do
+                    if view:get_block("tile", x, y) == "rustaria:grass" then
+                        view:set_block("tile", x, y, "corrupt_grass")
+                        view:set_biome(x, "corruption")
+                        break
+                    end
+                end
+            end
+        end
+    }
+}
@@ -18,6 +18,9 @@ impl<I, V> IdTable<I, V> {
 
 	pub fn get_mut(&mut self, id: Id<I>) -> &mut V { &mut self.values[id.index()] }
 
+	/// If the id belongs to this table, ids from packets and saves might not.
+	pub fn contains(&self, id: Id<I>) -> bool { id.index() < self.values.len() }
+
 	pub fn iter(&self) -> IdTableIter<I, &V, Iter<V>> { IdTableIter::new(self.values.iter()) }
 
 	pub fn iter_mut(&mut self) -> IdTableIter<I, &mut V, IterMut<V>> {
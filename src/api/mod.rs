@@ -14,8 +14,11 @@ use crate::{
 	ty::{identifier::Identifier, MultiDeref},
 	util::blake3::{Blake3Hash, Hasher},
 	world::{
+		biome::{BiomeDesc, BiomePrototype},
 		chunk::layer::{BlockLayer, BlockLayerPrototype},
 		entity::prototype::{EntityDesc, EntityPrototype},
+		gen::pass::{WorldGenDesc, WorldGenPrototype},
+		liquid::{self, LiquidDesc, LiquidPrototype},
 	},
 };
 
@@ -27,6 +30,8 @@ pub mod luna;
 pub mod plugin;
 pub mod prototype;
 pub mod registry;
+#[cfg(test)]
+pub mod test;
 pub mod util;
 
 pub struct Api {
@@ -78,6 +83,9 @@ impl Api {
 			carrier: Carrier {
 				block_layer: Registry::default(),
 				entity: Registry::default(),
+				biome: Registry::default(),
+				world_gen: Registry::default(),
+				liquid: Registry::default(),
 			},
 			resources,
 			thread_pool: Arc::new(ThreadPoolBuilder::new().build()?),
@@ -91,6 +99,9 @@ impl Api {
 		// Prepare for reload
 		reload.stargate.register_builder::<BlockLayerPrototype>();
 		reload.stargate.register_builder::<EntityPrototype>();
+		reload.stargate.register_builder::<BiomePrototype>();
+		reload.stargate.register_builder::<WorldGenPrototype>();
+		reload.stargate.register_builder::<LiquidPrototype>();
 
 		{
 			let reload_scope = LuaScope::from(&mut *reload);
@@ -112,6 +123,26 @@ impl Api {
 			}
 		}
 
+		let entity: Registry<EntityDesc> = reload
+			.stargate
+			.build_registry::<EntityPrototype>(&self.luna.lua)?
+			.into_entries()
+			.map(|(id, ident, prototype)| (id.build(), ident, prototype.bake(id)))
+			.collect();
+
+		let mut biome = Vec::new();
+		for (id, ident, prototype) in reload
+			.stargate
+			.build_registry::<BiomePrototype>(&self.luna.lua)?
+			.into_entries()
+		{
+			let desc = prototype
+				.bake(&entity)
+				.wrap_err_with(|| format!("Failed to bake biome {ident}"))?;
+			biome.push((id.build(), ident, desc));
+		}
+		let biome: Registry<BiomeDesc> = biome.into_iter().collect();
+
 		let registry = reload
 			.stargate
 			.build_registry::<BlockLayerPrototype>(&self.luna.lua)?;
@@ -123,24 +154,36 @@ impl Api {
 
 		let mut out = Vec::new();
 		for ((id, prototype), (_, identifier)) in block_layer {
-			out.push((id.build(), identifier, prototype.bake()?));
+			out.push((id.build(), identifier, prototype.bake(&entity, &biome)?));
 		}
 		let block_layer = out.into_iter().collect();
+		let liquid = liquid::bake_liquids(
+			reload
+				.stargate
+				.build_registry::<LiquidPrototype>(&self.luna.lua)?,
+			&block_layer,
+		)?;
 
 		self.carrier = Carrier {
 			block_layer,
-			entity: reload
+			entity,
+			biome,
+			world_gen: reload
 				.stargate
-				.build_registry::<EntityPrototype>(&self.luna.lua)?
+				.build_registry::<WorldGenPrototype>(&self.luna.lua)?
 				.into_entries()
-				.map(|(id, ident, prototype)| (id.build(), ident, prototype.bake(id)))
+				.map(|(id, ident, prototype)| (id.build(), ident, prototype.bake()))
 				.collect(),
+			liquid,
 		};
 
 		// Hash
 		let mut hasher = Hasher::new();
 		self.carrier.block_layer.append_hasher(&mut hasher);
 		self.carrier.entity.append_hasher(&mut hasher);
+		self.carrier.biome.append_hasher(&mut hasher);
+		self.carrier.world_gen.append_hasher(&mut hasher);
+		self.carrier.liquid.append_hasher(&mut hasher);
 		self.hash = Some(hasher.finalize());
 		Ok(())
 	}
@@ -175,11 +218,17 @@ impl Plugins {
 pub struct Carrier {
 	pub block_layer: Registry<BlockLayer>,
 	pub entity: Registry<EntityDesc>,
+	pub biome: Registry<BiomeDesc>,
+	pub world_gen: Registry<WorldGenDesc>,
+	pub liquid: Registry<LiquidDesc>,
 }
 
 multi_deref_fields!(Carrier {
 	block_layer: Registry<BlockLayer>,
-	entity: Registry<EntityDesc>
+	entity: Registry<EntityDesc>,
+	biome: Registry<BiomeDesc>,
+	world_gen: Registry<WorldGenDesc>,
+	liquid: Registry<LiquidDesc>
 });
 
 #[lua_impl]
@@ -193,4 +242,19 @@ impl Carrier {
 	pub fn get_entity(&self) -> &Registry<EntityDesc> {
 		&self.entity
 	}
+
+	#[lua_field(get biome)]
+	pub fn get_biome(&self) -> &Registry<BiomeDesc> {
+		&self.biome
+	}
+
+	#[lua_field(get world_gen)]
+	pub fn get_world_gen(&self) -> &Registry<WorldGenDesc> {
+		&self.world_gen
+	}
+
+	#[lua_field(get liquid)]
+	pub fn get_liquid(&self) -> &Registry<LiquidDesc> {
+		&self.liquid
+	}
 }
\ No newline at end of file
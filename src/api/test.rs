@@ -0,0 +1,88 @@
+//! An [`Api`] put together in Rust, for tests which need registries without loading plugins.
+use std::{collections::HashMap, sync::Arc};
+
+use euclid::Vector2D;
+use rayon::ThreadPoolBuilder;
+
+use crate::{
+	api::{luna::Luna, registry::Registry, Api, Carrier, Plugins},
+	ty::{id::Id, identifier::Identifier},
+	world::{
+		chunk::{block::BlockDesc, layer::BlockLayer, state::BlockProperties},
+		entity::{component::PositionComponent, prototype::EntityPrototype},
+	},
+};
+
+/// A block which neither ticks, reacts, drops nor falls.
+pub fn block_desc(collision: bool) -> BlockDesc {
+	BlockDesc {
+		collision,
+		random_tick: None,
+		neighbor_update: None,
+		block_entity: None,
+		properties: BlockProperties::default(),
+		light_emission: 0,
+		light_opacity: 0,
+		hardness: 0.0,
+		tool: None,
+		drops: Vec::new(),
+		falls: None,
+	}
+}
+
+/// An entity which only has a position.
+pub fn entity() -> EntityPrototype {
+	EntityPrototype {
+		position: PositionComponent {
+			pos: Vector2D::zero(),
+		},
+		velocity: None,
+		collision: None,
+		humanoid: None,
+		gravity: None,
+	}
+}
+
+/// An api with a single colliding layer `tile`, whose blocks and entities get their ids in the
+/// order they are listed. The first block is the default of the layer.
+pub fn api(
+	blocks: Vec<(&'static str, BlockDesc)>,
+	entities: Vec<(&'static str, EntityPrototype)>,
+) -> Api {
+	let layer = BlockLayer {
+		blocks: blocks
+			.into_iter()
+			.enumerate()
+			.map(|(id, (name, desc))| (unsafe { Id::new(id) }, Identifier::new(name), desc))
+			.collect(),
+		default: unsafe { Id::new(0) },
+		collision: true,
+	};
+	let entity = entities
+		.into_iter()
+		.enumerate()
+		.map(|(id, (name, prototype))| {
+			let id: Id<EntityPrototype> = unsafe { Id::new(id) };
+			(id.build(), Identifier::new(name), prototype.bake(id))
+		})
+		.collect();
+
+	let resources = Plugins {
+		plugins: Arc::new(HashMap::new()),
+	};
+	Api {
+		luna: Luna::new(&resources).unwrap(),
+		carrier: Carrier {
+			block_layer: vec![(unsafe { Id::new(0) }, Identifier::new("tile"), layer)]
+				.into_iter()
+				.collect(),
+			entity,
+			biome: Registry::default(),
+			world_gen: Registry::default(),
+			liquid: Registry::default(),
+		},
+		resources,
+		thread_pool: Arc::new(ThreadPoolBuilder::new().num_threads(1).build().unwrap()),
+		hash: None,
+	}
+}
@@ -1,26 +1,35 @@
 #![allow(clippy::new_without_default)]
 
+use std::collections::BTreeMap;
+
 use eyre::{Context, Result};
 use semver::Version;
-use tracing::info;
-use ty::chunk_pos::ChunkPos;
+use tracing::{info, warn};
+use ty::{block_pos::BlockPos, chunk_pos::ChunkPos};
 use world::{
 	chunk::{storage::ChunkStorage, Chunk},
 	entity::EntityWorld,
+	save::WorldSave,
 };
 
 use crate::{
 	api::Api,
 	debug::DummyRenderer,
-	network::{packet::ServerBoundPacket, ServerNetwork},
-	player::PlayerSystem,
-	world::World,
+	network::{packet::ServerBoundPacket, ServerNetwork, Token},
+	player::{PlayerSystem, ServerBoundPlayerPacket},
+	tracker::ChunkTracker,
+	world::{
+		entity::system::network::{EntityComponentPacket, EntityPacket},
+		time::TIME_SYNC_INTERVAL,
+		ClientBoundWorldPacket, World,
+	},
 };
 
 pub mod api;
 pub mod debug;
 pub mod network;
 pub mod player;
+pub mod tracker;
 pub mod ty;
 pub mod util;
 pub mod world;
@@ -31,7 +40,10 @@ pub const KERNEL_VERSION: Version = Version::new(0, 0, 1);
 pub struct Server {
 	network: ServerNetwork,
 	player: PlayerSystem,
+	tracker: ChunkTracker,
 	world: World,
+	/// Lets players place and remove blocks directly instead of mining them.
+	creative: bool,
 }
 
 impl Server {
@@ -40,26 +52,220 @@ impl Server {
 		Ok(Server {
 			network,
 			player: PlayerSystem::new(api)?,
+			tracker: ChunkTracker::new(),
 			world,
+			creative: false,
 		})
 	}
 
+	pub fn set_creative(&mut self, creative: bool) { self.creative = creative; }
+
+	/// Forgets everything the server kept for a player which left.
+	pub fn disconnect(&mut self, token: Token) {
+		self.player.remove(token, &mut self.world);
+		self.tracker.remove(token);
+		self.world.forget_edits(token);
+	}
+
 	pub fn tick(&mut self, api: &Api) -> Result<()> {
 		for (token, packet) in self.network.poll() {
 			match packet {
+				ServerBoundPacket::Player(ServerBoundPlayerPacket::Leave()) => {
+					self.disconnect(token);
+				}
 				ServerBoundPacket::Player(packet) => {
 					self.player.packet(api, token, packet, &mut self.world);
 				}
+				ServerBoundPacket::World(packet)
+					if packet.requires_creative() && !self.creative =>
+				{
+					warn!("{token:?} tried to change blocks directly outside of creative.");
+				}
 				ServerBoundPacket::World(packet) => {
 					self.world.packet(api, token, packet, &mut self.network)?;
 				}
+				ServerBoundPacket::Tracker(packet) => {
+					self.tracker.packet(token, packet);
+				}
 			}
 		}
 
+		self.world.time_mut().tick();
 		self.world.tick(api, &mut DummyRenderer);
 		self.player
 			.tick(&mut self.network, &self.world)
 			.wrap_err("Ticking player system.")?;
+		self.tracker
+			.tick(api, &mut self.network, &mut self.world, &self.player)
+			.wrap_err("Ticking chunk tracker.")?;
+		self.sync_blocks().wrap_err("Syncing blocks.")?;
+		self.sync_cracks().wrap_err("Syncing cracks.")?;
+		self.sync_spawns().wrap_err("Syncing spawned entities.")?;
+		self.sync_despawns().wrap_err("Syncing removed entities.")?;
+		self.sync_block_entities().wrap_err("Syncing block entities.")?;
+		self.sync_liquids().wrap_err("Syncing liquids.")?;
+		self.sync_light().wrap_err("Syncing light.")?;
+		self.sync_biomes().wrap_err("Syncing biomes.")?;
+		self.sync_time().wrap_err("Syncing time.")?;
+		// Nothing on the server meshes chunks, the changes would only pile up.
+		self.world.chunks.reset_dirty();
+		Ok(())
+	}
+
+	/// Chunks with a single change get a [`ClientBoundWorldPacket::SetBlock`],
+	/// anything more gets batched into one [`ClientBoundWorldPacket::SetBlocks`].
+	fn sync_blocks(&mut self) -> Result<()> {
+		let mut chunks: BTreeMap<ChunkPos, Vec<_>> = BTreeMap::new();
+		for (pos, layer_id) in self.world.drain_block_changes() {
+			chunks.entry(pos.chunk).or_default().push((pos.entry, layer_id));
+		}
+
+		for (pos, changes) in chunks {
+			let chunk = match self.world.chunks.get(pos) {
+				Some(chunk) => chunk,
+				None => continue,
+			};
+			let blocks: Vec<_> = changes
+				.into_iter()
+				.map(|(entry, layer_id)| {
+					let block = chunk.layers.get(layer_id)[entry];
+					(entry, layer_id, block.id, block.state)
+				})
+				.collect();
+
+			for token in self.tracker.watching(pos) {
+				let packet = match blocks.as_slice() {
+					[(entry, layer_id, block_id, state)] => ClientBoundWorldPacket::SetBlock(
+						BlockPos::new(pos, *entry),
+						*layer_id,
+						*block_id,
+						*state,
+					),
+					_ => ClientBoundWorldPacket::SetBlocks(pos, blocks.clone()),
+				};
+				self.network.send(token, packet)?;
+			}
+		}
+		Ok(())
+	}
+
+	fn sync_cracks(&mut self) -> Result<()> {
+		let cracks: Vec<_> = self.world.drain_cracks().collect();
+		for (pos, layer_id, stage) in cracks {
+			for token in self.tracker.watching(pos.chunk) {
+				self.network
+					.send(token, ClientBoundWorldPacket::SetCrack(pos, layer_id, stage))?;
+			}
+		}
+		Ok(())
+	}
+
+	fn sync_spawns(&mut self) -> Result<()> {
+		let spawned: Vec<_> = self.world.drain_spawned().collect();
+		for (entity, id, pos) in spawned {
+			let chunk = match BlockPos::from_block(pos.x.floor() as i64, pos.y.floor() as i64) {
+				Some(block) => block.chunk,
+				None => continue,
+			};
+
+			for token in self.tracker.watching(chunk) {
+				self.network
+					.send(token, ClientBoundWorldPacket::SpawnEntity(entity, id))?;
+				self.network.send(
+					token,
+					ClientBoundWorldPacket::UpdateEntity(EntityPacket {
+						entity,
+						component: EntityComponentPacket::Pos { set_pos: pos },
+					}),
+				)?;
+			}
+		}
+		Ok(())
+	}
+
+	fn sync_despawns(&mut self) -> Result<()> {
+		let despawned: Vec<_> = self.world.drain_despawned().collect();
+		for (entity, chunk) in despawned {
+			for token in self.tracker.watching(chunk) {
+				self.network
+					.send(token, ClientBoundWorldPacket::RemoveEntity(entity))?;
+			}
+		}
 		Ok(())
 	}
+
+	fn sync_block_entities(&mut self) -> Result<()> {
+		let changes: Vec<_> = self.world.drain_block_entity_changes().collect();
+		for (pos, layer_id) in changes {
+			let block_entity = self.world.block_entity(pos, layer_id);
+			for token in self.tracker.watching(pos.chunk) {
+				self.network.send(
+					token,
+					ClientBoundWorldPacket::SetBlockEntity(pos, layer_id, block_entity.cloned()),
+				)?;
+			}
+		}
+		Ok(())
+	}
+
+	fn sync_liquids(&mut self) -> Result<()> {
+		let changes: Vec<_> = self.world.drain_liquid_changes().collect();
+		for pos in changes {
+			if let Some(chunk) = self.world.chunks.get(pos) {
+				for token in self.tracker.watching(pos) {
+					self.network.send(
+						token,
+						ClientBoundWorldPacket::SetLiquids(pos, chunk.liquids.clone()),
+					)?;
+				}
+			}
+		}
+		Ok(())
+	}
+
+	fn sync_biomes(&mut self) -> Result<()> {
+		let changes: Vec<_> = self.world.drain_biome_changes().collect();
+		for pos in changes {
+			if let Some(chunk) = self.world.chunks.get(pos) {
+				for token in self.tracker.watching(pos) {
+					self.network
+						.send(token, ClientBoundWorldPacket::SetBiomes(pos, chunk.biomes))?;
+				}
+			}
+		}
+		Ok(())
+	}
+
+	/// Clients advance the time on their own, so it only gets sent every
+	/// [`TIME_SYNC_INTERVAL`] ticks or when somebody set it.
+	fn sync_time(&mut self) -> Result<()> {
+		let changed = self.world.time_mut().take_changed();
+		if !changed && self.world.time().ticks() % TIME_SYNC_INTERVAL != 0 {
+			return Ok(());
+		}
+
+		for token in self.player.tokens() {
+			self.network
+				.send(token, ClientBoundWorldPacket::SetTime(self.world.time()))?;
+		}
+		Ok(())
+	}
+
+	fn sync_light(&mut self) -> Result<()> {
+		for pos in self.world.drain_light_changes() {
+			if let Some(chunk) = self.world.chunks.get(pos) {
+				for token in self.tracker.watching(pos) {
+					self.network.send(
+						token,
+						ClientBoundWorldPacket::SetLight(pos, chunk.light.clone()),
+					)?;
+				}
+			}
+		}
+		Ok(())
+	}
+
+	pub fn save(&self, api: &Api, save: &WorldSave) -> Result<()> {
+		save.save(api, &self.world).wrap_err("Saving world.")
+	}
 }
@@ -1,5 +1,6 @@
 use crate::{
 	player::{ClientBoundPlayerPacket, ServerBoundPlayerPacket},
+	tracker::{ClientBoundTrackerPacket, ServerBoundTrackerPacket},
 	world::{ClientBoundWorldPacket, ServerBoundWorldPacket},
 };
 
@@ -25,10 +26,12 @@ macro_rules! packet {
 pub enum ServerBoundPacket {
 	World(ServerBoundWorldPacket),
 	Player(ServerBoundPlayerPacket),
+	Tracker(ServerBoundTrackerPacket),
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
 pub enum ClientBoundPacket {
 	World(ClientBoundWorldPacket),
 	Player(ClientBoundPlayerPacket),
+	Tracker(ClientBoundTrackerPacket),
 }
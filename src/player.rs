@@ -10,9 +10,12 @@ use crate::{
 	network::Token,
 	packet,
 	ty::{id::Id, identifier::Identifier, WS},
-	world::entity::{
-		component::{HumanoidComponent, PositionComponent},
-		prototype::EntityDesc,
+	world::{
+		entity::{
+			component::{HumanoidComponent, PositionComponent},
+			prototype::EntityDesc,
+		},
+		ClientBoundWorldPacket,
 	},
 	EntityWorld, ServerNetwork, World,
 };
@@ -23,6 +26,10 @@ packet!(Player(ServerBoundPlayerPacket, ClientBoundPlayerPacket));
 pub enum ServerBoundPlayerPacket {
 	SetMove(u32, PlayerCommand),
 	Join(),
+	/// Sent before the client goes away, see [`Server::disconnect`].
+	///
+	/// [`Server::disconnect`]: crate::Server::disconnect
+	Leave(),
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -80,10 +87,25 @@ impl PlayerSystem {
 		None
 	}
 
+	pub fn tokens(&self) -> impl Iterator<Item = Token> + '_ { self.players.keys().copied() }
+
+	/// Every player which currently has an entity, with its position.
+	pub fn positions<'a>(
+		&'a self,
+		entity_world: &'a EntityWorld,
+	) -> impl Iterator<Item = (Token, Vector2D<f32, WS>)> + 'a {
+		self.players.iter().filter_map(|(token, entity)| {
+			let entity = entity_world.storage.get((*entity)?)?;
+			let position = entity.get::<PositionComponent>()?;
+			Some((*token, position.pos))
+		})
+	}
+
 	pub fn tick(&mut self, networking: &mut ServerNetwork, world: &World) -> Result<()> {
 		for (token, entity) in self.joined.drain(..) {
 			debug!("Sent joined packet");
 			networking.send(token, ClientBoundPlayerPacket::Joined(entity))?;
+			networking.send(token, ClientBoundWorldPacket::SetTime(world.time()))?;
 		}
 
 		let responses: Vec<_> = self.response_requests.drain(..).collect();
@@ -124,9 +146,25 @@ impl PlayerSystem {
 			ServerBoundPlayerPacket::Join() => {
 				info!("Player {:?} joined", token);
 				let entity = world.entities.storage.push(api, self.player_entity);
+				if let Some(pos) = world.spawn_point() {
+					world.entities.storage.insert_comp(entity, PositionComponent { pos });
+				}
 				self.players.insert(token, Some(entity));
 				self.joined.push((token, entity));
 			}
+			// The server handles leaving, as more than the player system has to forget it.
+			ServerBoundPlayerPacket::Leave() => {}
+		}
+	}
+
+	/// Forgets the player and removes its entity.
+	pub fn remove(&mut self, token: Token, world: &mut World) {
+		info!("Player {:?} left", token);
+		if let Some(Some(entity)) = self.players.remove(&token) {
+			world.despawn_entity(entity);
+		}
+		self.joined.retain(|(joined, _)| *joined != token);
+		self.response_requests.retain(|(_, requested)| *requested != token);
 		}
 	}
 }
@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+
+use eyre::Result;
+use fxhash::FxHashSet;
+use tracing::debug;
+
+use crate::{
+	network::Token,
+	packet,
+	player::PlayerSystem,
+	ty::Offset,
+	world::chunk::storage::ChunkBounds,
+	Api, Chunk, ChunkPos, ServerNetwork, World,
+};
+
+packet!(Tracker(ServerBoundTrackerPacket, ClientBoundTrackerPacket));
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum ServerBoundTrackerPacket {
+	/// The radius in chunks around the player the client wants to have loaded.
+	SetViewDistance(u32),
+	/// The client dropped all of its chunks, everything in range gets sent again.
+	Reset,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum ClientBoundTrackerPacket {
+	LoadChunk(ChunkPos, Chunk),
+	UnloadChunk(ChunkPos),
+}
+
+pub const DEFAULT_VIEW_DISTANCE: u32 = 4;
+/// The furthest a client may ask for, so a bad packet can not make us generate half the world.
+pub const MAX_VIEW_DISTANCE: u32 = 32;
+/// How many chunks get sent to a single player each tick.
+const CHUNKS_PER_TICK: usize = 8;
+
+/// Keeps track of which chunks every player has loaded.
+/// New chunks get streamed as the player moves and chunks that fall out of range get unloaded.
+pub(crate) struct ChunkTracker {
+	players: HashMap<Token, TrackedPlayer>,
+}
+
+struct TrackedPlayer {
+	view_distance: u32,
+	loaded: FxHashSet<ChunkPos>,
+}
+
+impl ChunkTracker {
+	pub fn new() -> ChunkTracker {
+		ChunkTracker {
+			players: Default::default(),
+		}
+	}
+
+	pub fn packet(&mut self, token: Token, packet: ServerBoundTrackerPacket) {
+		match packet {
+			ServerBoundTrackerPacket::SetViewDistance(distance) => {
+				debug!("Player {token:?} set view distance to {distance}");
+				self.player(token).view_distance = distance.min(MAX_VIEW_DISTANCE);
+			}
+			ServerBoundTrackerPacket::Reset => {
+				self.player(token).loaded.clear();
+			}
+		}
+	}
+
+	pub fn tick(
+		&mut self,
+		api: &Api,
+		network: &mut ServerNetwork,
+		world: &mut World,
+		players: &PlayerSystem,
+	) -> Result<()> {
+		let positions: Vec<_> = players.positions(&world.entities).collect();
+		for (token, pos) in positions {
+			let center = match ChunkPos::try_from(pos) {
+				Ok(center) => center,
+				Err(_) => continue,
+			};
+
+			let player = self.player(token);
+			for pos in player.unload(center) {
+				network.send(token, ClientBoundTrackerPacket::UnloadChunk(pos))?;
+			}
+
+			for pos in player.missing(center, world.chunks.bounds()) {
+				if let Some(chunk) = world.generate_chunk(api, pos) {
+					network.send(token, ClientBoundTrackerPacket::LoadChunk(pos, chunk.clone()))?;
+					player.loaded.insert(pos);
+				}
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Forgets a player which left, so it stops getting chunks.
+	pub fn remove(&mut self, token: Token) { self.players.remove(&token); }
+
+	/// Every player which has this chunk loaded.
+	pub fn watching(&self, pos: ChunkPos) -> impl Iterator<Item = Token> + '_ {
+		self.players
+			.iter()
+			.filter(move |(_, player)| player.loaded.contains(&pos))
+			.map(|(token, _)| *token)
+	}
+
+	fn player(&mut self, token: Token) -> &mut TrackedPlayer {
+		self.players.entry(token).or_insert_with(|| TrackedPlayer {
+			view_distance: DEFAULT_VIEW_DISTANCE,
+			loaded: Default::default(),
+		})
+	}
+}
+
+impl TrackedPlayer {
+	/// Drops the chunks which are out of range.
+	fn unload(&mut self, center: ChunkPos) -> Vec<ChunkPos> {
+		// Keep one extra chunk before unloading so walking along a chunk border does not
+		// constantly load and unload the same row.
+		let distance = self.view_distance as i64 + 1;
+		let unload: Vec<ChunkPos> = self
+			.loaded
+			.iter()
+			.filter(|pos| chebyshev(center, **pos) > distance)
+			.copied()
+			.collect();
+		for pos in &unload {
+			self.loaded.remove(pos);
+		}
+		unload
+	}
+
+	/// The chunks in range which are not loaded yet, closest first and at most
+	/// [`CHUNKS_PER_TICK`] of them.
+	fn missing(&self, center: ChunkPos, bounds: Option<ChunkBounds>) -> Vec<ChunkPos> {
+		let distance = self.view_distance as i32;
+		let mut missing = Vec::new();
+		for y in -distance..=distance {
+			for x in -distance..=distance {
+				if let Some(pos) = center.checked_offset((x, y)) {
+					let in_bounds = bounds.map_or(true, |bounds| bounds.contains(pos));
+					if in_bounds && !self.loaded.contains(&pos) {
+						missing.push(pos);
+					}
+				}
+			}
+		}
+
+		missing.sort_by_key(|pos| {
+			let dx = (pos.x - center.x) as i64;
+			let dy = (pos.y - center.y) as i64;
+			dx * dx + dy * dy
+		});
+		missing.truncate(CHUNKS_PER_TICK);
+		missing
+	}
+}
+
+fn chebyshev(a: ChunkPos, b: ChunkPos) -> i64 {
+	(a.x as i64 - b.x as i64)
+		.abs()
+		.max((a.y as i64 - b.y as i64).abs())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn pos(x: i32, y: i32) -> ChunkPos { ChunkPos { x, y } }
+
+	#[test]
+	fn view_distance_is_clamped() {
+		let mut tracker = ChunkTracker::new();
+		tracker.packet(Token(), ServerBoundTrackerPacket::SetViewDistance(u32::MAX));
+		assert_eq!(tracker.player(Token()).view_distance, MAX_VIEW_DISTANCE);
+	}
+
+	#[test]
+	fn loads_closest_first_within_budget() {
+		let mut tracker = ChunkTracker::new();
+		let player = tracker.player(Token());
+		let missing = player.missing(pos(0, 0), None);
+		assert_eq!(missing.len(), CHUNKS_PER_TICK);
+		assert_eq!(missing[0], pos(0, 0));
+		assert!(missing.iter().all(|loaded| chebyshev(*loaded, pos(0, 0)) <= 1));
+
+		// Nothing outside of the world.
+		let bounds = ChunkBounds::new(1, 1);
+		assert_eq!(player.missing(pos(0, 0), Some(bounds)), vec![pos(0, 0)]);
+	}
+
+	#[test]
+	fn unloads_beyond_one_extra_chunk() {
+		let mut tracker = ChunkTracker::new();
+		let distance = DEFAULT_VIEW_DISTANCE as i32;
+		let player = tracker.player(Token());
+		player.loaded.insert(pos(distance + 1, 0));
+		player.loaded.insert(pos(distance + 2, 0));
+		assert_eq!(player.unload(pos(0, 0)), vec![pos(distance + 2, 0)]);
+		assert!(player.loaded.contains(&pos(distance + 1, 0)));
+
+		assert_eq!(tracker.watching(pos(distance + 1, 0)).count(), 1);
+		tracker.remove(Token());
+		assert_eq!(tracker.watching(pos(distance + 1, 0)).count(), 0);
+	}
+}
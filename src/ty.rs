@@ -10,6 +10,7 @@ pub struct WS;
 /// Screen Space
 pub struct SS;
 
+#[derive(Debug)]
 pub enum Error {
 	OutOfBounds,
 }
@@ -40,19 +41,6 @@ pub trait Offset<D>: Sized {
 	fn checked_offset(self, displacement: D) -> Option<Self>;
 }
 
-#[inline]
-fn checked_add_signed_u32(a: u32, b: i32) -> Option<u32> {
-	// XXX(leocth):
-	// replace with std's `checked_add_signed` when `mixed_integer_ops` reaches stable.
-	// see https://github.com/rust-lang/rust/issues/87840
-	let (res, overflowed) = a.overflowing_add(b as u32);
-	if overflowed ^ (b < 0) {
-		None
-	} else {
-		Some(res)
-	}
-}
-
 #[inline]
 fn checked_add_signed_u8(a: u8, b: i8) -> Option<u8> {
 	// XXX(leocth):
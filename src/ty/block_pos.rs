@@ -28,6 +28,17 @@ pub struct BlockPos {
 impl BlockPos {
 	pub fn new(chunk: ChunkPos, entry: BlockLayerPos) -> BlockPos { BlockPos { chunk, entry } }
 
+	/// The position of this block coordinate, [`None`] if the chunk does not fit in an [`i32`].
+	pub fn from_block(x: i64, y: i64) -> Option<BlockPos> {
+		Some(BlockPos {
+			chunk: ChunkPos::from_block(x, y)?,
+			entry: BlockLayerPos::new(
+				x.rem_euclid(CHUNK_SIZE as i64) as u8,
+				y.rem_euclid(CHUNK_SIZE as i64) as u8,
+			),
+		})
+	}
+
 	pub fn x(&self) -> i64 { (self.chunk.x as i64 * CHUNK_SIZE as i64) + self.entry.x() as i64 }
 
 	pub fn y(&self) -> i64 { (self.chunk.y as i64 * CHUNK_SIZE as i64) + self.entry.y() as i64 }
@@ -62,14 +73,11 @@ impl<S> TryFrom<Vector2D<f32, S>> for BlockPos {
 	type Error = Error;
 
 	fn try_from(value: Vector2D<f32, S>) -> Result<Self, Self::Error> {
-		Ok(BlockPos {
-			chunk: ChunkPos::try_from(value)?,
-			entry: BlockLayerPos::try_new(
-				(value.x as i64 % CHUNK_SIZE as i64) as u8,
-				(value.y as i64 % CHUNK_SIZE as i64) as u8,
-			)
-			.ok_or(OutOfBounds)?,
-		})
+		if !value.x.is_finite() || !value.y.is_finite() {
+			return Err(OutOfBounds);
+		}
+
+		BlockPos::from_block(value.x.floor() as i64, value.y.floor() as i64).ok_or(OutOfBounds)
 	}
 }
 
@@ -87,3 +95,22 @@ impl Display for BlockPos {
 		))
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use euclid::{vec2, UnknownUnit};
+
+	use super::*;
+
+	#[test]
+	fn negative_positions() {
+		let pos = BlockPos::try_from(vec2::<f32, UnknownUnit>(-0.5, -17.0)).unwrap();
+		assert_eq!(pos.chunk, ChunkPos { x: -1, y: -2 });
+		assert_eq!((pos.entry.x(), pos.entry.y()), (15, 15));
+		assert_eq!((pos.x(), pos.y()), (-1, -17));
+
+		let pos = BlockPos::from_block(16, 0).unwrap();
+		assert_eq!(pos.chunk, ChunkPos { x: 1, y: 0 });
+		assert_eq!(pos.x(), 16);
+	}
+}
@@ -2,7 +2,6 @@ use euclid::Vector2D;
 use num::FromPrimitive;
 
 use crate::{
-	ty,
 	ty::{direction::Direction, Error, Offset},
 	world::chunk::CHUNK_SIZE,
 };
@@ -22,8 +21,18 @@ use crate::{
 	serde::Deserialize,
 )]
 pub struct ChunkPos {
-	pub x: u32,
-	pub y: u32,
+	pub x: i32,
+	pub y: i32,
+}
+
+impl ChunkPos {
+	/// The chunk containing this block coordinate.
+	pub fn from_block(x: i64, y: i64) -> Option<ChunkPos> {
+		Some(ChunkPos {
+			x: i32::from_i64(x.div_euclid(CHUNK_SIZE as i64))?,
+			y: i32::from_i64(y.div_euclid(CHUNK_SIZE as i64))?,
+		})
+	}
 }
 
 impl Offset<Direction> for ChunkPos {
@@ -51,17 +60,14 @@ impl Offset<Direction> for ChunkPos {
 
 impl Offset<(i32, i32)> for ChunkPos {
 	fn wrapping_offset(self, (dx, dy): (i32, i32)) -> Self {
-		// NOTE(leocth): no joke. this is how `wrapping_add_signed` is implemented.
-		// https://doc.rust-lang.org/src/core/num/uint_macros.rs.html#1205-1207
-
 		Self {
-			x: self.x + dx as u32,
-			y: self.y + dy as u32,
+			x: self.x.wrapping_add(dx),
+			y: self.y.wrapping_add(dy),
 		}
 	}
 	fn checked_offset(self, (dx, dy): (i32, i32)) -> Option<Self> {
-		let x = ty::checked_add_signed_u32(self.x, dx)?;
-		let y = ty::checked_add_signed_u32(self.y, dy)?;
+		let x = self.x.checked_add(dx)?;
+		let y = self.y.checked_add(dy)?;
 		Some(Self { x, y })
 	}
 }
@@ -70,8 +76,8 @@ impl<S> TryFrom<Vector2D<f32, S>> for ChunkPos {
 
 	fn try_from(value: Vector2D<f32, S>) -> Result<Self, Self::Error> {
 		Ok(ChunkPos {
-			x: u32::from_f32(value.x / CHUNK_SIZE as f32).ok_or(Error::OutOfBounds)?,
-			y: u32::from_f32(value.y / CHUNK_SIZE as f32).ok_or(Error::OutOfBounds)?,
+			x: i32::from_f32((value.x / CHUNK_SIZE as f32).floor()).ok_or(Error::OutOfBounds)?,
+			y: i32::from_f32((value.y / CHUNK_SIZE as f32).floor()).ok_or(Error::OutOfBounds)?,
 		})
 	}
 }
@@ -81,8 +87,8 @@ impl TryFrom<(i64, i64)> for ChunkPos {
 
 	fn try_from(value: (i64, i64)) -> Result<Self, Self::Error> {
 		Ok(ChunkPos {
-			x: u32::from_i64(value.0).ok_or(Error::OutOfBounds)?,
-			y: u32::from_i64(value.1).ok_or(Error::OutOfBounds)?,
+			x: i32::from_i64(value.0).ok_or(Error::OutOfBounds)?,
+			y: i32::from_i64(value.1).ok_or(Error::OutOfBounds)?,
 		})
 	}
 }
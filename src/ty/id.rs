@@ -1,4 +1,5 @@
 use std::{
+	cmp::Ordering,
 	hash::{Hash, Hasher},
 	marker::PhantomData,
 };
@@ -8,7 +9,7 @@ use apollo::impl_macro::*;
 
 /// The internal id is a instance bound identifier to the registry,
 /// absolutely not forward/backwards compatible across versions or even game instances.
-#[derive(Ord, PartialOrd, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct Id<P> {
 	id: u32,
 	prototype: PhantomData<P>,
@@ -45,6 +46,12 @@ impl<P> PartialEq<Self> for Id<P> {
 	fn eq(&self, other: &Self) -> bool { self.id == other.id }
 }
 impl<P> Eq for Id<P> {}
+impl<P> PartialOrd<Self> for Id<P> {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+impl<P> Ord for Id<P> {
+	fn cmp(&self, other: &Self) -> Ordering { self.id.cmp(&other.id) }
+}
 
 // This is needed as rustc cringes on the phantomdata
 impl<P> Clone for Id<P> {
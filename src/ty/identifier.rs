@@ -6,7 +6,9 @@ use crate::api::util;
 use apollo::impl_macro::*;
 
 /// The identifier is a dual-string notifying which mod (namespace) the entry is from. and what it is.
-#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+#[derive(
+	Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, serde::Serialize, serde::Deserialize,
+)]
 pub struct Identifier {
 	pub namespace: String,
 	pub path: String,
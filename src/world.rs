@@ -1,62 +1,322 @@
-use chunk::{block::BlockDesc, layer::BlockLayer};
+use chunk::{
+	block::{Block, BlockDesc},
+	block_entity::BlockEntity,
+	layer::BlockLayer,
+	state::BlockState,
+	ChunkLayer, CHUNK_SIZE, CHUNK_SIZE_F32,
+};
+use euclid::{vec2, Vector2D};
 use eyre::Result;
+use fxhash::FxHashSet;
 use hecs::Entity;
+use parking_lot::MutexGuard;
+use rand::Rng;
+use tracing::{debug, warn};
 
 use crate::{
 	debug::DebugRendererImpl,
 	network::Token,
 	packet,
-	ty::{block_pos::BlockPos, id::Id},
-	world::spread::SpreaderSystem,
+	ty::{
+		block_layer_pos::BlockLayerPos, block_pos::BlockPos, direction::Direction, id::Id, Offset,
+		WS,
+	},
+	world::{
+		biome::{BiomeDesc, BiomeSystem, ChunkBiomes, SPAWN_INTERVAL},
+		block_update::BlockUpdateSystem,
+		edit::{BlockEdit, EditOperation, EditSystem},
+		entity::component::PositionComponent,
+		falling::{FallingBlockComponent, FallingBlockSystem, LandedBlock},
+		gen::WorldGenerator,
+		light::{ChunkLight, LightEngine},
+		liquid::{LiquidCell, LiquidSystem},
+		mining::{MinedBlock, MiningSystem},
+		random::{self, WorldRng},
+		random_tick::RandomTickSystem,
+		save::entity::EntitySnapshot,
+		time::{WorldClock, WorldTime},
+	},
 	Api, Chunk, ChunkPos, ChunkStorage, EntityWorld, ServerNetwork,
 };
 use crate::world::entity::prototype::EntityDesc;
 use crate::world::entity::system::network::{EntityComponentPacket, EntityPacket};
 
+pub mod biome;
+pub mod block_update;
+pub mod block_view;
 pub mod chunk;
+pub mod edit;
 pub mod entity;
-pub mod spread;
+pub mod falling;
+pub mod gen;
+pub mod light;
+pub mod liquid;
+pub mod mining;
+pub mod random;
+pub mod random_tick;
+pub mod raycast;
+pub mod save;
+pub mod schematic;
+pub mod time;
 
 packet!(World(ServerBoundWorldPacket, ClientBoundWorldPacket));
 
+/// How many cells a falling block looks up for room when it lands in a solid block,
+/// before it gives up and drops instead.
+const MAX_LAND_STEPS: usize = 4;
+
 #[derive(serde::Serialize, serde::Deserialize)]
 pub enum ServerBoundWorldPacket {
-	RequestChunk(ChunkPos),
+	/// Only allowed in creative, everyone else mines. See [`Self::requires_creative`].
 	SetBlock(BlockPos, Id<BlockLayer>, Id<BlockDesc>),
+	StartMining(BlockPos, Id<BlockLayer>),
+	StopMining,
+	Edit(EditOperation),
+	UndoEdit,
+	RedoEdit,
+	SetLiquid(BlockPos, LiquidCell),
 	SpawnEntity(Id<EntityDesc>, Vec<EntityComponentPacket>),
 	UpdateEntity(EntityPacket),
 }
 
+impl ServerBoundWorldPacket {
+	/// Packets which change blocks or liquids without mining them.
+	pub fn requires_creative(&self) -> bool {
+		matches!(
+			self,
+			ServerBoundWorldPacket::SetBlock(..)
+				| ServerBoundWorldPacket::Edit(..)
+				| ServerBoundWorldPacket::UndoEdit
+				| ServerBoundWorldPacket::RedoEdit
+				| ServerBoundWorldPacket::SetLiquid(..)
+		)
+	}
+}
+
 #[derive(serde::Serialize, serde::Deserialize)]
 pub enum ClientBoundWorldPacket {
-	Chunk(ChunkPos, Chunk),
-	SetBlock(BlockPos, Id<BlockLayer>, Id<BlockDesc>),
+	SetBlock(BlockPos, Id<BlockLayer>, Id<BlockDesc>, BlockState),
+	/// Every block which changed in a chunk during a tick.
+	SetBlocks(ChunkPos, Vec<(BlockLayerPos, Id<BlockLayer>, Id<BlockDesc>, BlockState)>),
+	/// How far a block has been mined, [`None`] once nobody mines it anymore.
+	SetCrack(BlockPos, Id<BlockLayer>, Option<u8>),
+	SetBlockEntity(BlockPos, Id<BlockLayer>, Option<BlockEntity>),
+	SetLiquids(ChunkPos, ChunkLayer<LiquidCell>),
+	SetLight(ChunkPos, ChunkLight),
+	SetBiomes(ChunkPos, ChunkBiomes),
+	SetTime(WorldTime),
 	SpawnEntity(Entity, Id<EntityDesc>),
 	UpdateEntity(EntityPacket),
+	RemoveEntity(Entity),
 }
 
 pub struct World {
-	pub chunks:   ChunkStorage,
-	pub entities: EntityWorld,
+	pub chunks:    ChunkStorage,
+	pub entities:  EntityWorld,
+	pub generator: Option<WorldGenerator>,
 
-	spreader: SpreaderSystem,
+	/// Advanced by the [`Server`] and shared with Lua, see [`time`].
+	///
+	/// [`Server`]: crate::Server
+	clock: WorldClock,
+	/// Every random stream of the world derives from this, see [`random`].
+	seed: u64,
+	/// A remote world mirrors the server and leaves spawning entities to it.
+	remote: bool,
+	random_ticks: RandomTickSystem,
+	block_updates: BlockUpdateSystem,
+	mining: MiningSystem,
+	edits: EditSystem,
+	falling: FallingBlockSystem,
+	drop_rand: WorldRng,
+	spawn_rand: WorldRng,
+	liquids: LiquidSystem,
+	biomes: BiomeSystem,
+	/// Only the server calculates light, clients receive it with the chunks.
+	light: Option<LightEngine>,
+	block_changes: FxHashSet<(BlockPos, Id<BlockLayer>)>,
+	block_entity_changes: FxHashSet<(BlockPos, Id<BlockLayer>)>,
+	biome_changes: FxHashSet<ChunkPos>,
+	spawned: Vec<(Entity, Id<EntityDesc>, Vector2D<f32, WS>)>,
+	despawned: Vec<(Entity, ChunkPos)>,
 }
 
 impl World {
-	pub fn new(api: &Api, chunk: ChunkStorage) -> Result<World> {
-		Ok(World {
-			chunks:   chunk,
-			entities: EntityWorld::new(api)?,
-			spreader: SpreaderSystem::new(),
-		})
+	pub fn new(api: &Api, chunk: ChunkStorage, seed: u64) -> Result<World> {
+		let mut random_ticks = RandomTickSystem::new(api, seed);
+		let mut liquids = LiquidSystem::new();
+		for (pos, chunk) in chunk.iter() {
+			random_ticks.wake_chunk(api, pos, chunk);
+			liquids.wake_chunk(pos, chunk);
+		}
+
+		let world = World {
+			chunks:    chunk,
+			entities:  EntityWorld::new(api)?,
+			generator: None,
+			clock: WorldClock::default(),
+			seed,
+			remote: false,
+			random_ticks,
+			block_updates: BlockUpdateSystem::new(api),
+			mining: MiningSystem::new(),
+			edits: EditSystem::new(),
+			falling: FallingBlockSystem,
+			drop_rand: random::stream(seed, "drops"),
+			spawn_rand: random::stream(seed, "spawns"),
+			liquids,
+			biomes: BiomeSystem::new(api),
+			light: None,
+			block_changes: Default::default(),
+			block_entity_changes: Default::default(),
+			biome_changes: Default::default(),
+			spawned: vec![],
+			despawned: vec![],
+		};
+		world.bind_clock(api);
+		Ok(world)
+	}
+
+	pub fn seed(&self) -> u64 { self.seed }
+
+	pub fn set_remote(&mut self, remote: bool) { self.remote = remote; }
+
+	/// Starts calculating light, lighting every chunk which is already loaded.
+	/// Call this after the generator is set so the sky starts at the right height.
+	pub fn enable_light(&mut self, api: &Api) {
+		let mut sky_level = match &self.generator {
+			Some(generator) => {
+				let settings = generator.settings();
+				(settings.surface_level + settings.surface_amplitude).ceil() as i64
+			}
+			None => i64::MAX,
+		};
+		if let Some(bounds) = self.chunks.bounds() {
+			sky_level = sky_level.min(bounds.max.y as i64 * CHUNK_SIZE as i64 - 1);
+		}
+
+		let mut light = LightEngine::from_api(api, sky_level);
+		let loaded: Vec<ChunkPos> = self.chunks.iter().map(|(pos, _)| pos).collect();
+		for pos in loaded {
+			light.light_chunk(&mut self.chunks, pos);
+		}
+		self.light = Some(light);
+	}
+
+	/// Gets the chunk, generating it first if it does not exist yet.
+	pub fn generate_chunk(&mut self, api: &Api, pos: ChunkPos) -> Option<&Chunk> {
+		if !self.chunks.contains(pos) {
+			if let Some(generator) = &self.generator {
+				let chunk = generator.generate(pos);
+				self.random_ticks.wake_chunk(api, pos, &chunk);
+				self.liquids.wake_chunk(pos, &chunk);
+				self.chunks.insert(pos, chunk);
+				if let Some(light) = &mut self.light {
+					light.light_chunk(&mut self.chunks, pos);
+				}
+			}
+		}
+
+		self.chunks.get(pos)
+	}
+
+	/// Where new players appear, right above the surface in the middle of the world.
+	/// Infinite worlds spawn at the origin.
+	pub fn spawn_point(&self) -> Option<Vector2D<f32, WS>> {
+		let generator = self.generator.as_ref()?;
+		let x = match self.chunks.bounds() {
+			Some(bounds) => ((bounds.min.x + bounds.max.x) as f32 * CHUNK_SIZE_F32 / 2.0).floor(),
+			None => 0.0,
+		};
+		let y = generator.surface_height(x as i64) as f32 + 3.0;
+		Some(vec2(x + 0.5, y))
+	}
+
+	pub fn time(&self) -> WorldTime { self.clock.get() }
+
+	pub fn time_mut(&mut self) -> MutexGuard<'_, WorldTime> { self.clock.lock() }
+
+	/// Makes the clock of this world the `time` global of Lua.
+	fn bind_clock(&self, api: &Api) {
+		if let Err(err) = api.luna.lua.globals().set("time", self.clock.clone()) {
+			warn!("Could not hand the world time to Lua: {err}");
+		}
 	}
 
 	pub fn tick(&mut self, api: &Api, debug: &mut impl DebugRendererImpl) {
-		for (pos, layer_id, block_id) in self.spreader.tick(api, &mut self.chunks, debug) {
-			self.place_block(api, pos, layer_id, block_id);
+		// Another world may have taken the global since the last tick.
+		self.bind_clock(api);
+
+		// Clients get the blocks random ticks change from the server, rolling them on their own
+		// would make them drift apart.
+		if !self.remote {
+			let changes = self.random_ticks.tick(api, &mut self.chunks, debug);
+			for change in changes {
+				self.place_block_state(
+					api,
+					change.pos,
+					change.layer_id,
+					change.block_id,
+					change.state,
+				);
+			}
 		}
+		// The server syncs the blocks neighbor updates change, or turn into falling blocks.
+		if !self.remote {
+			let updates = self.block_updates.tick(api, &mut self.chunks);
+			for change in updates.changes {
+				self.place_block_state(
+					api,
+					change.pos,
+					change.layer_id,
+					change.block_id,
+					change.state,
+				);
+			}
+			for (pos, layer_id) in updates.falling {
+				self.start_falling(api, pos, layer_id);
+			}
+		}
+
+		// Clients get the liquids from the server.
+		if !self.remote {
+			let layers = &api.carrier.block_layer;
+			let reactions =
+				self.liquids.tick(&api.carrier.liquid, &mut self.chunks, |chunk, entry| {
+					chunk.layers.iter().any(|(layer_id, layer)| {
+						layers.get(layer_id).collision && layer[entry].collision
+					})
+				});
+			for (pos, layer_id, block_id) in reactions {
+				self.place_block(api, pos, layer_id, block_id);
+			}
+		}
+
+		for mined in self.mining.tick(api, &self.chunks) {
+			self.break_block(api, mined);
+		}
+
+		// Clients get the biomes and the entities they spawn from the server.
+		if !self.remote {
+			self.biome_changes.extend(self.biomes.tick(&mut self.chunks));
+		}
+		if !self.remote && self.time().ticks() % SPAWN_INTERVAL == 0 {
+			let spawn = self.biomes.pick_spawn(
+				api,
+				&self.chunks,
+				&self.entities.storage,
+				&mut self.spawn_rand,
+			);
+			if let Some((id, pos)) = spawn {
+				self.spawn_entity(api, id, pos);
+			}
+		}
+
 		// Entity
 		self.entities.tick(api, &self.chunks, debug);
+		for landed in self.falling.tick(&mut self.entities.storage) {
+			self.land_block(api, landed);
+		}
 	}
 
 	pub fn place_block(
@@ -66,18 +326,344 @@ impl World {
 		layer_id: Id<BlockLayer>,
 		block_id: Id<BlockDesc>,
 	) {
-		if let Some(chunk) = self.chunks.get_mut(pos.chunk) {
-			// Layer
-			let layer = chunk.layers.get_mut(layer_id);
+		self.place_block_state(api, pos, layer_id, block_id, BlockState::DEFAULT);
+	}
+
+	/// Changes made through this get synced to every player which has the chunk loaded.
+	pub fn place_block_state(
+		&mut self,
+		api: &Api,
+		pos: BlockPos,
+		layer_id: Id<BlockLayer>,
+		block_id: Id<BlockDesc>,
+		state: BlockState,
+	) {
+		if let Some(chunk) = self.chunks.get_mut_untracked(pos.chunk) {
+			self.block_changes.insert((pos, layer_id));
 			let prototype = api.carrier.block_layer.get(layer_id);
 
 			// Block
 			let block_prototype = prototype.blocks.get(block_id);
-			layer[pos.entry] = block_prototype.create(block_id);
+			chunk.set_block(
+				layer_id,
+				pos.entry,
+				block_prototype.create_with_state(block_id, state),
+				block_prototype.create_block_entity(),
+			);
 
-			self.spreader
+			self.random_ticks
 				.place_block(pos, layer_id, block_id, block_prototype);
+			self.block_updates.block_changed(pos, layer_id);
+			self.biomes.block_changed(pos.chunk);
+			if block_prototype.falls.is_some() {
+				self.block_updates.schedule(pos, layer_id);
+			}
+
+			// Solid blocks push out whatever liquid was there, and removing one lets liquids flow in.
+			if prototype.collision && block_prototype.collision {
+				chunk.liquids.set(pos.entry, LiquidCell::EMPTY);
+				self.chunks.mark_liquids(pos.chunk);
+				self.liquids.mark_changed(pos.chunk);
+			}
+			self.chunks.mark_block(pos, layer_id);
+			self.liquids.wake(pos);
+			if let Some(light) = &mut self.light {
+				light.block_changed(&mut self.chunks, pos);
+			}
+		}
+	}
+
+	/// Runs a world-edit operation as one batch which `actor` can undo.
+	/// Returns how many blocks changed, or an error if the layer or block does not exist.
+	pub fn edit(&mut self, api: &Api, actor: Token, operation: &EditOperation) -> Result<usize> {
+		let layer_id = operation.layer_id();
+		let block_id = operation.block_id();
+		if !api.carrier.block_layer.table.contains(layer_id) {
+			eyre::bail!("Block layer {} does not exist", layer_id.index());
+		}
+		let blocks = &api.carrier.block_layer.get(layer_id).blocks;
+		if !blocks.table.contains(block_id) {
+			eyre::bail!("Block {} does not exist", block_id.index());
+		}
+		let after = blocks.get(block_id).create(block_id);
+
+		let mut batch = Vec::new();
+		for pos in operation.plan(&self.chunks)? {
+			if let Some(chunk) = self.chunks.get(pos.chunk) {
+				let before = chunk.layers.get(layer_id)[pos.entry];
+				if before != after {
+					batch.push(BlockEdit {
+						pos,
+						layer_id,
+						before,
+						after,
+					});
+				}
+			}
 		}
+
+		self.apply_edits(api, batch.iter().map(|edit| (edit.pos, edit.layer_id, edit.after)));
+		let changed = batch.len();
+		self.edits.record(actor, batch);
+		Ok(changed)
+	}
+
+	/// Reverts the last edit of `actor`, returning false if there is nothing to undo.
+	pub fn undo_edit(&mut self, api: &Api, actor: Token) -> bool {
+		let batch: Vec<_> = match self.edits.undo(actor) {
+			Some(batch) => batch
+				.iter()
+				.rev()
+				.map(|edit| (edit.pos, edit.layer_id, edit.before))
+				.collect(),
+			None => return false,
+		};
+		self.apply_edits(api, batch.into_iter());
+		true
+	}
+
+	/// Applies the last undone edit of `actor` again, returning false if there is nothing to redo.
+	pub fn redo_edit(&mut self, api: &Api, actor: Token) -> bool {
+		let batch: Vec<_> = match self.edits.redo(actor) {
+			Some(batch) => batch
+				.iter()
+				.map(|edit| (edit.pos, edit.layer_id, edit.after))
+				.collect(),
+			None => return false,
+		};
+		self.apply_edits(api, batch.into_iter());
+		true
+	}
+
+	/// Drops the edit history of an actor which left.
+	pub fn forget_edits(&mut self, actor: Token) { self.edits.forget(actor); }
+
+	fn apply_edits(
+		&mut self,
+		api: &Api,
+		edits: impl Iterator<Item = (BlockPos, Id<BlockLayer>, Block)>,
+	) {
+		for (pos, layer_id, block) in edits {
+			self.place_block_state(api, pos, layer_id, block.id, block.state);
+		}
+	}
+
+	/// Replaces a mined block with the layer default and spawns its drops.
+	fn break_block(&mut self, api: &Api, mined: MinedBlock) {
+		let layer = api.carrier.block_layer.get(mined.layer_id);
+		self.place_block(api, mined.pos, mined.layer_id, layer.default);
+		if mined.drops {
+			self.spawn_drops(api, mined.pos, mined.layer_id, mined.block_id);
+		}
+	}
+
+	fn spawn_drops(
+		&mut self,
+		api: &Api,
+		pos: BlockPos,
+		layer_id: Id<BlockLayer>,
+		block_id: Id<BlockDesc>,
+	) {
+		let layer = api.carrier.block_layer.get(layer_id);
+		let center = vec2(pos.x() as f32 + 0.5, pos.y() as f32 + 0.5);
+		for drop in &layer.blocks.get(block_id).drops {
+			if self.drop_rand.gen_range(0.0..1.0) >= drop.chance {
+				continue;
+			}
+
+			for _ in 0..self.drop_rand.gen_range(drop.min..=drop.max) {
+				self.spawn_entity(api, drop.entity, center);
+			}
+		}
+	}
+
+	/// Turns a block which lost its support into its falling entity.
+	fn start_falling(&mut self, api: &Api, pos: BlockPos, layer_id: Id<BlockLayer>) {
+		let block = match self.chunks.get(pos.chunk) {
+			Some(chunk) => chunk.layers.get(layer_id)[pos.entry],
+			None => return,
+		};
+		let layer = api.carrier.block_layer.get(layer_id);
+		let entity_id = match layer.blocks.get(block.id).falls {
+			Some(entity_id) => entity_id,
+			None => return,
+		};
+
+		self.place_block(api, pos, layer_id, layer.default);
+		let center = vec2(pos.x() as f32 + 0.5, pos.y() as f32 + 0.5);
+		let entity = self.spawn_entity(api, entity_id, center);
+		self.entities.storage.insert_comp(
+			entity,
+			FallingBlockComponent {
+				layer_id,
+				block_id: block.id,
+				state: block.state,
+			},
+		);
+	}
+
+	/// Places a falling block where it landed. If something solid got there first the block goes
+	/// in the first free cell above it, and drops if there is none within [`MAX_LAND_STEPS`].
+	fn land_block(&mut self, api: &Api, landed: LandedBlock) {
+		self.despawned.push((landed.entity, landed.pos.chunk));
+		let FallingBlockComponent {
+			layer_id,
+			block_id,
+			state,
+		} = landed.block;
+		let blocks = &api.carrier.block_layer.get(layer_id).blocks;
+		let up = Direction::Up.offset();
+		let free = std::iter::successors(Some(landed.pos), |pos| pos.checked_offset(up))
+			.take(MAX_LAND_STEPS)
+			.find(|pos| {
+				self.chunks.get(pos.chunk).map_or(false, |chunk| {
+					!blocks.get(chunk.layers.get(layer_id)[pos.entry].id).collision
+				})
+			});
+		match free {
+			Some(pos) => self.place_block_state(api, pos, layer_id, block_id, state),
+			None => self.spawn_drops(api, landed.pos, layer_id, block_id),
+		}
+	}
+
+	/// Spawns an entity which gets synced to everyone watching its chunk.
+	pub fn spawn_entity(
+		&mut self,
+		api: &Api,
+		id: Id<EntityDesc>,
+		pos: Vector2D<f32, WS>,
+	) -> Entity {
+		let entity = self.entities.storage.push(api, id);
+		self.entities.packet(&EntityPacket {
+			entity,
+			component: EntityComponentPacket::Pos { set_pos: pos },
+		});
+		self.spawned.push((entity, id, pos));
+		entity
+	}
+
+	/// Removes an entity, which gets synced to everyone watching the chunk it was in.
+	pub fn despawn_entity(&mut self, entity: Entity) {
+		let pos = self
+			.entities
+			.storage
+			.get_comp::<PositionComponent>(entity)
+			.map(|position| position.pos);
+		if self.entities.storage.remove(entity).is_none() {
+			return;
+		}
+		if let Some(chunk) = pos.and_then(|pos| ChunkPos::try_from(pos).ok()) {
+			self.despawned.push((entity, chunk));
+		}
+	}
+
+	/// Moves an entity with all of its registered components into another world, where it gets
+	/// a new handle. Both worlds sync the move to their players.
+	pub fn transfer_entity(&mut self, api: &Api, entity: Entity, to: &mut World) -> Option<Entity> {
+		let snapshot = EntitySnapshot::new(api, &self.entities.storage, entity)?;
+		let id = snapshot.prototype_id(api).ok()?;
+		let pos = snapshot.position().map_or(Vector2D::zero(), |comp| comp.pos);
+		self.despawn_entity(entity);
+
+		let new = to.spawn_entity(api, id, pos);
+		snapshot.restore(api, &mut to.entities.storage, new);
+		Some(new)
+	}
+
+	/// Blocks placed since the last call, the server syncs these and the client drops them.
+	pub fn drain_block_changes(
+		&mut self,
+	) -> impl Iterator<Item = (BlockPos, Id<BlockLayer>)> + '_ {
+		self.block_changes.drain()
+	}
+
+	pub(crate) fn drain_cracks(
+		&mut self,
+	) -> impl Iterator<Item = (BlockPos, Id<BlockLayer>, Option<u8>)> + '_ {
+		self.mining.drain_cracks()
+	}
+
+	/// Entities the world spawned on its own, like block drops.
+	pub(crate) fn drain_spawned(
+		&mut self,
+	) -> impl Iterator<Item = (Entity, Id<EntityDesc>, Vector2D<f32, WS>)> + '_ {
+		self.spawned.drain(..)
+	}
+
+	/// Entities the world removed on its own and the chunk they were in.
+	pub(crate) fn drain_despawned(&mut self) -> impl Iterator<Item = (Entity, ChunkPos)> + '_ {
+		self.despawned.drain(..)
+	}
+
+	pub fn biome(&self, pos: BlockPos) -> Option<Id<BiomeDesc>> {
+		self.chunks.get(pos.chunk)?.biomes.get(pos.entry.x())
+	}
+
+	/// Changes the biome of the column in this chunk, which gets synced to every player which
+	/// has the chunk loaded.
+	pub fn set_biome(&mut self, pos: BlockPos, biome: Option<Id<BiomeDesc>>) {
+		if let Some(chunk) = self.chunks.get_mut_untracked(pos.chunk) {
+			chunk.biomes.set(pos.entry.x(), biome);
+			self.biome_changes.insert(pos.chunk);
+		}
+	}
+
+	pub(crate) fn drain_biome_changes(&mut self) -> impl Iterator<Item = ChunkPos> + '_ {
+		self.biome_changes.drain()
+	}
+
+	pub fn liquid(&self, pos: BlockPos) -> Option<LiquidCell> {
+		self.liquids.get(&self.chunks, pos)
+	}
+
+	/// Changes made through this get synced to every player which has the chunk loaded.
+	pub fn set_liquid(&mut self, api: &Api, pos: BlockPos, cell: LiquidCell) -> Result<()> {
+		if let Some(liquid) = cell.liquid {
+			if !api.carrier.liquid.table.contains(liquid) {
+				eyre::bail!("Liquid {} does not exist", liquid.index());
+			}
+		}
+		self.liquids.set(&mut self.chunks, pos, cell);
+		Ok(())
+	}
+
+	pub(crate) fn drain_liquid_changes(&mut self) -> impl Iterator<Item = ChunkPos> + '_ {
+		self.liquids.drain_changes()
+	}
+
+	pub(crate) fn drain_light_changes(&mut self) -> Vec<ChunkPos> {
+		match &mut self.light {
+			Some(light) => light.drain_changes().collect(),
+			None => Vec::new(),
+		}
+	}
+
+	pub fn block_entity(&self, pos: BlockPos, layer_id: Id<BlockLayer>) -> Option<&BlockEntity> {
+		self.chunks
+			.get(pos.chunk)?
+			.block_entities
+			.get(&(layer_id, pos.entry))
+	}
+
+	/// Changes made through this get synced to every player which has the chunk loaded.
+	pub fn block_entity_mut(
+		&mut self,
+		pos: BlockPos,
+		layer_id: Id<BlockLayer>,
+	) -> Option<&mut BlockEntity> {
+		let block_entity = self
+			.chunks
+			.get_mut_untracked(pos.chunk)?
+			.block_entities
+			.get_mut(&(layer_id, pos.entry))?;
+		self.block_entity_changes.insert((pos, layer_id));
+		Some(block_entity)
+	}
+
+	pub(crate) fn drain_block_entity_changes(
+		&mut self,
+	) -> impl Iterator<Item = (BlockPos, Id<BlockLayer>)> + '_ {
+		self.block_entity_changes.drain()
 	}
 
 	pub(crate) fn packet(
@@ -88,17 +674,34 @@ impl World {
 		network: &mut ServerNetwork,
 	) -> Result<()> {
 		match packet {
-			ServerBoundWorldPacket::RequestChunk(chunk_pos) => {
-				if let Some(chunk) = self.chunks.get(chunk_pos) {
-					network.send(
-						token,
-						ClientBoundWorldPacket::Chunk(chunk_pos, chunk.clone()),
-					)?;
-				}
-			}
 			ServerBoundWorldPacket::SetBlock(pos, layer_id, block_id) => {
 				self.place_block(api, pos, layer_id, block_id);
 			}
+			ServerBoundWorldPacket::StartMining(pos, layer_id) => {
+				if api.carrier.block_layer.table.contains(layer_id) {
+					self.mining.start(&self.chunks, token, pos, layer_id);
+				} else {
+					warn!("{token:?} tried to mine in unknown layer {}", layer_id.index());
+				}
+			}
+			ServerBoundWorldPacket::StopMining => {
+				self.mining.stop(token);
+			}
+			ServerBoundWorldPacket::Edit(operation) => match self.edit(api, token, &operation) {
+				Ok(changed) => debug!("{token:?} edited {changed} blocks"),
+				Err(err) => warn!("{token:?} could not edit: {err}"),
+			},
+			ServerBoundWorldPacket::UndoEdit => {
+				self.undo_edit(api, token);
+			}
+			ServerBoundWorldPacket::RedoEdit => {
+				self.redo_edit(api, token);
+			}
+			ServerBoundWorldPacket::SetLiquid(pos, cell) => {
+				if let Err(err) = self.set_liquid(api, pos, cell) {
+					warn!("{token:?} could not set a liquid: {err}");
+				}
+			}
 			ServerBoundWorldPacket::SpawnEntity(id, packets) => {
 				let entity = self.entities.storage.push(api, id);
 				network.send(token, ClientBoundWorldPacket::SpawnEntity(entity, id))?;
@@ -120,3 +723,249 @@ impl World {
 		Ok(())
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use apollo::Function;
+	use euclid::rect;
+
+	use super::*;
+	use crate::{
+		api::test::{api, block_desc, entity},
+		debug::DummyRenderer,
+		world::{
+			chunk::{
+				random_tick::{RandomTick, TickBehavior},
+				test::{block, chunk},
+			},
+			entity::component::{CollisionComponent, GravityComponent, PhysicsComponent},
+		},
+		TPS,
+	};
+
+	fn decaying_api() -> Api {
+		let mut grass = block_desc(true);
+		grass.random_tick = Some(RandomTick {
+			chance: 6.0,
+			biome_chance: Default::default(),
+			behavior: TickBehavior::Decay(unsafe { Id::new(2) }),
+		});
+		api(
+			vec![("air", block_desc(false)), ("grass", grass), ("dirt", block_desc(true))],
+			vec![],
+		)
+	}
+
+	const CHUNKS: [ChunkPos; 2] = [ChunkPos { x: 0, y: 0 }, ChunkPos { x: -1, y: 3 }];
+
+	fn ids(world: &World) -> Vec<Id<BlockDesc>> {
+		let mut out = Vec::new();
+		for pos in CHUNKS {
+			let layer = world.chunks.get(pos).unwrap().layers.get(unsafe { Id::new(0) });
+			layer.entries(|_, block| out.push(block.id));
+		}
+		out
+	}
+
+	#[test]
+	fn same_seed_ticks_the_same() {
+		let api = decaying_api();
+		let layer_id = unsafe { Id::new(0) };
+		let world = |seed| {
+			let mut chunks = ChunkStorage::unbounded();
+			for pos in CHUNKS {
+				chunks.insert(pos, chunk(layer_id, 1));
+			}
+			World::new(&api, chunks, seed).unwrap()
+		};
+
+		let mut first = world(42);
+		let mut second = world(42);
+		let mut other = world(43);
+		for _ in 0..10 {
+			first.tick(&api, &mut DummyRenderer);
+			second.tick(&api, &mut DummyRenderer);
+			other.tick(&api, &mut DummyRenderer);
+		}
+
+		let blocks = ids(&first);
+		let dirt = unsafe { Id::new(2) };
+		let decayed = blocks.iter().filter(|id| **id == dirt).count();
+		assert!(decayed > 0 && decayed < blocks.len());
+		assert_eq!(blocks, ids(&second));
+		assert_ne!(blocks, ids(&other));
+	}
+
+	fn sand_api() -> Api {
+		let mut sand = block_desc(true);
+		sand.falls = Some(unsafe { Id::new(0) });
+		let mut falling_sand = entity();
+		falling_sand.velocity = Some(PhysicsComponent {
+			vel: Vector2D::zero(),
+			accel: Vector2D::zero(),
+		});
+		falling_sand.collision = Some(CollisionComponent {
+			collision_box: rect(-0.49, -0.49, 0.98, 0.98),
+			hit_callback: None,
+			collided: Default::default(),
+			collisions: vec![],
+		});
+		falling_sand.gravity = Some(GravityComponent { amount: 1.0 });
+		api(
+			vec![("air", block_desc(false)), ("stone", block_desc(true)), ("sand", sand)],
+			vec![("falling_sand", falling_sand)],
+		)
+	}
+
+	fn block_at(world: &World, x: i64, y: i64) -> Id<BlockDesc> {
+		let pos = BlockPos::from_block(x, y).unwrap();
+		world.chunks.get(pos.chunk).unwrap().layers.get(unsafe { Id::new(0) })[pos.entry].id
+	}
+
+	fn falling_blocks(world: &World) -> usize {
+		world.entities.storage.query::<&FallingBlockComponent>().iter().count()
+	}
+
+	#[test]
+	fn unsupported_blocks_fall_and_land() {
+		let api = sand_api();
+		let layer_id = unsafe { Id::new(0) };
+		let (air, stone, sand) = unsafe { (Id::new(0), Id::new(1), Id::new(2)) };
+		let mut chunks = ChunkStorage::unbounded();
+		chunks.insert(ChunkPos { x: 0, y: 0 }, chunk(layer_id, 0));
+		let mut world = World::new(&api, chunks, 42).unwrap();
+		let at = |y| BlockPos::from_block(4, y).unwrap();
+		world.place_block(&api, at(1), layer_id, stone);
+		world.place_block(&api, at(6), layer_id, stone);
+		world.place_block(&api, at(7), layer_id, sand);
+
+		world.tick(&api, &mut DummyRenderer);
+		assert_eq!(block_at(&world, 4, 7), sand);
+		assert_eq!(falling_blocks(&world), 0);
+
+		world.place_block(&api, at(6), layer_id, air);
+		world.tick(&api, &mut DummyRenderer);
+		assert_eq!(block_at(&world, 4, 7), air);
+		assert_eq!(falling_blocks(&world), 1);
+
+		for _ in 0..600 {
+			if falling_blocks(&world) == 0 {
+				break;
+			}
+			world.tick(&api, &mut DummyRenderer);
+		}
+		assert_eq!(falling_blocks(&world), 0);
+		assert_eq!(block_at(&world, 4, 2), sand);
+	}
+
+	#[test]
+	fn blocks_landing_in_solid_blocks_step_up() {
+		let api = sand_api();
+		let layer_id = unsafe { Id::new(0) };
+		let (stone, sand) = unsafe { (Id::new(1), Id::new(2)) };
+		let mut chunks = ChunkStorage::unbounded();
+		chunks.insert(ChunkPos { x: 0, y: 0 }, chunk(layer_id, 0));
+		let mut world = World::new(&api, chunks, 42).unwrap();
+		for y in 2..4 {
+			world.place_block(&api, BlockPos::from_block(4, y).unwrap(), layer_id, stone);
+		}
+
+		let entity = world.spawn_entity(&api, unsafe { Id::new(0) }, vec2(4.5, 2.5));
+		world.land_block(&api, LandedBlock {
+			entity,
+			pos: BlockPos::from_block(4, 2).unwrap(),
+			block: FallingBlockComponent {
+				layer_id,
+				block_id: sand,
+				state: BlockState::DEFAULT,
+			},
+		});
+		assert_eq!(block_at(&world, 4, 4), sand);
+	}
+
+	#[test]
+	fn lua_sets_the_clock_while_ticking() {
+		let mut api = api(vec![("air", block_desc(false)), ("clock", block_desc(true))], vec![]);
+		let callback = api
+			.luna
+			.lua
+			.load("return function() time:set_time(0.0) end")
+			.eval()
+			.unwrap();
+		let layer_id = unsafe { Id::new(0) };
+		let clock = unsafe { Id::new(1) };
+		api.carrier.block_layer.get_mut(layer_id).blocks.get_mut(clock).random_tick =
+			Some(RandomTick {
+				// Ticks every tick.
+				chance: TPS as f32,
+				biome_chance: Default::default(),
+				behavior: TickBehavior::Lua(callback),
+			});
+
+		let mut chunks = ChunkStorage::unbounded();
+		let mut with_clock = chunk(layer_id, 0);
+		with_clock.layers.get_mut(layer_id).set(BlockLayerPos::new(3, 3), block(1));
+		chunks.insert(ChunkPos { x: 0, y: 0 }, with_clock);
+		let mut world = World::new(&api, chunks, 42).unwrap();
+		assert!(world.time().is_day());
+
+		world.tick(&api, &mut DummyRenderer);
+		assert_eq!(world.time().time_of_day(), 0.0);
+		assert!(world.time_mut().take_changed());
+
+		// The global outlives the tick.
+		let set_time: Function = api
+			.luna
+			.lua
+			.load("return function() time:set_time(0.5) end")
+			.eval()
+			.unwrap();
+		set_time.call::<_, ()>(()).unwrap();
+		assert_eq!(world.time().time_of_day(), 0.5);
+	}
+
+	#[test]
+	fn transferred_entities_keep_their_components() {
+		let api = sand_api();
+		let layer_id = unsafe { Id::new(0) };
+		let mut from = World::new(&api, ChunkStorage::unbounded(), 42).unwrap();
+		let mut to = World::new(&api, ChunkStorage::unbounded(), 43).unwrap();
+		let entity = from.spawn_entity(&api, unsafe { Id::new(0) }, vec2(-3.5, 20.0));
+		from.entities.storage.insert_comp(entity, FallingBlockComponent {
+			layer_id,
+			block_id: unsafe { Id::new(2) },
+			state: BlockState::DEFAULT,
+		});
+
+		let moved = from.transfer_entity(&api, entity, &mut to).unwrap();
+		assert!(!from.entities.storage.contains(entity));
+		assert_eq!(from.despawned, vec![(entity, ChunkPos { x: -1, y: 1 })]);
+		assert_eq!(to.spawned.len(), 1);
+		assert_eq!(to.spawned[0].0, moved);
+
+		let storage = &to.entities.storage;
+		assert_eq!(storage.get_comp::<PositionComponent>(moved).unwrap().pos, vec2(-3.5, 20.0));
+		let falling = storage.get_comp::<FallingBlockComponent>(moved).unwrap();
+		assert!(falling.block_id == unsafe { Id::new(2) });
+		assert!(storage.get_comp::<CollisionComponent>(moved).is_some());
+	}
+
+	#[test]
+	fn edits_reject_unknown_layers_and_blocks() {
+		let api = sand_api();
+		let layer_id = unsafe { Id::new(0) };
+		let mut chunks = ChunkStorage::unbounded();
+		chunks.insert(ChunkPos { x: 0, y: 0 }, chunk(layer_id, 0));
+		let mut world = World::new(&api, chunks, 42).unwrap();
+		let fill = |layer_id, block_id| EditOperation::Fill {
+			from: BlockPos::from_block(1, 1).unwrap(),
+			to: BlockPos::from_block(2, 2).unwrap(),
+			layer_id: unsafe { Id::new(layer_id) },
+			block_id: unsafe { Id::new(block_id) },
+		};
+
+		assert!(world.edit(&api, Token(), &fill(7, 1)).is_err());
+		assert!(world.edit(&api, Token(), &fill(0, 99)).is_err());
+		assert_eq!(world.edit(&api, Token(), &fill(0, 1)).unwrap(), 4);
+	}
+}
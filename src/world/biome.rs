@@ -0,0 +1,504 @@
+//! Regions of the world with their own blocks, spawns and ambience.
+//!
+//! Every chunk stores the biome of each of its columns. The generator picks the biome whose
+//! `temperature` and `humidity` lie closest to the climate noise of a column and builds its
+//! surface from the biome blocks. Biomes with `convert` take over a chunk once enough of their
+//! blocks spread into it, like corrupt grass turning a forest into corruption.
+//! Every [`SPAWN_INTERVAL`] the server picks a random loaded column and spawns an entity from the
+//! `spawns` of its biome on the highest ground in that chunk, until [`MAX_SPAWNED`] of them live.
+//! ```lua
+//! reload.stargate.biome:register {
+//!     ["forest"] = {
+//!         temperature = 0.5,
+//!         humidity = 0.6,
+//!         surface = "grass",
+//!         filler = "dirt",
+//!         underground = "stone",
+//!         spawns = { ["slime"] = 1.0, ["zombie"] = 0.5 },
+//!         ambient = { sky_color = { 0.45, 0.65, 0.9 } }
+//!     },
+//!     ["corruption"] = {
+//!         -- Only appears by converting other biomes.
+//!         generate = false,
+//!         surface = "corrupt_grass",
+//!         convert = { layer = "tile", blocks = { ["corrupt_grass"] = 8 } }
+//!     }
+//! }
+//! ```
+//! Biomes get baked before the block layers so blocks can refer to them, which is why the biome
+//! blocks stay identifiers until the generator and [`BiomeSystem`] resolve them.
+use std::collections::{BTreeSet, HashMap};
+
+use apollo::{impl_macro::*, FromLua, Lua, LuaSerdeExt, Value};
+use euclid::{vec2, Vector2D};
+use eyre::{bail, ContextCompat, Result};
+use fxhash::FxHashSet;
+use rand::Rng;
+use tracing::{error_span, warn};
+
+use crate::{
+	api::{
+		luna::table::LunaTable, prototype::Prototype, registry::Registry, util::lua_table, Api,
+	},
+	ty::{block_layer_pos::BlockLayerPos, block_pos::BlockPos, id::Id, identifier::Identifier, WS},
+	world::{
+		chunk::{block::BlockDesc, layer::BlockLayer, Chunk, CHUNK_SIZE},
+		entity::{component::PrototypeComponent, prototype::EntityDesc, EntityStorage},
+	},
+	ChunkPos, ChunkStorage, TPS,
+};
+
+/// How many chunks get checked for conversions every tick.
+pub const MAX_CONVERSION_CHECKS: usize = 16;
+/// Ticks between two attempts at spawning an entity from the biome spawn tables.
+pub const SPAWN_INTERVAL: u64 = TPS as u64 * 2;
+/// Biomes stop spawning once this many entities from their spawn tables are alive.
+pub const MAX_SPAWNED: usize = 32;
+
+pub struct BiomeDesc {
+	pub temperature: f32,
+	pub humidity: f32,
+	/// If the generator places this biome, otherwise it only appears through conversions.
+	pub generate: bool,
+	/// The top block of the tile layer.
+	pub surface: Option<Identifier>,
+	/// The blocks between the surface and the stone.
+	pub filler: Option<Identifier>,
+	pub underground: Option<Identifier>,
+	/// The entities which spawn here with their weights.
+	pub spawns: Vec<(Id<EntityDesc>, f32)>,
+	pub ambient: BiomeAmbient,
+	pub convert: Option<BiomeConversionPrototype>,
+}
+
+#[lua_impl]
+impl BiomeDesc {
+	#[lua_method]
+	pub fn get_temperature(&self) -> f32 { self.temperature }
+
+	#[lua_method]
+	pub fn get_humidity(&self) -> f32 { self.humidity }
+}
+
+impl BiomeDesc {
+	/// A random entity from the spawn table, weighted.
+	pub fn pick_spawn(&self, rand: &mut impl Rng) -> Option<Id<EntityDesc>> {
+		let total: f32 = self.spawns.iter().map(|(_, weight)| weight).sum();
+		// Huge weights can still add up to infinity.
+		if !(total > 0.0 && total.is_finite()) {
+			return None;
+		}
+
+		let mut pick = rand.gen_range(0.0..total);
+		for (entity, weight) in &self.spawns {
+			if pick < *weight {
+				return Some(*entity);
+			}
+			pick -= weight;
+		}
+		self.spawns.last().map(|(entity, _)| *entity)
+	}
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct BiomeAmbient {
+	#[serde(default = "BiomeAmbient::default_sky_color")]
+	pub sky_color: [f32; 3],
+}
+
+impl BiomeAmbient {
+	fn default_sky_color() -> [f32; 3] { [0.10, 0.10, 0.10] }
+}
+
+impl Default for BiomeAmbient {
+	fn default() -> Self {
+		BiomeAmbient {
+			sky_color: BiomeAmbient::default_sky_color(),
+		}
+	}
+}
+
+pub struct BiomePrototype {
+	pub temperature: f32,
+	pub humidity: f32,
+	pub generate: bool,
+	pub surface: Option<Identifier>,
+	pub filler: Option<Identifier>,
+	pub underground: Option<Identifier>,
+	pub spawns: HashMap<Identifier, f32>,
+	pub ambient: BiomeAmbient,
+	pub convert: Option<BiomeConversionPrototype>,
+}
+
+impl BiomePrototype {
+	pub fn bake(self, entities: &Registry<EntityDesc>) -> Result<BiomeDesc> {
+		let mut spawns = Vec::new();
+		for (entity, weight) in self.spawns {
+			let id = entities
+				.get_id(&entity)
+				.wrap_err_with(|| format!("Could not find spawned entity {entity}"))?;
+			if !weight.is_finite() || weight < 0.0 {
+				bail!("Spawn weight {weight} of {entity} must be a positive number");
+			}
+			spawns.push((id, weight));
+		}
+		// Keeps picking spawns the same for the same registries.
+		spawns.sort_by_key(|(id, _)| *id);
+
+		Ok(BiomeDesc {
+			temperature: self.temperature,
+			humidity: self.humidity,
+			generate: self.generate,
+			surface: self.surface,
+			filler: self.filler,
+			underground: self.underground,
+			spawns,
+			ambient: self.ambient,
+			convert: self.convert,
+		})
+	}
+}
+
+impl Prototype for BiomePrototype {
+	type Output = BiomeDesc;
+
+	fn get_name() -> &'static str { "biome" }
+
+	fn from_lua(table: LunaTable) -> Result<Self> {
+		let _span = error_span!(target: "lua", "biome").entered();
+		let temperature: Option<f32> = table.get("temperature")?;
+		let humidity: Option<f32> = table.get("humidity")?;
+		let generate: Option<bool> = table.get("generate")?;
+		let spawns: Option<HashMap<Identifier, f32>> = table.get("spawns")?;
+		let ambient: Value = table.get("ambient")?;
+		Ok(BiomePrototype {
+			temperature: temperature.unwrap_or(0.5),
+			humidity: humidity.unwrap_or(0.5),
+			generate: generate.unwrap_or(true),
+			surface: table.get("surface")?,
+			filler: table.get("filler")?,
+			underground: table.get("underground")?,
+			spawns: spawns.unwrap_or_default(),
+			ambient: match ambient {
+				Value::Nil => BiomeAmbient::default(),
+				value => table.lua.from_value(value)?,
+			},
+			convert: table.get("convert")?,
+		})
+	}
+}
+
+/// A chunk holding at least that many of one of the `blocks` turns into the biome.
+#[derive(Clone, Debug)]
+pub struct BiomeConversionPrototype {
+	pub layer: Identifier,
+	pub blocks: HashMap<Identifier, u32>,
+}
+
+impl FromLua for BiomeConversionPrototype {
+	fn from_lua(lua_value: Value, lua: &Lua) -> Result<Self> {
+		let table = lua_table(lua_value)?;
+		Ok(BiomeConversionPrototype {
+			layer: table.get("layer")?,
+			blocks: FromLua::from_lua(table.get("blocks")?, lua)?,
+		})
+	}
+}
+
+/// The biome of every column in a chunk, [`None`] where no biome was assigned.
+#[derive(Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+pub struct ChunkBiomes {
+	columns: [Option<Id<BiomeDesc>>; CHUNK_SIZE],
+}
+
+impl ChunkBiomes {
+	pub fn get(&self, x: u8) -> Option<Id<BiomeDesc>> {
+		self.columns.get(x as usize).copied().flatten()
+	}
+
+	pub fn set(&mut self, x: u8, biome: Option<Id<BiomeDesc>>) {
+		if let Some(column) = self.columns.get_mut(x as usize) {
+			*column = biome;
+		}
+	}
+
+	pub fn columns(&self) -> &[Option<Id<BiomeDesc>>; CHUNK_SIZE] { &self.columns }
+
+	/// Sets every column, returning if anything changed.
+	pub fn fill(&mut self, biome: Option<Id<BiomeDesc>>) -> bool {
+		let changed = self.columns.iter().any(|column| *column != biome);
+		self.columns = [biome; CHUNK_SIZE];
+		changed
+	}
+}
+
+struct Conversion {
+	biome: Id<BiomeDesc>,
+	layer_id: Id<BlockLayer>,
+	blocks: Vec<(Id<BlockDesc>, u32)>,
+}
+
+impl Conversion {
+	fn matches(&self, chunk: &Chunk) -> bool {
+		let layer = chunk.layers.get(self.layer_id);
+		self.blocks.iter().any(|(block_id, amount)| {
+			if !layer.palette().iter().any(|block| block.id == *block_id) {
+				return false;
+			}
+
+			let mut count = 0;
+			layer.entries(|_, block| {
+				if block.id == *block_id {
+					count += 1;
+				}
+			});
+			count >= *amount
+		})
+	}
+}
+
+/// Converts the biome of chunks whose blocks changed and spawns the entities of the biomes.
+pub struct BiomeSystem {
+	conversions: Vec<Conversion>,
+	dirty: BTreeSet<ChunkPos>,
+	/// Every entity which shows up in a spawn table.
+	spawnable: FxHashSet<Id<EntityDesc>>,
+}
+
+impl BiomeSystem {
+	pub fn new(api: &Api) -> BiomeSystem {
+		let mut conversions = Vec::new();
+		let mut spawnable = FxHashSet::default();
+		for (biome, identifier, desc) in api.carrier.biome.entries() {
+			spawnable.extend(desc.spawns.iter().map(|(entity, _)| *entity));
+			let convert = match &desc.convert {
+				Some(convert) => convert,
+				None => continue,
+			};
+			let layer_id = match api.carrier.block_layer.get_id(&convert.layer) {
+				Some(layer_id) => layer_id,
+				None => {
+					warn!("Biome {identifier} converts in unknown layer {}", convert.layer);
+					continue;
+				}
+			};
+
+			let layer = api.carrier.block_layer.get(layer_id);
+			let mut blocks = Vec::new();
+			for (block, amount) in &convert.blocks {
+				match layer.blocks.get_id(block) {
+					Some(block_id) => blocks.push((block_id, *amount)),
+					None => warn!("Biome {identifier} converts on unknown block {block}"),
+				}
+			}
+			blocks.sort_by_key(|(block_id, _)| *block_id);
+			conversions.push(Conversion {
+				biome,
+				layer_id,
+				blocks,
+			});
+		}
+
+		BiomeSystem {
+			conversions,
+			dirty: BTreeSet::new(),
+			spawnable,
+		}
+	}
+
+	pub fn block_changed(&mut self, pos: ChunkPos) {
+		if !self.conversions.is_empty() {
+			self.dirty.insert(pos);
+		}
+	}
+
+	/// Returns the chunks which changed biome.
+	pub fn tick(&mut self, chunks: &mut ChunkStorage) -> Vec<ChunkPos> {
+		let checked: Vec<ChunkPos> = self
+			.dirty
+			.iter()
+			.take(MAX_CONVERSION_CHECKS)
+			.copied()
+			.collect();
+
+		let mut changed = Vec::new();
+		for pos in checked {
+			self.dirty.remove(&pos);
+			let chunk = match chunks.get(pos) {
+				Some(chunk) => chunk,
+				None => continue,
+			};
+
+			// The first biome in registry order wins.
+			let biome = self
+				.conversions
+				.iter()
+				.find(|conversion| conversion.matches(chunk))
+				.map(|conversion| conversion.biome);
+			// Biomes do not show up in the chunk meshes, so nothing gets marked.
+			let chunk = chunks.get_mut_untracked(pos);
+			if let (Some(biome), Some(chunk)) = (biome, chunk) {
+				if chunk.biomes.fill(Some(biome)) {
+					changed.push(pos);
+				}
+			}
+		}
+		changed
+	}
+
+	/// Picks an entity from the spawn table of a random loaded column, standing on the highest
+	/// ground of that column in its chunk. [`None`] if the column has nowhere to stand, no biome
+	/// or too many spawned entities are alive.
+	pub fn pick_spawn(
+		&self,
+		api: &Api,
+		chunks: &ChunkStorage,
+		storage: &EntityStorage,
+		rand: &mut impl Rng,
+	) -> Option<(Id<EntityDesc>, Vector2D<f32, WS>)> {
+		if self.spawnable.is_empty() {
+			return None;
+		}
+		let alive = storage
+			.query::<&PrototypeComponent>()
+			.iter()
+			.filter(|(_, prototype)| self.spawnable.contains(&prototype.id))
+			.count();
+		if alive >= MAX_SPAWNED {
+			return None;
+		}
+
+		// Sorted so the same world picks the same chunk.
+		let mut loaded: Vec<ChunkPos> = chunks.iter().map(|(pos, _)| pos).collect();
+		if loaded.is_empty() {
+			return None;
+		}
+		loaded.sort();
+		let pos = loaded[rand.gen_range(0..loaded.len())];
+		let chunk = chunks.get(pos)?;
+		let x = rand.gen_range(0..CHUNK_SIZE) as u8;
+		let entity = api.carrier.biome.get(chunk.biomes.get(x)?).pick_spawn(rand)?;
+
+		let solid = |y: u8| {
+			let entry = BlockLayerPos::new(x, y);
+			chunk.layers.iter().any(|(layer_id, layer)| {
+				api.carrier.block_layer.get(layer_id).collision && layer[entry].collision
+			})
+		};
+		let y = (1..CHUNK_SIZE as u8).rev().find(|y| !solid(*y) && solid(y - 1))?;
+		let ground = BlockPos::new(pos, BlockLayerPos::new(x, y));
+		Some((entity, vec2(ground.x() as f32 + 0.5, ground.y() as f32 + 0.5)))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		api::test::{api, block_desc, entity},
+		world::{
+			chunk::test::{block, chunk},
+			random,
+		},
+	};
+
+	#[test]
+	fn chunks_convert_once_enough_blocks_spread() {
+		let layer_id = unsafe { Id::new(0) };
+		let biome = unsafe { Id::new(1) };
+		let mut system = BiomeSystem {
+			conversions: vec![Conversion {
+				biome,
+				layer_id,
+				blocks: vec![(unsafe { Id::new(2) }, 3)],
+			}],
+			dirty: BTreeSet::new(),
+			spawnable: FxHashSet::default(),
+		};
+
+		let pos = ChunkPos::default();
+		let mut chunks = ChunkStorage::unbounded();
+		chunks.insert(pos, chunk(layer_id, 0));
+
+		for x in 0..3 {
+			let chunk = chunks.get_mut(pos).unwrap();
+			let entry = BlockLayerPos::new(x, 0);
+			chunk.layers.get_mut(layer_id).set(entry, block(2));
+			system.block_changed(pos);
+			let changed = system.tick(&mut chunks);
+			assert_eq!(changed.len(), if x == 2 { 1 } else { 0 });
+		}
+		assert!(chunks.get(pos).unwrap().biomes.get(7) == Some(biome));
+
+		// Already converted, so nothing changes anymore.
+		system.block_changed(pos);
+		assert!(system.tick(&mut chunks).is_empty());
+	}
+
+	#[test]
+	fn spawns_entities_on_the_ground_of_their_biome() {
+		let mut api = api(
+			vec![("air", block_desc(false)), ("stone", block_desc(true))],
+			vec![("slime", entity())],
+		);
+		let (biome, slime) = unsafe { (Id::new(0), Id::new(0)) };
+		let forest = BiomeDesc {
+			temperature: 0.5,
+			humidity: 0.5,
+			generate: true,
+			surface: None,
+			filler: None,
+			underground: None,
+			spawns: vec![(slime, 1.0)],
+			ambient: BiomeAmbient::default(),
+			convert: None,
+		};
+		api.carrier.biome = vec![(biome, Identifier::new("forest"), forest)]
+			.into_iter()
+			.collect();
+		let system = BiomeSystem::new(&api);
+
+		let layer_id = unsafe { Id::new(0) };
+		let mut ground = chunk(layer_id, 0);
+		for x in 0..CHUNK_SIZE as u8 {
+			for y in 0..4 {
+				ground.layers.get_mut(layer_id).set(BlockLayerPos::new(x, y), block(1));
+			}
+		}
+		ground.biomes.fill(Some(biome));
+		let mut chunks = ChunkStorage::unbounded();
+		chunks.insert(ChunkPos { x: 2, y: -1 }, ground);
+
+		let mut storage = EntityStorage::new();
+		let mut rand = random::stream(42, "spawns");
+		let (entity, pos) = system.pick_spawn(&api, &chunks, &storage, &mut rand).unwrap();
+		assert!(entity == slime);
+		assert_eq!(pos.y, -16.0 + 4.5);
+		assert!((32.0..48.0).contains(&pos.x));
+
+		for _ in 0..MAX_SPAWNED {
+			storage.push(&api, slime);
+		}
+		assert!(system.pick_spawn(&api, &chunks, &storage, &mut rand).is_none());
+	}
+
+	#[test]
+	fn rejects_invalid_spawn_weights() {
+		let api = api(vec![("air", block_desc(false))], vec![("slime", entity())]);
+		let biome = |weight| BiomePrototype {
+			temperature: 0.5,
+			humidity: 0.5,
+			generate: true,
+			surface: None,
+			filler: None,
+			underground: None,
+			spawns: HashMap::from([(Identifier::new("slime"), weight)]),
+			ambient: BiomeAmbient::default(),
+			convert: None,
+		};
+		for weight in [f32::NAN, f32::INFINITY, -1.0] {
+			assert!(biome(weight).bake(&api.carrier.entity).is_err());
+		}
+		assert!(biome(0.5).bake(&api.carrier.entity).is_ok());
+	}
+}
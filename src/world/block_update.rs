@@ -0,0 +1,241 @@
+//! Tells blocks when the blocks next to them change.
+//!
+//! Every placed block queues an update for its four neighbours in the same layer. Updates
+//! queued while the queue runs wait for the next tick, so blocks which keep changing each other
+//! take one step per tick instead of locking up the server. At most [`MAX_UPDATES_PER_TICK`]
+//! updates run per tick and the rest stay queued. Past [`MAX_QUEUED_UPDATES`] new updates
+//! get dropped. Blocks which fall check the block below them before running their handler.
+use std::{collections::VecDeque, sync::Arc};
+
+use apollo::{LuaScope, Value};
+use fxhash::FxHashSet;
+use tracing::{error, warn};
+
+use crate::{
+	ty::{block_pos::BlockPos, direction::Direction, id::Id, Offset},
+	world::{
+		block_view::BlockView,
+		chunk::{
+			layer::BlockLayer,
+			neighbor_update::{is_supported, NeighborHandler},
+			random_tick::TickChange,
+		},
+		gen::pass::BlockLookup,
+	},
+	Api, ChunkStorage,
+};
+
+pub const MAX_UPDATES_PER_TICK: usize = 1024;
+pub const MAX_QUEUED_UPDATES: usize = 65536;
+
+/// The pending updates in the order they got queued, each position at most once.
+#[derive(Default)]
+struct UpdateQueue {
+	queue: VecDeque<(BlockPos, Id<BlockLayer>)>,
+	queued: FxHashSet<(BlockPos, Id<BlockLayer>)>,
+	overflowed: bool,
+}
+
+impl UpdateQueue {
+	fn push(&mut self, pos: BlockPos, layer_id: Id<BlockLayer>) {
+		if self.queued.len() >= MAX_QUEUED_UPDATES {
+			if !self.overflowed {
+				warn!("More than {MAX_QUEUED_UPDATES} block updates queued, dropping new ones.");
+				self.overflowed = true;
+			}
+			return;
+		}
+
+		if self.queued.insert((pos, layer_id)) {
+			self.queue.push_back((pos, layer_id));
+		}
+	}
+
+	/// The updates which run this tick, anything queued after this waits for the next one.
+	fn take_batch(&mut self) -> Vec<(BlockPos, Id<BlockLayer>)> {
+		let len = self.queue.len().min(MAX_UPDATES_PER_TICK);
+		let batch: Vec<_> = self.queue.drain(..len).collect();
+		for key in &batch {
+			self.queued.remove(key);
+		}
+		self.overflowed = false;
+		batch
+	}
+}
+
+#[derive(Default)]
+pub struct BlockUpdates {
+	/// Blocks the handlers want placed.
+	pub changes: Vec<TickChange>,
+	/// Blocks with `falls` which lost the block below them.
+	pub falling: Vec<(BlockPos, Id<BlockLayer>)>,
+}
+
+pub struct BlockUpdateSystem {
+	queue: UpdateQueue,
+	lookup: Arc<BlockLookup>,
+}
+
+impl BlockUpdateSystem {
+	pub fn new(api: &Api) -> BlockUpdateSystem {
+		BlockUpdateSystem {
+			queue: UpdateQueue::default(),
+			lookup: Arc::new(BlockLookup::new(api)),
+		}
+	}
+
+	/// Queues updates for the neighbours of a block which changed.
+	pub fn block_changed(&mut self, pos: BlockPos, layer_id: Id<BlockLayer>) {
+		for dir in Direction::values() {
+			if let Some(neighbor) = pos.checked_offset(dir.offset()) {
+				self.queue.push(neighbor, layer_id);
+			}
+		}
+	}
+
+	/// Queues an update for the block itself, like a falling block which just got placed.
+	pub fn schedule(&mut self, pos: BlockPos, layer_id: Id<BlockLayer>) {
+		self.queue.push(pos, layer_id);
+	}
+
+	pub fn tick(&mut self, api: &Api, chunks: &mut ChunkStorage) -> BlockUpdates {
+		let mut updates = BlockUpdates::default();
+		let mut callbacks = Vec::new();
+		for (pos, layer_id) in self.queue.take_batch() {
+			let block = match chunks.get(pos.chunk) {
+				Some(chunk) => chunk.layers.get(layer_id)[pos.entry],
+				None => continue,
+			};
+
+			let layer = api.carrier.block_layer.get(layer_id);
+			let desc = layer.blocks.get(block.id);
+			if desc.falls.is_some()
+				&& !is_supported(pos, Direction::Down, layer_id, layer, chunks)
+			{
+				updates.falling.push((pos, layer_id));
+				continue;
+			}
+
+			match &desc.neighbor_update {
+				Some(NeighborHandler::Lua(callback)) => {
+					callbacks.push((pos, layer_id, block.id, callback.clone()));
+				}
+				Some(handler) => updates
+					.changes
+					.extend(handler.update(pos, layer_id, layer, chunks)),
+				None => {}
+			}
+		}
+
+		if !callbacks.is_empty() {
+			let lookup = self.lookup.clone();
+			updates.changes.extend(BlockView::lend(chunks, lookup, |view| {
+				for (pos, layer_id, block_id, callback) in callbacks {
+					let view_scope = LuaScope::from(&mut *view);
+					// Updates only run when a neighbour changes, so the result has nothing to stop.
+					let result = callback.call::<_, Value>((view_scope.lua(), pos.x(), pos.y()));
+					if let Err(err) = result {
+						error!(
+							target: "lua",
+							"Neighbor update of {} at {pos:?} failed: {err}",
+							self.lookup.identifier(layer_id, block_id)
+						);
+					}
+				}
+			}));
+		}
+
+		updates
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		api::test::{api, block_desc},
+		world::chunk::test::{block, chunk},
+		ChunkPos,
+	};
+
+	const AIR: usize = 0;
+	const STONE: usize = 1;
+
+	fn set(chunks: &mut ChunkStorage, x: i64, y: i64, id: usize) -> BlockPos {
+		let pos = BlockPos::from_block(x, y).unwrap();
+		let layer_id = unsafe { Id::new(0) };
+		chunks
+			.get_mut(pos.chunk)
+			.unwrap()
+			.layers
+			.get_mut(layer_id)
+			.set(pos.entry, block(id));
+		pos
+	}
+
+	#[test]
+	fn unsupported_blocks_break_across_chunks() {
+		let supported = |dir| {
+			let mut desc = block_desc(false);
+			desc.neighbor_update = Some(NeighborHandler::Support(dir));
+			desc
+		};
+		let api = api(
+			vec![
+				("air", block_desc(false)),
+				("stone", block_desc(true)),
+				("torch", supported(Direction::Down)),
+				("vine", supported(Direction::Up)),
+				("sign", supported(Direction::Left)),
+			],
+			vec![],
+		);
+		let layer_id = unsafe { Id::new(0) };
+		let mut chunks = ChunkStorage::unbounded();
+		chunks.insert(ChunkPos { x: 0, y: 0 }, chunk(layer_id, AIR));
+		chunks.insert(ChunkPos { x: 1, y: 0 }, chunk(layer_id, AIR));
+
+		// Everything hangs on a stone in the last column of the first chunk.
+		let stone = set(&mut chunks, 15, 5, STONE);
+		let torch = set(&mut chunks, 15, 6, 2);
+		let vine = set(&mut chunks, 15, 4, 3);
+		let sign = set(&mut chunks, 16, 5, 4);
+
+		let mut system = BlockUpdateSystem::new(&api);
+		system.block_changed(stone, layer_id);
+		let updates = system.tick(&api, &mut chunks);
+		assert!(updates.changes.is_empty());
+
+		set(&mut chunks, 15, 5, AIR);
+		system.block_changed(stone, layer_id);
+		let updates = system.tick(&api, &mut chunks);
+		let mut broken: Vec<_> = updates.changes.iter().map(|change| change.pos).collect();
+		broken.sort_by_key(|pos| (pos.x(), pos.y()));
+		assert_eq!(broken, vec![vine, torch, sign]);
+		assert!(updates
+			.changes
+			.iter()
+			.all(|change| change.block_id == unsafe { Id::new(AIR) }));
+	}
+
+	#[test]
+	fn updates_run_once_per_batch() {
+		let layer_id = unsafe { Id::new(0) };
+		let pos = BlockPos::from_block(5, 5).unwrap();
+		let mut queue = UpdateQueue::default();
+		queue.push(pos, layer_id);
+		queue.push(pos, layer_id);
+		assert_eq!(queue.take_batch().len(), 1);
+
+		// Queued again while running, so it waits for the next batch.
+		queue.push(pos, layer_id);
+		assert_eq!(queue.take_batch().len(), 1);
+		assert!(queue.take_batch().is_empty());
+
+		for x in 0..MAX_UPDATES_PER_TICK as i64 + 10 {
+			queue.push(BlockPos::from_block(x, 0).unwrap(), layer_id);
+		}
+		assert_eq!(queue.take_batch().len(), MAX_UPDATES_PER_TICK);
+		assert_eq!(queue.take_batch().len(), 10);
+	}
+}
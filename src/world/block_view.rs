@@ -0,0 +1,185 @@
+//! The world handed to Lua block callbacks, like random ticks and neighbor updates.
+//!
+//! Lua borrows the chunks during the callbacks, like a generation pass borrows its chunk.
+//! Positions are world positions, and placed blocks only show up once the world applies them
+//! after every callback ran. The clock is the `time` global, see [`WorldClock`].
+//! Area queries take two corners in any order and skip unloaded chunks.
+//!
+//! [`WorldClock`]: crate::world::time::WorldClock
+use std::{collections::HashMap, sync::Arc};
+
+use apollo::{impl_macro::*, Lua, LuaSerdeExt, Value};
+use eyre::{ContextCompat, Result};
+
+use crate::{
+	ty::{block_pos::BlockPos, identifier::Identifier},
+	world::{
+		chunk::{
+			query::{BlockArea, MAX_QUERY_BLOCKS},
+			random_tick::TickChange,
+			state::BlockState,
+		},
+		gen::pass::BlockLookup,
+	},
+	ChunkStorage,
+};
+
+pub struct BlockView {
+	chunks: ChunkStorage,
+	lookup: Arc<BlockLookup>,
+	changes: Vec<TickChange>,
+}
+
+impl BlockView {
+	/// Moves the chunks into a view for `func`, returning the blocks the callbacks want placed.
+	pub fn lend(
+		chunks: &mut ChunkStorage,
+		lookup: Arc<BlockLookup>,
+		func: impl FnOnce(&mut BlockView),
+	) -> Vec<TickChange> {
+		let empty = ChunkStorage::with_bounds(chunks.bounds());
+		let mut view = BlockView {
+			chunks: std::mem::replace(chunks, empty),
+			lookup,
+			changes: Vec::new(),
+		};
+		func(&mut view);
+		*chunks = view.chunks;
+		view.changes
+	}
+
+	fn pos(x: i64, y: i64) -> Result<BlockPos> {
+		BlockPos::from_block(x, y).wrap_err_with(|| format!("{x}, {y} is outside of the world"))
+	}
+
+	fn area(x0: i64, y0: i64, x1: i64, y1: i64) -> Result<BlockArea> {
+		let area = BlockArea::between(Self::pos(x0, y0)?, Self::pos(x1, y1)?);
+		if area.blocks() > MAX_QUERY_BLOCKS {
+			eyre::bail!(
+				"Query of {} blocks is larger than the limit of {MAX_QUERY_BLOCKS}",
+				area.blocks()
+			);
+		}
+		Ok(area)
+	}
+}
+
+#[derive(serde::Serialize)]
+struct FoundBlock<'a> {
+	x: i64,
+	y: i64,
+	block: &'a str,
+}
+
+#[lua_impl]
+impl BlockView {
+	/// The block identifier, nil if the chunk is not loaded.
+	#[lua_method]
+	pub fn get_block(&self, layer: Identifier, x: i64, y: i64) -> Result<Option<String>> {
+		let layer_id = self.lookup.layer(&layer)?;
+		let pos = Self::pos(x, y)?;
+		Ok(self.chunks.get(pos.chunk).map(|chunk| {
+			let block = chunk.layers.get(layer_id)[pos.entry];
+			self.lookup.identifier(layer_id, block.id).to_string()
+		}))
+	}
+
+	/// The biome identifier, nil if the chunk is not loaded or the column has no biome.
+	#[lua_method]
+	pub fn get_biome(&self, x: i64, y: i64) -> Result<Option<String>> {
+		let pos = Self::pos(x, y)?;
+		let biome = self
+			.chunks
+			.get(pos.chunk)
+			.and_then(|chunk| chunk.biomes.get(pos.entry.x()));
+		Ok(biome.map(|biome| self.lookup.biome_identifier(biome).to_string()))
+	}
+
+	/// Every block in the area as `{ x, y, block }` tables.
+	#[lua_method]
+	pub fn get_blocks(
+		&self,
+		lua: &Lua,
+		layer: Identifier,
+		x0: i64,
+		y0: i64,
+		x1: i64,
+		y1: i64,
+	) -> Result<Value> {
+		let layer_id = self.lookup.layer(&layer)?;
+		let blocks: Vec<_> = self
+			.chunks
+			.blocks_in(Self::area(x0, y0, x1, y1)?, layer_id)
+			.map(|(pos, block)| FoundBlock {
+				x: pos.x(),
+				y: pos.y(),
+				block: self.lookup.identifier(layer_id, block.id),
+			})
+			.collect();
+		Ok(lua.to_value(&blocks)?)
+	}
+
+	/// The positions of every `block` in the area as `{ x, y }` tables.
+	#[allow(clippy::too_many_arguments)]
+	#[lua_method]
+	pub fn find_blocks(
+		&self,
+		lua: &Lua,
+		layer: Identifier,
+		block: Identifier,
+		x0: i64,
+		y0: i64,
+		x1: i64,
+		y1: i64,
+	) -> Result<Value> {
+		let (layer_id, (block, _)) = self.lookup.block(&layer, &block)?;
+		let area = Self::area(x0, y0, x1, y1)?;
+		let found: Vec<_> = self
+			.chunks
+			.find_block_id(area, layer_id, block.id)
+			.into_iter()
+			.map(|pos| HashMap::from([("x", pos.x()), ("y", pos.y())]))
+			.collect();
+		Ok(lua.to_value(&found)?)
+	}
+
+	/// How many of every block there are in the area, keyed by the block identifier.
+	#[lua_method]
+	pub fn count_blocks(
+		&self,
+		lua: &Lua,
+		layer: Identifier,
+		x0: i64,
+		y0: i64,
+		x1: i64,
+		y1: i64,
+	) -> Result<Value> {
+		let layer_id = self.lookup.layer(&layer)?;
+		let counts: HashMap<_, _> = self
+			.chunks
+			.count_blocks(Self::area(x0, y0, x1, y1)?, layer_id)
+			.into_iter()
+			.map(|(block_id, count)| (self.lookup.identifier(layer_id, block_id), count))
+			.collect();
+		Ok(lua.to_value(&counts)?)
+	}
+
+	#[lua_method]
+	pub fn set_block(
+		&mut self,
+		layer: Identifier,
+		x: i64,
+		y: i64,
+		block: Identifier,
+	) -> Result<()> {
+		let pos = Self::pos(x, y)?;
+		let (layer_id, (block, _)) = self.lookup.block(&layer, &block)?;
+		self.changes.push(TickChange {
+			pos,
+			layer_id,
+			block_id: block.id,
+			state: BlockState::DEFAULT,
+		});
+		Ok(())
+	}
+}
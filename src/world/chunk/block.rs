@@ -1,18 +1,30 @@
 use std::collections::HashMap;
 
-use eyre::WrapErr;
-use apollo::Value;
+use apollo::{Lua, Value};
+use eyre::{ContextCompat, WrapErr};
 
 use crate::{
-	api::{luna::table::LunaTable, prototype::Prototype},
+	api::{luna::table::LunaTable, prototype::Prototype, registry::Registry},
 	ty::{id::Id, identifier::Identifier},
-	world::chunk::spread::{BlockSpreader, BlockSpreaderPrototype},
+	world::chunk::{
+		block_entity::{BlockEntity, BlockEntityDesc, BlockEntityPrototype},
+		mining::{bake_drops, BlockDrop, BlockDropPrototype, ToolRequirement},
+		neighbor_update::{NeighborHandler, NeighborHandlerPrototype},
+		random_tick::{RandomTick, RandomTickPrototype},
+		state::{BlockProperties, BlockState, PropertyValue},
+	},
+	world::{
+		biome::BiomeDesc,
+		entity::prototype::EntityDesc,
+		light::{DEFAULT_SOLID_OPACITY, MAX_LIGHT},
+	},
 };
 use apollo::impl_macro::*;
-#[derive(Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[derive(Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Block {
 	pub id: Id<BlockDesc>,
 	pub collision: bool,
+	pub state: BlockState,
 }
 
 #[lua_impl]
@@ -26,38 +38,148 @@ impl Block {
 	pub fn get_collision(&self) -> bool {
 		self.collision
 	}
+
+	#[lua_method]
+	pub fn get_state(&self) -> u16 {
+		self.state.0
+	}
 }
 
 pub struct BlockDesc {
 	pub collision: bool,
-	pub spread: Option<BlockSpreader>,
+	pub random_tick: Option<RandomTick>,
+	pub neighbor_update: Option<NeighborHandler>,
+	pub block_entity: Option<BlockEntityDesc>,
+	pub properties: BlockProperties,
+	/// How much light this block gives off, up to [`MAX_LIGHT`].
+	pub light_emission: u8,
+	/// How much light is lost when passing through this block.
+	pub light_opacity: u8,
+	/// Seconds it takes to mine by hand.
+	pub hardness: f32,
+	pub tool: Option<ToolRequirement>,
+	pub drops: Vec<BlockDrop>,
+	/// The entity this block turns into when nothing holds it up, see [`falling`].
+	///
+	/// [`falling`]: crate::world::falling
+	pub falls: Option<Id<EntityDesc>>,
 }
 
 #[lua_impl]
 impl BlockDesc {
 	#[lua_method]
 	pub fn create(&self, id: Id<BlockDesc>) -> Block {
+		self.create_with_state(id, BlockState::DEFAULT)
+	}
+
+	/// Lua side of [`BlockDesc::create_with_state`], which checks that the state exists.
+	#[lua_method]
+	pub fn create_with(&self, id: Id<BlockDesc>, state: u16) -> eyre::Result<Block> {
+		if state as u32 >= self.properties.state_count() {
+			eyre::bail!("State {state} does not exist");
+		}
+		Ok(self.create_with_state(id, BlockState(state)))
+	}
+
+	pub fn create_with_state(&self, id: Id<BlockDesc>, state: BlockState) -> Block {
 		Block {
 			id,
 			collision: self.collision,
+			state,
 		}
 	}
+
+	/// The value of a property in `state`, nil if the block has no such property.
+	#[lua_method]
+	pub fn get_property(&self, lua: &Lua, state: u16, name: String) -> eyre::Result<Value> {
+		Ok(match self.properties.get(BlockState(state), &name) {
+			Some(value) => value.to_lua(lua)?,
+			None => Value::Nil,
+		})
+	}
+
+	/// `state` with the property `name` changed to `value`.
+	#[lua_method]
+	pub fn set_property(
+		&self,
+		state: u16,
+		name: String,
+		value: PropertyValue,
+	) -> eyre::Result<u16> {
+		Ok(self.properties.with(BlockState(state), &name, &value)?.0)
+	}
+
+	pub fn create_block_entity(&self) -> Option<BlockEntity> {
+		self.block_entity.as_ref().map(BlockEntityDesc::create)
+	}
 }
 
 pub struct BlockPrototype {
 	pub collision: bool,
-	pub spread: Option<BlockSpreaderPrototype>,
+	pub random_tick: Option<RandomTickPrototype>,
+	pub neighbor_update: Option<NeighborHandlerPrototype>,
+	pub block_entity: Option<BlockEntityPrototype>,
+	pub properties: Option<BlockProperties>,
+	pub light_emission: Option<u8>,
+	pub light_opacity: Option<u8>,
+	pub hardness: Option<f32>,
+	pub tool: Option<ToolRequirement>,
+	pub drops: Option<HashMap<Identifier, BlockDropPrototype>>,
+	pub falls: Option<Identifier>,
 }
 
 impl BlockPrototype {
-	pub fn bake(self, blocks: &HashMap<Identifier, Id<BlockDesc>>) -> eyre::Result<BlockDesc> {
+	/// Blocks in layers without collision do not block light unless they say so.
+	pub fn bake(
+		self,
+		blocks: &HashMap<Identifier, Id<BlockDesc>>,
+		layer_collision: bool,
+		entities: &Registry<EntityDesc>,
+		biomes: &Registry<BiomeDesc>,
+	) -> eyre::Result<BlockDesc> {
+		let solid = layer_collision && self.collision;
+		let properties = self.properties.unwrap_or_default();
 		Ok(BlockDesc {
 			collision: self.collision,
-			spread: if let Some(spread) = self.spread {
-				Some(spread.bake(blocks).wrap_err("Could not bake spreader")?)
+			random_tick: if let Some(random_tick) = self.random_tick {
+				Some(
+					random_tick
+						.bake(blocks, &properties, biomes)
+						.wrap_err("Could not bake random tick")?,
+				)
 			} else {
 				None
 			},
+			neighbor_update: if let Some(neighbor_update) = self.neighbor_update {
+				Some(
+					neighbor_update
+						.bake()
+						.wrap_err("Could not bake neighbor update")?,
+				)
+			} else {
+				None
+			},
+			block_entity: self.block_entity.map(BlockEntityPrototype::bake),
+			properties,
+			light_emission: self.light_emission.unwrap_or(0).min(MAX_LIGHT),
+			light_opacity: self
+				.light_opacity
+				.unwrap_or(if solid { DEFAULT_SOLID_OPACITY } else { 0 })
+				.min(MAX_LIGHT),
+			hardness: self
+				.hardness
+				.unwrap_or(if self.collision { 1.0 } else { 0.0 }),
+			tool: self.tool,
+			drops: bake_drops(self.drops.unwrap_or_default(), entities)
+				.wrap_err("Could not bake drops")?,
+			falls: match self.falls {
+				Some(entity) => Some(
+					entities
+						.get_id(&entity)
+						.wrap_err_with(|| format!("Could not find falling entity {entity}"))?,
+				),
+				None => None,
+			},
 		})
 	}
 }
@@ -70,7 +192,16 @@ impl Prototype for BlockPrototype {
 	fn from_lua(table: LunaTable) -> eyre::Result<Self> {
 		Ok(BlockPrototype {
 			collision: table.get("collision")?,
-			spread: table.get("spread")?,
+			random_tick: table.get("random_tick")?,
+			neighbor_update: table.get("neighbor_update")?,
+			block_entity: table.get("block_entity")?,
+			properties: table.get("properties")?,
+			light_emission: table.get("light_emission")?,
+			light_opacity: table.get("light_opacity")?,
+			hardness: table.get("hardness")?,
+			tool: table.get("tool")?,
+			drops: table.get("drops")?,
+			falls: table.get("falls")?,
 		})
 	}
 }
@@ -0,0 +1,163 @@
+//! Extra state attached to a single block, like the contents of a chest or the text on a sign.
+//!
+//! Blocks declare a block entity in their prototype:
+//! ```lua
+//! ["chest"] = {
+//!     collision = true,
+//!     block_entity = {
+//!         data = { items = {} }
+//!     }
+//! }
+//! ```
+//! Every chest placed then gets its own copy of `data`.
+use std::collections::BTreeMap;
+
+use apollo::{FromLua, Lua, Value};
+
+use crate::api::util::lua_table;
+
+/// How deep tables may nest, which also stops tables that contain themselves.
+const MAX_DEPTH: usize = 64;
+
+/// A Lua like value which can be stored and sent over the network.
+#[derive(Clone, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+pub enum DataValue {
+	Nil,
+	Bool(bool),
+	Int(i64),
+	Float(f64),
+	String(String),
+	List(Vec<DataValue>),
+	Map(BTreeMap<String, DataValue>),
+}
+
+impl DataValue {
+	pub fn to_lua(&self, lua: &Lua) -> apollo::Result<Value> {
+		Ok(match self {
+			DataValue::Nil => Value::Nil,
+			DataValue::Bool(value) => Value::Boolean(*value),
+			DataValue::Int(value) => Value::Integer(*value),
+			DataValue::Float(value) => Value::Number(*value),
+			DataValue::String(value) => Value::String(lua.create_string(value)?),
+			DataValue::List(values) => {
+				let table = lua.create_table()?;
+				for (i, value) in values.iter().enumerate() {
+					table.set(i + 1, value.to_lua(lua)?)?;
+				}
+				Value::Table(table)
+			}
+			DataValue::Map(values) => {
+				let table = lua.create_table()?;
+				for (key, value) in values {
+					table.set(key.as_str(), value.to_lua(lua)?)?;
+				}
+				Value::Table(table)
+			}
+		})
+	}
+
+	fn from_lua_nested(lua_value: Value, depth: usize) -> eyre::Result<Self> {
+		Ok(match lua_value {
+			Value::Nil => DataValue::Nil,
+			Value::Boolean(value) => DataValue::Bool(value),
+			Value::Integer(value) => DataValue::Int(value),
+			Value::Number(value) => DataValue::Float(value),
+			Value::String(value) => DataValue::String(value.to_str()?.to_string()),
+			Value::Table(table) => {
+				if depth >= MAX_DEPTH {
+					eyre::bail!("Block entity data nests deeper than {MAX_DEPTH} tables");
+				}
+				let mut entries = Vec::new();
+				for pair in table.pairs::<Value, Value>() {
+					let (key, value) = pair?;
+					entries.push((key, DataValue::from_lua_nested(value, depth + 1)?));
+				}
+
+				// Tables keyed 1 to n are lists, everything else is a map.
+				let is_list = entries.iter().all(|(key, _)| {
+					matches!(key, Value::Integer(i) if *i >= 1 && *i as usize <= entries.len())
+				});
+				if is_list {
+					entries.sort_by_key(|(key, _)| match key {
+						Value::Integer(i) => *i,
+						_ => unreachable!(),
+					});
+					DataValue::List(entries.into_iter().map(|(_, value)| value).collect())
+				} else {
+					let mut map = BTreeMap::new();
+					for (key, value) in entries {
+						let key = match key {
+							Value::String(key) => key.to_str()?.to_string(),
+							Value::Integer(key) => key.to_string(),
+							key => eyre::bail!("Block entity data keys must be strings, not {key:?}"),
+						};
+						map.insert(key, value);
+					}
+					DataValue::Map(map)
+				}
+			}
+			value => eyre::bail!("{value:?} can not be stored in a block entity"),
+		})
+	}
+}
+
+impl FromLua for DataValue {
+	fn from_lua(lua_value: Value, _: &Lua) -> eyre::Result<Self> {
+		DataValue::from_lua_nested(lua_value, 0)
+	}
+}
+
+#[derive(Clone, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+pub struct BlockEntity {
+	pub data: DataValue,
+}
+
+pub struct BlockEntityDesc {
+	/// What every new instance starts out with.
+	pub data: DataValue,
+}
+
+impl BlockEntityDesc {
+	pub fn create(&self) -> BlockEntity {
+		BlockEntity {
+			data: self.data.clone(),
+		}
+	}
+}
+
+pub struct BlockEntityPrototype {
+	pub data: DataValue,
+}
+
+impl BlockEntityPrototype {
+	pub fn bake(self) -> BlockEntityDesc { BlockEntityDesc { data: self.data } }
+}
+
+impl FromLua for BlockEntityPrototype {
+	fn from_lua(lua_value: Value, _: &Lua) -> eyre::Result<Self> {
+		let table = lua_table(lua_value)?;
+		Ok(BlockEntityPrototype {
+			data: table.get("data")?,
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn rejects_cyclic_tables() {
+		let lua = Lua::new();
+		let cyclic: Value = lua.load("local t = {} t.self = t return t").eval().unwrap();
+		assert!(DataValue::from_lua(cyclic, &lua).is_err());
+
+		let nested: Value = lua.load("return { a = { b = { 1, 2 } } }").eval().unwrap();
+		let list = DataValue::List(vec![DataValue::Int(1), DataValue::Int(2)]);
+		let inner = DataValue::Map(BTreeMap::from([("b".to_string(), list)]));
+		assert_eq!(
+			DataValue::from_lua(nested, &lua).unwrap(),
+			DataValue::Map(BTreeMap::from([("a".to_string(), inner)]))
+		);
+	}
+}
@@ -10,7 +10,11 @@ use crate::{
 	},
 	ty::{id::Id, identifier::Identifier},
 	util::blake3::Hasher,
-	world::chunk::block::{BlockDesc, BlockPrototype},
+	world::{
+		biome::BiomeDesc,
+		chunk::block::{Block, BlockDesc, BlockPrototype},
+		entity::prototype::EntityDesc,
+	},
 };
 use apollo::impl_macro::*;
 
@@ -26,6 +30,8 @@ impl BlockLayer {
 	pub fn get_blocks(&mut self) -> LuaResult<&mut Registry<BlockDesc>> {
 		Ok(&mut self.blocks)
 	}
+
+	pub fn create_default(&self) -> Block { self.blocks.get(self.default).create(self.default) }
 }
 
 pub struct BlockLayerPrototype {
@@ -35,7 +41,11 @@ pub struct BlockLayerPrototype {
 }
 
 impl BlockLayerPrototype {
-	pub fn bake(self) -> eyre::Result<BlockLayer> {
+	pub fn bake(
+		self,
+		entities: &Registry<EntityDesc>,
+		biomes: &Registry<BiomeDesc>,
+	) -> eyre::Result<BlockLayer> {
 		let lookup = self
 			.blocks
 			.ident_to_id
@@ -46,7 +56,7 @@ impl BlockLayerPrototype {
 		let mut out = Vec::new();
 		for (id, ident, entry) in self.blocks.into_entries() {
 			let prototype = entry
-				.bake(&lookup)
+				.bake(&lookup, self.collision, entities, biomes)
 				.wrap_err_with(|| format!("Failed to bake block {}", ident))?;
 			out.push((id.build(), ident, prototype));
 		}
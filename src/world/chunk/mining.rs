@@ -0,0 +1,157 @@
+//! How blocks get mined.
+//!
+//! `hardness` is how many seconds a block takes to mine by hand, blocks without collision
+//! break instantly and `math.huge` makes a block unbreakable. A block may ask for a tool, mining it
+//! without one is a lot slower and drops nothing.
+//! ```lua
+//! ["stone"] = {
+//!     collision = true,
+//!     hardness = 1.5,
+//!     tool = { kind = "pickaxe", level = 1 },
+//!     drops = {
+//!         ["stone_item"] = { chance = 1.0, min = 1, max = 1 },
+//!     }
+//! }
+//! ```
+//! Drops are entities which get spawned in the middle of the block.
+use std::collections::HashMap;
+
+use apollo::{FromLua, Lua, Value};
+use eyre::ContextCompat;
+
+use crate::{
+	api::{registry::Registry, util::lua_table},
+	ty::{id::Id, identifier::Identifier},
+	world::entity::prototype::EntityDesc,
+	TPS,
+};
+
+/// How much slower mining gets without the right tool.
+pub const WRONG_TOOL_PENALTY: f32 = 5.0;
+
+/// What a player mines with.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Tool {
+	pub kind: String,
+	pub level: u32,
+	/// How many times faster than a hand this tool mines blocks it is made for.
+	pub speed: f32,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct ToolRequirement {
+	pub kind: String,
+	pub level: u32,
+}
+
+impl ToolRequirement {
+	pub fn is_met(&self, tool: Option<&Tool>) -> bool {
+		tool.map_or(false, |tool| tool.kind == self.kind && tool.level >= self.level)
+	}
+}
+
+impl FromLua for ToolRequirement {
+	fn from_lua(lua_value: Value, _: &Lua) -> eyre::Result<Self> {
+		let table = lua_table(lua_value)?;
+		let level: Option<u32> = table.get("level")?;
+		Ok(ToolRequirement {
+			kind: table.get("kind")?,
+			level: level.unwrap_or(0),
+		})
+	}
+}
+
+pub struct BlockDrop {
+	pub entity: Id<EntityDesc>,
+	pub chance: f32,
+	pub min: u32,
+	pub max: u32,
+}
+
+pub struct BlockDropPrototype {
+	pub chance: f32,
+	pub min: u32,
+	pub max: u32,
+}
+
+impl FromLua for BlockDropPrototype {
+	fn from_lua(lua_value: Value, _: &Lua) -> eyre::Result<Self> {
+		let table = lua_table(lua_value)?;
+		let chance: Option<f32> = table.get("chance")?;
+		let min: Option<u32> = table.get("min")?;
+		let max: Option<u32> = table.get("max")?;
+		let min = min.unwrap_or(1);
+		Ok(BlockDropPrototype {
+			chance: chance.unwrap_or(1.0),
+			min,
+			max: max.unwrap_or(min).max(min),
+		})
+	}
+}
+
+/// Resolves the dropped entities, sorted so drops roll in the same order every run.
+pub fn bake_drops(
+	drops: HashMap<Identifier, BlockDropPrototype>,
+	entities: &Registry<EntityDesc>,
+) -> eyre::Result<Vec<BlockDrop>> {
+	let mut out = Vec::new();
+	for (identifier, drop) in drops {
+		out.push(BlockDrop {
+			entity: entities
+				.get_id(&identifier)
+				.wrap_err_with(|| format!("Could not find dropped entity {identifier}"))?,
+			chance: drop.chance,
+			min: drop.min,
+			max: drop.max,
+		});
+	}
+	out.sort_by_key(|drop| drop.entity);
+	Ok(out)
+}
+
+/// How much of the block gets mined every tick, 1.0 being the whole block.
+pub fn mining_progress(
+	hardness: f32,
+	requirement: Option<&ToolRequirement>,
+	tool: Option<&Tool>,
+) -> f32 {
+	if hardness <= 0.0 {
+		return 1.0;
+	}
+
+	let speed = match (requirement, tool) {
+		(Some(requirement), _) if !requirement.is_met(tool) => 1.0 / WRONG_TOOL_PENALTY,
+		(Some(requirement), Some(tool)) if requirement.kind == tool.kind => tool.speed,
+		_ => 1.0,
+	};
+	speed / (hardness * TPS as f32)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn pickaxe(level: u32) -> Tool {
+		Tool {
+			kind: "pickaxe".to_string(),
+			level,
+			speed: 4.0,
+		}
+	}
+
+	#[test]
+	fn tools_speed_up_mining() {
+		let requirement = ToolRequirement {
+			kind: "pickaxe".to_string(),
+			level: 1,
+		};
+		let hand = mining_progress(1.0, None, None);
+		assert_eq!(hand, 1.0 / TPS as f32);
+		assert_eq!(mining_progress(0.0, Some(&requirement), None), 1.0);
+		assert_eq!(mining_progress(1.0, None, Some(&pickaxe(1))), hand);
+		assert_eq!(mining_progress(1.0, Some(&requirement), Some(&pickaxe(1))), hand * 4.0);
+		let slow = mining_progress(1.0, Some(&requirement), Some(&pickaxe(0)));
+		assert!((slow - hand / WRONG_TOOL_PENALTY).abs() < f32::EPSILON);
+		assert_eq!(mining_progress(f32::INFINITY, None, None), 0.0);
+	}
+}
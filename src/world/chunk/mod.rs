@@ -1,83 +1,92 @@
-use std::ops::{Index, IndexMut};
-
 use block::Block;
+use block_entity::BlockEntity;
+use fxhash::FxHashMap;
 use layer::BlockLayer;
 
-use crate::{api::id_table::IdTable, ty::block_layer_pos::BlockLayerPos};
+use crate::{
+	api::id_table::IdTable,
+	ty::{block_layer_pos::BlockLayerPos, id::Id},
+	world::{biome::ChunkBiomes, light::ChunkLight, liquid::LiquidCell},
+};
 
 pub mod block;
+pub mod block_entity;
 pub mod layer;
+pub mod mining;
+pub mod neighbor_update;
+pub mod palette;
+pub mod portable;
+pub mod query;
+pub mod random_tick;
 pub mod spread;
+pub mod state;
 pub mod storage;
 
+pub use palette::ChunkLayer;
+
 pub const CHUNK_SIZE: usize = 16;
 pub const CHUNK_SIZE_F32: f32 = CHUNK_SIZE as f32;
 
 #[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub struct Chunk {
 	pub layers: IdTable<BlockLayer, ChunkLayer<Block>>,
+	pub block_entities: FxHashMap<(Id<BlockLayer>, BlockLayerPos), BlockEntity>,
+	pub liquids: ChunkLayer<LiquidCell>,
+	pub biomes: ChunkBiomes,
+	/// Calculated by the server, never saved.
+	pub light: ChunkLight,
 }
 
-// Layer
-#[derive(Clone, serde::Serialize, serde::Deserialize)]
-pub struct ChunkLayer<T: Clone> {
-	pub data: [[T; CHUNK_SIZE]; CHUNK_SIZE],
-}
-
-impl<T: Clone> ChunkLayer<T> {
-	pub fn entries(&self, mut func: impl FnMut(BlockLayerPos, &T)) {
-		for y in 0..CHUNK_SIZE {
-			for x in 0..CHUNK_SIZE {
-				func(BlockLayerPos::new(x as u8, y as u8), &self.data[y][x]);
-			}
+impl Chunk {
+	pub fn new(layers: IdTable<BlockLayer, ChunkLayer<Block>>) -> Chunk {
+		Chunk {
+			layers,
+			block_entities: Default::default(),
+			liquids: ChunkLayer::new_copy(LiquidCell::EMPTY),
+			biomes: ChunkBiomes::default(),
+			light: ChunkLight::dark(),
 		}
 	}
 
-	pub fn entries_mut(&mut self, mut func: impl FnMut(BlockLayerPos, &mut T)) {
-		for y in 0..CHUNK_SIZE {
-			for x in 0..CHUNK_SIZE {
-				func(BlockLayerPos::new(x as u8, y as u8), &mut self.data[y][x]);
+	/// Places a block and replaces whatever block entity was at that position.
+	pub fn set_block(
+		&mut self,
+		layer_id: Id<BlockLayer>,
+		entry: BlockLayerPos,
+		block: Block,
+		block_entity: Option<BlockEntity>,
+	) {
+		self.layers.get_mut(layer_id).set(entry, block);
+		match block_entity {
+			Some(block_entity) => {
+				self.block_entities.insert((layer_id, entry), block_entity);
 			}
-		}
-	}
-
-	pub fn map<O: Clone + Copy>(
-		&self,
-		default: O,
-		mut func: impl FnMut(&T) -> Option<O>,
-	) -> ChunkLayer<O> {
-		let mut out = ChunkLayer::new_copy(default);
-		for y in 0..CHUNK_SIZE {
-			for x in 0..CHUNK_SIZE {
-				if let Some(value) = func(&self.data[y][x]) {
-					out.data[y][x] = value;
-				}
+			None => {
+				self.block_entities.remove(&(layer_id, entry));
 			}
 		}
-
-		out
-	}
-}
-
-impl<T: Clone + Copy> ChunkLayer<T> {
-	pub fn new_copy(value: T) -> Self {
-		ChunkLayer {
-			data: [[value; CHUNK_SIZE]; CHUNK_SIZE],
-		}
 	}
 }
 
-impl<T: Clone> Index<BlockLayerPos> for ChunkLayer<T> {
-	type Output = T;
+/// Fixtures shared by the tests of the world.
+#[cfg(test)]
+pub(crate) mod test {
+	use super::{block::Block, layer::BlockLayer, state::BlockState, Chunk, ChunkLayer};
+	use crate::ty::id::Id;
 
-	fn index(&self, index: BlockLayerPos) -> &Self::Output {
-		&self.data[index.y() as usize][index.x() as usize]
+	/// Every block except `0`, which stands in for air, collides.
+	pub fn block(id: usize) -> Block {
+		Block {
+			id: unsafe { Id::new(id) },
+			collision: id != 0,
+			state: BlockState::DEFAULT,
+		}
 	}
-}
 
-impl<T: Clone> IndexMut<BlockLayerPos> for ChunkLayer<T> {
-	fn index_mut(&mut self, index: BlockLayerPos) -> &mut Self::Output {
-		&mut self.data[index.y() as usize][index.x() as usize]
+	/// A chunk with a single layer filled with `block(fill)`.
+	pub fn chunk(layer_id: Id<BlockLayer>, fill: usize) -> Chunk {
+		let layers = vec![(layer_id, ChunkLayer::new_copy(block(fill)))];
+		Chunk::new(layers.into_iter().collect())
 	}
 }
 
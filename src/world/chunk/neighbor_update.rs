@@ -0,0 +1,114 @@
+//! Blocks which react to the blocks next to them changing.
+//!
+//! Whenever a block gets placed, its four neighbours in the same layer get an update.
+//! A block either needs support from one side and breaks when that block stops colliding,
+//! or runs a Lua callback. Unlike random ticks there is nothing to stop, so whatever the
+//! callback returns gets ignored.
+//! ```lua
+//! ["torch"] = {
+//!     neighbor_update = { support = "down" }
+//! },
+//! ["vine"] = {
+//!     neighbor_update = { support = "up" }
+//! },
+//! ["cactus"] = {
+//!     neighbor_update = {
+//!         callback = function(world, x, y)
+//!             if world:get_block("tile", x + 1, y) ~= "rustaria:air" then
+//!                 world:set_block("tile", x, y, "air")
+//!             end
+//!         end
+//!     }
+//! }
+//! ```
+use apollo::{FromLua, Function, Lua, Value};
+use eyre::ContextCompat;
+
+use crate::{
+	api::util::lua_table,
+	ty::{block_pos::BlockPos, direction::Direction, id::Id, Offset},
+	world::chunk::{layer::BlockLayer, random_tick::TickChange, state::BlockState},
+	ChunkStorage,
+};
+
+pub enum NeighborHandler {
+	/// Breaks into the layer default once the block on this side does not collide.
+	Support(Direction),
+	Lua(Function),
+}
+
+impl NeighborHandler {
+	/// Runs a builtin handler, [`NeighborHandler::Lua`] gets handled by the update system.
+	pub fn update(
+		&self,
+		pos: BlockPos,
+		layer_id: Id<BlockLayer>,
+		layer: &BlockLayer,
+		chunks: &ChunkStorage,
+	) -> Option<TickChange> {
+		match self {
+			NeighborHandler::Support(dir) => {
+				if is_supported(pos, *dir, layer_id, layer, chunks) {
+					return None;
+				}
+
+				Some(TickChange {
+					pos,
+					layer_id,
+					block_id: layer.default,
+					state: BlockState::DEFAULT,
+				})
+			}
+			NeighborHandler::Lua(_) => None,
+		}
+	}
+}
+
+/// If the block on the `dir` side of `pos` collides.
+/// Unloaded chunks and the edge of the world count as support.
+pub fn is_supported(
+	pos: BlockPos,
+	dir: Direction,
+	layer_id: Id<BlockLayer>,
+	layer: &BlockLayer,
+	chunks: &ChunkStorage,
+) -> bool {
+	let support = match pos.checked_offset(dir.offset()) {
+		Some(support) => support,
+		None => return true,
+	};
+	match chunks.get(support.chunk) {
+		Some(chunk) => layer.blocks.get(chunk.layers.get(layer_id)[support.entry].id).collision,
+		None => true,
+	}
+}
+
+pub struct NeighborHandlerPrototype {
+	pub support: Option<String>,
+	pub callback: Option<Function>,
+}
+
+impl NeighborHandlerPrototype {
+	pub fn bake(self) -> eyre::Result<NeighborHandler> {
+		match (self.support, self.callback) {
+			(Some(support), None) => Ok(NeighborHandler::Support(
+				Direction::values()
+					.into_iter()
+					.find(|dir| format!("{dir:?}").eq_ignore_ascii_case(&support))
+					.wrap_err_with(|| format!("Unknown support side {support}"))?,
+			)),
+			(None, Some(callback)) => Ok(NeighborHandler::Lua(callback)),
+			_ => eyre::bail!("A neighbor update needs exactly one of support or callback"),
+		}
+	}
+}
+
+impl FromLua for NeighborHandlerPrototype {
+	fn from_lua(lua_value: Value, _: &Lua) -> eyre::Result<Self> {
+		let table = lua_table(lua_value)?;
+		Ok(NeighborHandlerPrototype {
+			support: table.get("support")?,
+			callback: table.get("callback")?,
+		})
+	}
+}
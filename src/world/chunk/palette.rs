@@ -0,0 +1,345 @@
+//! Paletted chunk layers.
+//!
+//! Most layers only hold a handful of different blocks, and a lot of them are just air.
+//! Instead of storing every value a [`ChunkLayer`] stores each distinct value once in a palette
+//! and bit-packs the indices into that palette. A layer holding a single value stores no indices at all.
+use std::ops::Index;
+
+use eyre::{bail, Report, Result};
+
+use crate::{ty::block_layer_pos::BlockLayerPos, world::chunk::CHUNK_SIZE};
+
+const CHUNK_AREA: usize = CHUNK_SIZE * CHUNK_SIZE;
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "RawChunkLayer<T>")]
+pub struct ChunkLayer<T> {
+	palette: Vec<T>,
+	indices: PackedIndices,
+}
+
+/// A [`ChunkLayer`] as it comes off the wire, before its indices are checked against the palette.
+#[derive(serde::Deserialize)]
+struct RawChunkLayer<T> {
+	palette: Vec<T>,
+	indices: PackedIndices,
+}
+
+impl<T> TryFrom<RawChunkLayer<T>> for ChunkLayer<T> {
+	type Error = Report;
+
+	fn try_from(raw: RawChunkLayer<T>) -> Result<Self> {
+		let RawChunkLayer { palette, indices } = raw;
+		if palette.is_empty() {
+			bail!("Chunk layer has an empty palette");
+		}
+		if palette.len() > indices.capacity() {
+			bail!(
+				"Chunk layer palette holds {} values but its indices only address {}",
+				palette.len(),
+				indices.capacity()
+			);
+		}
+		if let Some(index) = (0..CHUNK_AREA)
+			.map(|slot| indices.get(slot))
+			.find(|index| *index >= palette.len())
+		{
+			bail!("Chunk layer index {index} is outside of its palette of {}", palette.len());
+		}
+
+		Ok(ChunkLayer { palette, indices })
+	}
+}
+
+impl<T: Clone + PartialEq> ChunkLayer<T> {
+	pub fn new_copy(value: T) -> Self {
+		ChunkLayer {
+			palette: vec![value],
+			indices: PackedIndices::new(0),
+		}
+	}
+
+	pub fn from_fn(mut func: impl FnMut(BlockLayerPos) -> T) -> Self {
+		let mut palette: Vec<T> = Vec::new();
+		let mut indices = [0usize; CHUNK_AREA];
+		for_each_pos(|slot, pos| {
+			let value = func(pos);
+			indices[slot] = match palette.iter().position(|entry| *entry == value) {
+				Some(index) => index,
+				None => {
+					palette.push(value);
+					palette.len() - 1
+				}
+			};
+		});
+
+		let mut packed = PackedIndices::new(PackedIndices::bits_for(palette.len()));
+		for (slot, index) in indices.into_iter().enumerate() {
+			packed.set(slot, index);
+		}
+
+		ChunkLayer {
+			palette,
+			indices: packed,
+		}
+	}
+
+	pub fn get(&self, pos: BlockLayerPos) -> &T { &self.palette[self.indices.get(slot(pos))] }
+
+	pub fn set(&mut self, pos: BlockLayerPos, value: T) {
+		let index = match self.palette.iter().position(|entry| *entry == value) {
+			Some(index) => index,
+			None => {
+				if self.palette.len() >= self.indices.capacity() {
+					self.compact();
+				}
+				if self.palette.len() >= self.indices.capacity() {
+					self.indices = self
+						.indices
+						.resize(PackedIndices::bits_for(self.palette.len() + 1));
+				}
+				self.palette.push(value);
+				self.palette.len() - 1
+			}
+		};
+
+		self.indices.set(slot(pos), index);
+	}
+
+	/// The distinct values in this layer. This may contain values which are no longer used.
+	pub fn palette(&self) -> &[T] { &self.palette }
+
+	/// If every position holds the same value.
+	pub fn is_uniform(&self) -> bool { self.indices.bits == 0 }
+
+	pub fn entries(&self, mut func: impl FnMut(BlockLayerPos, &T)) {
+		for_each_pos(|slot, pos| func(pos, &self.palette[self.indices.get(slot)]));
+	}
+
+	pub fn entries_mut(&mut self, mut func: impl FnMut(BlockLayerPos, &mut T)) {
+		for_each_pos(|slot, pos| {
+			let old = &self.palette[self.indices.get(slot)];
+			let mut value = old.clone();
+			func(pos, &mut value);
+			if value != *old {
+				self.set(pos, value);
+			}
+		});
+	}
+
+	/// Maps every distinct value instead of every position, so `func` runs at most once per palette entry.
+	pub fn map<O: Clone + PartialEq>(
+		&self,
+		default: O,
+		mut func: impl FnMut(&T) -> Option<O>,
+	) -> ChunkLayer<O> {
+		let used = self.used();
+		ChunkLayer {
+			palette: self
+				.palette
+				.iter()
+				.zip(used)
+				.map(|(value, used)| {
+					if used {
+						func(value).unwrap_or_else(|| default.clone())
+					} else {
+						default.clone()
+					}
+				})
+				.collect(),
+			indices: self.indices.clone(),
+		}
+	}
+
+	/// Drops unused palette entries and shrinks the indices to the smallest size that fits.
+	pub fn compact(&mut self) {
+		let mut remap = vec![None; self.palette.len()];
+		let mut palette = Vec::new();
+		for (index, used) in self.used().into_iter().enumerate() {
+			if used {
+				remap[index] = Some(palette.len());
+				palette.push(self.palette[index].clone());
+			}
+		}
+
+		let mut indices = PackedIndices::new(PackedIndices::bits_for(palette.len()));
+		for slot in 0..CHUNK_AREA {
+			if let Some(index) = remap[self.indices.get(slot)] {
+				indices.set(slot, index);
+			}
+		}
+
+		self.palette = palette;
+		self.indices = indices;
+	}
+
+	fn used(&self) -> Vec<bool> {
+		let mut used = vec![false; self.palette.len()];
+		for slot in 0..CHUNK_AREA {
+			used[self.indices.get(slot)] = true;
+		}
+		used
+	}
+}
+
+impl<T: Clone + PartialEq> Index<BlockLayerPos> for ChunkLayer<T> {
+	type Output = T;
+
+	fn index(&self, index: BlockLayerPos) -> &Self::Output { self.get(index) }
+}
+
+fn slot(pos: BlockLayerPos) -> usize { pos.y() as usize * CHUNK_SIZE + pos.x() as usize }
+
+fn for_each_pos(mut func: impl FnMut(usize, BlockLayerPos)) {
+	for y in 0..CHUNK_SIZE {
+		for x in 0..CHUNK_SIZE {
+			func(y * CHUNK_SIZE + x, BlockLayerPos::new(x as u8, y as u8));
+		}
+	}
+}
+
+/// Indices packed into words, entries never cross a word boundary.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "RawPackedIndices")]
+struct PackedIndices {
+	/// Bits per index, 0 means every index is 0.
+	bits: u8,
+	words: Vec<u64>,
+}
+
+#[derive(serde::Deserialize)]
+struct RawPackedIndices {
+	bits: u8,
+	words: Vec<u64>,
+}
+
+impl TryFrom<RawPackedIndices> for PackedIndices {
+	type Error = Report;
+
+	fn try_from(raw: RawPackedIndices) -> Result<Self> {
+		if !matches!(raw.bits, 0 | 1 | 2 | 4 | 8 | 16) {
+			bail!("Packed indices can not be {} bits wide", raw.bits);
+		}
+		let expected = CHUNK_AREA * raw.bits as usize / 64;
+		if raw.words.len() != expected {
+			bail!(
+				"Packed indices of {} bits need {expected} words but have {}",
+				raw.bits,
+				raw.words.len()
+			);
+		}
+
+		Ok(PackedIndices {
+			bits: raw.bits,
+			words: raw.words,
+		})
+	}
+}
+
+impl PackedIndices {
+	fn new(bits: u8) -> PackedIndices {
+		PackedIndices {
+			bits,
+			words: vec![0; CHUNK_AREA * bits as usize / 64],
+		}
+	}
+
+	fn bits_for(len: usize) -> u8 {
+		match len {
+			0..=1 => 0,
+			2 => 1,
+			3..=4 => 2,
+			5..=16 => 4,
+			17..=256 => 8,
+			_ => 16,
+		}
+	}
+
+	fn capacity(&self) -> usize { 1 << self.bits }
+
+	fn get(&self, slot: usize) -> usize {
+		if self.bits == 0 {
+			return 0;
+		}
+
+		let per_word = 64 / self.bits as usize;
+		let word = self.words[slot / per_word];
+		let shift = (slot % per_word) * self.bits as usize;
+		((word >> shift) & ((1 << self.bits) - 1)) as usize
+	}
+
+	fn set(&mut self, slot: usize, index: usize) {
+		if self.bits == 0 {
+			debug_assert_eq!(index, 0);
+			return;
+		}
+
+		let per_word = 64 / self.bits as usize;
+		let shift = (slot % per_word) * self.bits as usize;
+		let mask = ((1u64 << self.bits) - 1) << shift;
+		let word = &mut self.words[slot / per_word];
+		*word = (*word & !mask) | ((index as u64) << shift);
+	}
+
+	fn resize(&self, bits: u8) -> PackedIndices {
+		let mut out = PackedIndices::new(bits);
+		for slot in 0..CHUNK_AREA {
+			out.set(slot, self.get(slot));
+		}
+		out
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn uniform_until_set() {
+		let mut layer = ChunkLayer::new_copy(0u16);
+		assert!(layer.is_uniform());
+
+		let pos = BlockLayerPos::new(3, 7);
+		layer.set(pos, 5);
+		assert!(!layer.is_uniform());
+		assert_eq!(layer[pos], 5);
+		assert_eq!(layer[BlockLayerPos::new(7, 3)], 0);
+	}
+
+	#[test]
+	fn grows_and_compacts() {
+		let mut layer = ChunkLayer::new_copy(0u16);
+		layer.entries_mut(|pos, value| *value = pos.x() as u16 * 16 + pos.y() as u16);
+		layer.entries(|pos, value| assert_eq!(*value, pos.x() as u16 * 16 + pos.y() as u16));
+		assert_eq!(layer.palette().len(), 256);
+
+		layer.entries_mut(|_, value| *value %= 3);
+		layer.compact();
+		assert_eq!(layer.palette().len(), 3);
+		layer.entries(|pos, value| assert_eq!(*value, (pos.x() as u16 * 16 + pos.y() as u16) % 3));
+	}
+
+	#[test]
+	fn rejects_broken_layers() {
+		fn read(layer: &ChunkLayer<u16>) -> bincode::Result<ChunkLayer<u16>> {
+			bincode::deserialize(&bincode::serialize(layer).unwrap())
+		}
+
+		let mut layer = ChunkLayer::new_copy(0u16);
+		layer.set(BlockLayerPos::new(1, 2), 5);
+		assert_eq!(read(&layer).unwrap()[BlockLayerPos::new(1, 2)], 5);
+
+		// Index 1 with only one palette entry.
+		let mut short = layer.clone();
+		short.palette.pop();
+		assert!(read(&short).is_err());
+
+		let mut words = layer.clone();
+		words.indices.words.pop();
+		assert!(read(&words).is_err());
+
+		let mut bits = layer;
+		bits.indices.bits = 3;
+		assert!(read(&bits).is_err());
+	}
+}
@@ -0,0 +1,302 @@
+//! Chunks which survive registry changes.
+//!
+//! A [`Chunk`] stores raw [`Id`]s which are only valid for the registries they were created with.
+//! Anything that outlives the current game instance stores a [`PortableChunk`] instead, which carries
+//! an [`Identifier`] palette that gets remapped onto the current registries when loading.
+//! Block states are stored by property name, so adding or reordering properties keeps saved values.
+use fxhash::FxHashMap;
+use tracing::warn;
+
+use crate::{
+	api::{id_table::IdTable, Api},
+	ty::{block_layer_pos::BlockLayerPos, id::Id, identifier::Identifier},
+	world::{
+		biome::ChunkBiomes,
+		chunk::{
+			block::{Block, BlockDesc},
+			block_entity::BlockEntity,
+			layer::BlockLayer,
+			state::{BlockState, PropertyValue},
+			Chunk, ChunkLayer,
+		},
+		light::ChunkLight,
+		liquid::{LiquidCell, LiquidDesc},
+	},
+	ChunkPos,
+};
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct PortableChunk {
+	pub layers: Vec<PortableLayer>,
+	pub liquids: PortableLiquids,
+	/// The biome of every column.
+	pub biomes: Vec<Option<Identifier>>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct PortableLayer {
+	pub layer: Identifier,
+	pub palette: Vec<PortableBlock>,
+	/// Indexes into the palette.
+	pub blocks: ChunkLayer<u16>,
+	pub block_entities: Vec<(BlockLayerPos, BlockEntity)>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct PortableBlock {
+	pub block: Identifier,
+	pub properties: Vec<(String, PropertyValue)>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct PortableLiquids {
+	pub palette: Vec<Identifier>,
+	/// Indexes into the palette and the amount.
+	pub cells: ChunkLayer<Option<(u16, u8)>>,
+}
+
+impl PortableChunk {
+	pub fn new(api: &Api, chunk: &Chunk) -> PortableChunk {
+		PortableChunk {
+			layers: chunk
+				.layers
+				.iter()
+				.map(|(layer_id, layer)| {
+					let mut layer = PortableLayer::new(api, layer_id, layer);
+					layer.block_entities = chunk
+						.block_entities
+						.iter()
+						.filter(|((id, _), _)| *id == layer_id)
+						.map(|((_, entry), block_entity)| (*entry, block_entity.clone()))
+						.collect();
+					layer.block_entities.sort_by_key(|(entry, _)| *entry);
+					layer
+				})
+				.collect(),
+			liquids: PortableLiquids::new(api, &chunk.liquids),
+			biomes: chunk
+				.biomes
+				.columns()
+				.iter()
+				.map(|biome| biome.map(|biome| api.carrier.biome.get_identifier(biome).clone()))
+				.collect(),
+		}
+	}
+
+	/// Remaps the palette onto the current registries.
+	/// Layers which no longer exist are dropped and missing layers get filled with their default block.
+	/// Block entities are kept as long as their block still declares one.
+	pub fn bake(self, api: &Api, pos: ChunkPos) -> Chunk {
+		let mut saved: FxHashMap<Identifier, PortableLayer> = self
+			.layers
+			.into_iter()
+			.map(|layer| (layer.layer.clone(), layer))
+			.collect();
+
+		let mut saved_entities = FxHashMap::default();
+		let layers: IdTable<BlockLayer, ChunkLayer<Block>> = api
+			.carrier
+			.block_layer
+			.entries()
+			.map(|(layer_id, identifier, prototype)| {
+				let layer = match saved.remove(identifier) {
+					Some(mut layer) => {
+						saved_entities.insert(layer_id, std::mem::take(&mut layer.block_entities));
+						layer.bake(prototype, pos)
+					}
+					None => {
+						warn!("Chunk {pos:?} is missing layer {identifier}, filling with default");
+						ChunkLayer::new_copy(prototype.create_default())
+					}
+				};
+				(layer_id, layer)
+			})
+			.collect();
+
+		for identifier in saved.keys() {
+			warn!("Chunk {pos:?} has unknown layer {identifier}, dropping it");
+		}
+
+		let mut block_entities = FxHashMap::default();
+		for (layer_id, layer) in layers.iter() {
+			let prototype = api.carrier.block_layer.get(layer_id);
+			let mut saved: FxHashMap<BlockLayerPos, BlockEntity> = saved_entities
+				.remove(&layer_id)
+				.unwrap_or_default()
+				.into_iter()
+				.collect();
+
+			layer.entries(|entry, block| {
+				if let Some(desc) = &prototype.blocks.get(block.id).block_entity {
+					let block_entity = saved.remove(&entry).unwrap_or_else(|| desc.create());
+					block_entities.insert((layer_id, entry), block_entity);
+				}
+			});
+
+			if !saved.is_empty() {
+				warn!(
+					"Dropping {} block entities in layer {} of chunk {pos:?} as their blocks no longer have one",
+					saved.len(),
+					api.carrier.block_layer.get_identifier(layer_id)
+				);
+			}
+		}
+
+		let mut biomes = ChunkBiomes::default();
+		for (x, biome) in self.biomes.iter().enumerate() {
+			let id = biome.as_ref().and_then(|biome| {
+				let id = api.carrier.biome.get_id(biome);
+				if id.is_none() {
+					warn!("Biome {biome} in chunk {pos:?} no longer exists, removing it");
+				}
+				id
+			});
+			biomes.set(x as u8, id);
+		}
+
+		Chunk {
+			layers,
+			block_entities,
+			liquids: self.liquids.bake(api, pos),
+			biomes,
+			light: ChunkLight::dark(),
+		}
+	}
+}
+
+impl PortableLiquids {
+	pub fn new(api: &Api, liquids: &ChunkLayer<LiquidCell>) -> PortableLiquids {
+		let mut palette = Vec::new();
+		let mut lookup: FxHashMap<Id<LiquidDesc>, u16> = FxHashMap::default();
+		let mut cells = liquids.map(None, |cell| {
+			let liquid = cell.liquid?;
+			let index = *lookup.entry(liquid).or_insert_with(|| {
+				palette.push(api.carrier.liquid.get_identifier(liquid).clone());
+				(palette.len() - 1) as u16
+			});
+			Some(Some((index, cell.amount)))
+		});
+		cells.compact();
+
+		PortableLiquids { palette, cells }
+	}
+
+	pub fn bake(self, api: &Api, pos: ChunkPos) -> ChunkLayer<LiquidCell> {
+		let palette: Vec<Option<Id<LiquidDesc>>> = self
+			.palette
+			.iter()
+			.map(|identifier| {
+				let id = api.carrier.liquid.get_id(identifier);
+				if id.is_none() {
+					warn!("Liquid {identifier} in chunk {pos:?} no longer exists, removing it");
+				}
+				id
+			})
+			.collect();
+
+		self.cells.map(LiquidCell::EMPTY, |cell| {
+			let (index, amount) = (*cell)?;
+			let liquid = (*palette.get(index as usize)?)?;
+			Some(LiquidCell::new(liquid, amount))
+		})
+	}
+}
+
+impl PortableBlock {
+	pub fn new(layer: &BlockLayer, block: Block) -> PortableBlock {
+		PortableBlock {
+			block: layer.blocks.get_identifier(block.id).clone(),
+			properties: layer
+				.blocks
+				.get(block.id)
+				.properties
+				.values(block.state)
+				.map(|(name, value)| (name.to_string(), value))
+				.collect(),
+		}
+	}
+
+	/// The block in the current registry, [`None`] if it no longer exists.
+	/// Properties which are no longer valid keep their default and get passed to `invalid`.
+	pub fn bake(&self, layer: &BlockLayer, mut invalid: impl FnMut(eyre::Report)) -> Option<Block> {
+		let id = layer.blocks.get_id(&self.block)?;
+		let desc = layer.blocks.get(id);
+		let mut state = BlockState::DEFAULT;
+		for (name, value) in &self.properties {
+			match desc.properties.with(state, name, value) {
+				Ok(new) => state = new,
+				Err(err) => invalid(err),
+			}
+		}
+		Some(desc.create_with_state(id, state))
+	}
+}
+
+impl PortableLayer {
+	pub fn new(api: &Api, layer_id: Id<BlockLayer>, layer: &ChunkLayer<Block>) -> PortableLayer {
+		let prototype = api.carrier.block_layer.get(layer_id);
+		let mut palette = Vec::new();
+		let mut lookup: FxHashMap<(Id<BlockDesc>, BlockState), u16> = FxHashMap::default();
+		let mut blocks = layer.map(0, |block| {
+			Some(*lookup.entry((block.id, block.state)).or_insert_with(|| {
+				palette.push(PortableBlock::new(prototype, *block));
+				(palette.len() - 1) as u16
+			}))
+		});
+		blocks.compact();
+
+		PortableLayer {
+			layer: api.carrier.block_layer.get_identifier(layer_id).clone(),
+			palette,
+			blocks,
+			block_entities: Vec::new(),
+		}
+	}
+
+	/// Blocks in the palette which do not exist in the layer anymore.
+	pub fn unknown_blocks<'a>(
+		&'a self,
+		layer: &'a BlockLayer,
+	) -> impl Iterator<Item = &'a Identifier> {
+		self.palette
+			.iter()
+			.map(|entry| &entry.block)
+			.filter(|identifier| layer.blocks.get_id(identifier).is_none())
+	}
+
+	pub fn bake(self, layer: &BlockLayer, pos: ChunkPos) -> ChunkLayer<Block> {
+		let default = layer.create_default();
+		let mut counts = vec![0u32; self.palette.len()];
+		self.blocks.entries(|_, index| {
+			if let Some(count) = counts.get_mut(*index as usize) {
+				*count += 1;
+			}
+		});
+
+		let palette: Vec<Block> = self
+			.palette
+			.iter()
+			.zip(counts)
+			.map(|(entry, count)| {
+				let block = entry.bake(layer, |err| {
+					warn!(
+						"Block {} in layer {} of chunk {pos:?} has an invalid property, using the default: {err}",
+						entry.block,
+						self.layer
+					)
+				});
+				block.unwrap_or_else(|| {
+					warn!(
+						"Block {} in layer {} of chunk {pos:?} no longer exists, replacing {count} blocks with {}",
+						entry.block,
+						self.layer,
+						layer.blocks.get_identifier(layer.default)
+					);
+					default
+				})
+			})
+			.collect();
+
+		self.blocks.map(default, |index| palette.get(*index as usize).copied())
+	}
+}
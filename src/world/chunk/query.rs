@@ -0,0 +1,215 @@
+//! Looking up blocks in an area without walking the chunks by hand.
+//!
+//! An area gets split into the chunks it overlaps, which get visited one after another, so the
+//! blocks come out chunk by chunk and row by row within a chunk. Unloaded chunks get skipped.
+use euclid::{rect, Rect};
+use fxhash::FxHashMap;
+
+use crate::{
+	ty::{block_layer_pos::BlockLayerPos, block_pos::BlockPos, id::Id, WS},
+	world::chunk::{
+		block::{Block, BlockDesc},
+		layer::BlockLayer,
+		CHUNK_SIZE,
+	},
+	Chunk, ChunkPos, ChunkStorage,
+};
+
+/// The largest area Lua can query at once.
+pub const MAX_QUERY_BLOCKS: u64 = 65536;
+
+/// A rectangle of blocks, both corners are inclusive.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+pub struct BlockArea {
+	pub min_x: i64,
+	pub min_y: i64,
+	pub max_x: i64,
+	pub max_y: i64,
+}
+
+impl BlockArea {
+	/// The area between two corners in any order.
+	pub fn new((x0, y0): (i64, i64), (x1, y1): (i64, i64)) -> BlockArea {
+		BlockArea {
+			min_x: x0.min(x1),
+			min_y: y0.min(y1),
+			max_x: x0.max(x1),
+			max_y: y0.max(y1),
+		}
+	}
+
+	pub fn between(from: BlockPos, to: BlockPos) -> BlockArea {
+		BlockArea::new((from.x(), from.y()), (to.x(), to.y()))
+	}
+
+	/// Every block the rectangle overlaps.
+	pub fn from_rect(rect: Rect<f32, WS>) -> BlockArea {
+		let min_x = rect.min_x().floor() as i64;
+		let min_y = rect.min_y().floor() as i64;
+		BlockArea {
+			min_x,
+			min_y,
+			max_x: (rect.max_x().ceil() as i64 - 1).max(min_x),
+			max_y: (rect.max_y().ceil() as i64 - 1).max(min_y),
+		}
+	}
+
+	pub fn to_rect(self) -> Rect<f32, WS> {
+		rect(
+			self.min_x as f32,
+			self.min_y as f32,
+			(self.max_x - self.min_x + 1) as f32,
+			(self.max_y - self.min_y + 1) as f32,
+		)
+	}
+
+	pub fn width(&self) -> u64 { Self::span(self.min_x, self.max_x) }
+
+	pub fn height(&self) -> u64 { Self::span(self.min_y, self.max_y) }
+
+	pub fn blocks(&self) -> u64 { self.width().saturating_mul(self.height()) }
+
+	pub fn contains(&self, pos: BlockPos) -> bool {
+		(self.min_x..=self.max_x).contains(&pos.x()) && (self.min_y..=self.max_y).contains(&pos.y())
+	}
+
+	/// Every chunk the area overlaps, loaded or not.
+	pub fn chunks(self) -> impl Iterator<Item = ChunkPos> {
+		let size = CHUNK_SIZE as i64;
+		let (min_x, max_x) = (self.min_x.div_euclid(size), self.max_x.div_euclid(size));
+		let (min_y, max_y) = (self.min_y.div_euclid(size), self.max_y.div_euclid(size));
+		(min_y..=max_y).flat_map(move |y| {
+			(min_x..=max_x).filter_map(move |x| ChunkPos::try_from((x, y)).ok())
+		})
+	}
+
+	/// Blocks from `min` to `max`, saturating instead of overflowing for far apart corners.
+	fn span(min: i64, max: i64) -> u64 {
+		(max as i128 - min as i128 + 1).clamp(0, u64::MAX as i128) as u64
+	}
+
+	/// The part of the area inside a chunk, as inclusive chunk local corners.
+	fn local(&self, pos: ChunkPos) -> ((u8, u8), (u8, u8)) {
+		let size = CHUNK_SIZE as i64;
+		let (origin_x, origin_y) = (pos.x as i64 * size, pos.y as i64 * size);
+		let clamp = |value: i64, origin: i64| (value - origin).clamp(0, size - 1) as u8;
+		(
+			(clamp(self.min_x, origin_x), clamp(self.min_y, origin_y)),
+			(clamp(self.max_x, origin_x), clamp(self.max_y, origin_y)),
+		)
+	}
+}
+
+impl ChunkStorage {
+	/// Every loaded chunk the area overlaps.
+	pub fn chunks_in(&self, area: BlockArea) -> impl Iterator<Item = (ChunkPos, &Chunk)> + '_ {
+		area.chunks().filter_map(|pos| Some((pos, self.get(pos)?)))
+	}
+
+	/// Every loaded block of the layer inside the area.
+	pub fn blocks_in(
+		&self,
+		area: BlockArea,
+		layer_id: Id<BlockLayer>,
+	) -> impl Iterator<Item = (BlockPos, Block)> + '_ {
+		self.chunks_in(area).flat_map(move |(pos, chunk)| {
+			let layer = chunk.layers.get(layer_id);
+			let ((min_x, min_y), (max_x, max_y)) = area.local(pos);
+			(min_y..=max_y).flat_map(move |y| {
+				(min_x..=max_x).map(move |x| {
+					let entry = BlockLayerPos::new(x, y);
+					(BlockPos::new(pos, entry), layer[entry])
+				})
+			})
+		})
+	}
+
+	/// The positions of every block in the area which matches.
+	pub fn find_blocks(
+		&self,
+		area: BlockArea,
+		layer_id: Id<BlockLayer>,
+		mut predicate: impl FnMut(BlockPos, Block) -> bool,
+	) -> Vec<BlockPos> {
+		self.blocks_in(area, layer_id)
+			.filter(|(pos, block)| predicate(*pos, *block))
+			.map(|(pos, _)| pos)
+			.collect()
+	}
+
+	/// The positions of every `block_id` in the area.
+	pub fn find_block_id(
+		&self,
+		area: BlockArea,
+		layer_id: Id<BlockLayer>,
+		block_id: Id<BlockDesc>,
+	) -> Vec<BlockPos> {
+		self.find_blocks(area, layer_id, |_, block| block.id == block_id)
+	}
+
+	/// How many of every block there are in the area.
+	pub fn count_blocks(
+		&self,
+		area: BlockArea,
+		layer_id: Id<BlockLayer>,
+	) -> FxHashMap<Id<BlockDesc>, u32> {
+		let mut counts = FxHashMap::default();
+		for (_, block) in self.blocks_in(area, layer_id) {
+			*counts.entry(block.id).or_insert(0) += 1;
+		}
+		counts
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::world::chunk::test::{block, chunk};
+
+	#[test]
+	fn areas_cover_the_right_chunks() {
+		let area = BlockArea::from_rect(rect(-0.5, 3.0, 17.0, 1.0));
+		assert_eq!(area, BlockArea::new((-1, 3), (16, 3)));
+		let chunks: Vec<_> = area.chunks().collect();
+		assert_eq!(
+			chunks,
+			vec![
+				ChunkPos { x: -1, y: 0 },
+				ChunkPos { x: 0, y: 0 },
+				ChunkPos { x: 1, y: 0 }
+			]
+		);
+		assert_eq!(area.local(ChunkPos { x: 0, y: 0 }), ((0, 3), (15, 3)));
+		assert_eq!(area.local(ChunkPos { x: -1, y: 0 }), ((15, 3), (15, 3)));
+
+		let huge = BlockArea::new((i64::MIN, 0), (i64::MAX, 0));
+		assert_eq!(huge.width(), u64::MAX);
+		assert_eq!(huge.blocks(), u64::MAX);
+	}
+
+	#[test]
+	fn queries_skip_unloaded_chunks() {
+		let layer_id = unsafe { Id::new(0) };
+		let mut chunks = ChunkStorage::unbounded();
+		let mut chunk = chunk(layer_id, 0);
+		for x in 2..5 {
+			chunk
+				.layers
+				.get_mut(layer_id)
+				.set(BlockLayerPos::new(x, 1), block(1));
+		}
+		chunks.insert(ChunkPos { x: 0, y: 0 }, chunk);
+
+		// Reaches into the unloaded chunk to the left.
+		let area = BlockArea::new((-4, 0), (3, 1));
+		assert_eq!(chunks.blocks_in(area, layer_id).count(), 8);
+
+		let found = chunks.find_block_id(area, layer_id, block(1).id);
+		let found: Vec<_> = found.iter().map(|pos| (pos.x(), pos.y())).collect();
+		assert_eq!(found, vec![(2, 1), (3, 1)]);
+
+		let counts = chunks.count_blocks(area, layer_id);
+		assert_eq!(counts.get(&block(0).id), Some(&6));
+		assert_eq!(counts.get(&block(1).id), Some(&2));
+	}
+}
@@ -0,0 +1,279 @@
+//! Blocks which change on their own.
+//!
+//! A block opts into random ticks by declaring how often it gets ticked and what a tick does.
+//! The `chance` is how many times per second the block is expected to tick.
+//! ```lua
+//! ["corrupt_grass"] = {
+//!     random_tick = { chance = 10.0, spread = { ["dirt"] = "corrupt_grass" } }
+//! },
+//! ["leaves"] = {
+//!     random_tick = { chance = 0.1, decay = "air" }
+//! },
+//! ["sapling"] = {
+//!     properties = { stage = { type = "int", min = 0, max = 3 } },
+//!     random_tick = { chance = 0.2, grow = "stage" }
+//! },
+//! ["grass"] = {
+//!     random_tick = {
+//!         chance = 0.5,
+//!         -- Return false to stop ticking this block until it gets placed or loaded again.
+//!         callback = function(world, x, y)
+//!             if world:get_block("tile", x, y + 1) ~= "rustaria:air" then
+//!                 world:set_block("tile", x, y, "dirt")
+//!             end
+//!         end
+//!     }
+//! }
+//! ```
+//! `biomes` scales the chance in some biomes, so corrupt grass can creep slower through a desert
+//! and not at all through a hallow:
+//! ```lua
+//! random_tick = {
+//!     chance = 10.0,
+//!     biomes = { ["desert"] = 0.25, ["hallow"] = 0.0 },
+//!     spread = { ["dirt"] = "corrupt_grass" }
+//! }
+//! ```
+use std::collections::HashMap;
+
+use apollo::{FromLua, Function, Lua, Value};
+use eyre::{ContextCompat, WrapErr};
+use fxhash::FxHashMap;
+
+use crate::{
+	api::{registry::Registry, util::lua_table},
+	ty::{block_pos::BlockPos, id::Id, identifier::Identifier},
+	world::{
+		biome::BiomeDesc,
+		chunk::{
+			block::{Block, BlockDesc},
+			layer::BlockLayer,
+			spread::{BlockSpreader, BlockSpreaderPrototype},
+			state::{BlockProperties, BlockState, PropertyKind, PropertyValue},
+		},
+	},
+	ChunkStorage,
+};
+
+pub struct RandomTick {
+	/// Expected ticks per second.
+	pub chance: f32,
+	/// Multiplies the chance in these biomes.
+	pub biome_chance: FxHashMap<Id<BiomeDesc>, f32>,
+	pub behavior: TickBehavior,
+}
+
+impl RandomTick {
+	/// The expected ticks per second in a biome.
+	pub fn chance_in(&self, biome: Option<Id<BiomeDesc>>) -> f32 {
+		let scale = biome.and_then(|biome| self.biome_chance.get(&biome).copied());
+		self.chance * scale.unwrap_or(1.0)
+	}
+}
+
+pub enum TickBehavior {
+	Spread(BlockSpreader),
+	/// Turns into another block.
+	Decay(Id<BlockDesc>),
+	/// Counts an int property up until it reaches its max.
+	Grow(String),
+	Lua(Function),
+}
+
+/// A block a tick wants placed, applied by the world after every block ticked.
+#[derive(Clone, Copy)]
+pub struct TickChange {
+	pub pos: BlockPos,
+	pub layer_id: Id<BlockLayer>,
+	pub block_id: Id<BlockDesc>,
+	pub state: BlockState,
+}
+
+pub struct TickOutcome {
+	/// If the block should keep getting ticked.
+	pub keep: bool,
+	pub change: Option<TickChange>,
+}
+
+impl TickBehavior {
+	/// Runs a builtin behavior, [`TickBehavior::Lua`] gets handled by the tick system.
+	pub fn tick(
+		&self,
+		pos: BlockPos,
+		layer_id: Id<BlockLayer>,
+		block: Block,
+		desc: &BlockDesc,
+		chunks: &ChunkStorage,
+	) -> TickOutcome {
+		match self {
+			TickBehavior::Spread(spreader) => {
+				let result = spreader.tick_spread(pos, layer_id, chunks);
+				TickOutcome {
+					keep: result.keep,
+					change: result.spread.map(|(pos, block_id)| TickChange {
+						pos,
+						layer_id,
+						block_id,
+						state: BlockState::DEFAULT,
+					}),
+				}
+			}
+			TickBehavior::Decay(block_id) => TickOutcome {
+				keep: false,
+				change: Some(TickChange {
+					pos,
+					layer_id,
+					block_id: *block_id,
+					state: BlockState::DEFAULT,
+				}),
+			},
+			TickBehavior::Grow(property) => {
+				let next = match desc.properties.get(block.state, property) {
+					Some(PropertyValue::Int(stage)) => desc
+						.properties
+						.with(block.state, property, &PropertyValue::Int(stage + 1))
+						.ok(),
+					_ => None,
+				};
+				TickOutcome {
+					keep: next.is_some(),
+					change: next.map(|state| TickChange {
+						pos,
+						layer_id,
+						block_id: block.id,
+						state,
+					}),
+				}
+			}
+			TickBehavior::Lua(_) => TickOutcome {
+				keep: true,
+				change: None,
+			},
+		}
+	}
+}
+
+pub struct RandomTickPrototype {
+	pub chance: f32,
+	pub biomes: Option<HashMap<Identifier, f32>>,
+	pub spread: Option<BlockSpreaderPrototype>,
+	pub decay: Option<Identifier>,
+	pub grow: Option<String>,
+	pub callback: Option<Function>,
+}
+
+impl RandomTickPrototype {
+	pub fn bake(
+		self,
+		blocks: &HashMap<Identifier, Id<BlockDesc>>,
+		properties: &BlockProperties,
+		biomes: &Registry<BiomeDesc>,
+	) -> eyre::Result<RandomTick> {
+		let mut biome_chance = FxHashMap::default();
+		for (biome, scale) in self.biomes.unwrap_or_default() {
+			let id = biomes
+				.get_id(&biome)
+				.wrap_err_with(|| format!("Could not find biome {biome}"))?;
+			biome_chance.insert(id, scale);
+		}
+
+		let mut behaviors = Vec::new();
+		if let Some(spread) = self.spread {
+			behaviors.push(TickBehavior::Spread(
+				spread.bake(blocks).wrap_err("Could not bake spreader")?,
+			));
+		}
+		if let Some(decay) = self.decay {
+			behaviors.push(TickBehavior::Decay(
+				*blocks
+					.get(&decay)
+					.wrap_err_with(|| format!("Could not find decay target {decay}"))?,
+			));
+		}
+		if let Some(grow) = self.grow {
+			let property = properties
+				.properties()
+				.iter()
+				.find(|property| property.name == grow)
+				.wrap_err_with(|| format!("Could not find grow property {grow}"))?;
+			if !matches!(property.kind, PropertyKind::Int { .. }) {
+				eyre::bail!("Grow property {grow} is not an int");
+			}
+			behaviors.push(TickBehavior::Grow(grow));
+		}
+		if let Some(callback) = self.callback {
+			behaviors.push(TickBehavior::Lua(callback));
+		}
+
+		if behaviors.len() != 1 {
+			eyre::bail!("A random tick needs exactly one of spread, decay, grow or callback");
+		}
+		Ok(RandomTick {
+			chance: self.chance,
+			biome_chance,
+			behavior: behaviors.remove(0),
+		})
+	}
+}
+
+impl FromLua for RandomTickPrototype {
+	fn from_lua(lua_value: Value, _: &Lua) -> eyre::Result<Self> {
+		let table = lua_table(lua_value)?;
+		Ok(RandomTickPrototype {
+			chance: table.get("chance")?,
+			biomes: table.get("biomes")?,
+			spread: table.get("spread")?,
+			decay: table.get("decay")?,
+			grow: table.get("grow")?,
+			callback: table.get("callback")?,
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::world::chunk::state::Property;
+
+	#[test]
+	fn grow_stops_at_max() {
+		let properties = BlockProperties::new(vec![Property::new(
+			"stage".to_string(),
+			PropertyKind::Int { min: 0, max: 2 },
+			PropertyValue::Int(0),
+		)
+		.unwrap()])
+		.unwrap();
+		let desc = BlockDesc {
+			collision: false,
+			random_tick: None,
+			neighbor_update: None,
+			block_entity: None,
+			properties,
+			light_emission: 0,
+			light_opacity: 0,
+			hardness: 0.0,
+			tool: None,
+			drops: vec![],
+			falls: None,
+		};
+		let id = unsafe { Id::new(0) };
+		let layer_id = unsafe { Id::new(0) };
+		let pos = BlockPos::from_block(3, 4).unwrap();
+		let chunks = ChunkStorage::unbounded();
+		let grow = TickBehavior::Grow("stage".to_string());
+
+		let mut block = desc.create(id);
+		for stage in 1..=2 {
+			let outcome = grow.tick(pos, layer_id, block, &desc, &chunks);
+			assert!(outcome.keep);
+			let change = outcome.change.unwrap();
+			block = desc.create_with_state(id, change.state);
+			assert_eq!(desc.properties.get(block.state, "stage"), Some(PropertyValue::Int(stage)));
+		}
+
+		let outcome = grow.tick(pos, layer_id, block, &desc, &chunks);
+		assert!(!outcome.keep);
+		assert!(outcome.change.is_none());
+	}
+}
@@ -2,18 +2,14 @@ use std::collections::HashMap;
 
 use eyre::ContextCompat;
 use apollo::{FromLua, Lua, Value};
-use rand::Rng;
-use rand_xoshiro::Xoroshiro64Star;
 
 use crate::{
-	api::util::lua_table,
 	ty::{block_pos::BlockPos, direction::Direction, id::Id, identifier::Identifier, Offset},
 	world::chunk::{block::BlockDesc, layer::BlockLayer},
-	ChunkStorage, TPS,
+	ChunkStorage,
 };
 
 pub struct BlockSpreader {
-	pub chance:        f32,
 	pub convert_table: HashMap<Id<BlockDesc>, Id<BlockDesc>>,
 }
 
@@ -22,37 +18,28 @@ impl BlockSpreader {
 		&self,
 		pos: BlockPos,
 		layer_id: Id<BlockLayer>,
-		chunks: &mut ChunkStorage,
-		rand: &mut Xoroshiro64Star,
+		chunks: &ChunkStorage,
 	) -> SpreadResult {
-		if (self.chance / TPS as f32) >= rand.gen_range(0.0..1.0) as f32 {
-			let mut spread = None;
-			let mut keep = false;
-			for dir in Direction::values() {
-				if let Some(pos) = pos.checked_offset(dir.offset()) {
-					if let Some(chunk) = chunks.get_mut(pos.chunk) {
-						let layer = chunk.layers.get_mut(layer_id);
-						let id = layer[pos.entry].id;
-						if let Some(next_id) = self.convert_table.get(&id) {
-							if spread.is_some() {
-								keep = true;
-								break;
-							}
-
-							spread = Some((pos, *next_id));
+		let mut spread = None;
+		let mut keep = false;
+		for dir in Direction::values() {
+			if let Some(pos) = pos.checked_offset(dir.offset()) {
+				if let Some(chunk) = chunks.get(pos.chunk) {
+					let id = chunk.layers.get(layer_id)[pos.entry].id;
+					if let Some(next_id) = self.convert_table.get(&id) {
+						if spread.is_some() {
+							keep = true;
+							break;
 						}
+
+						spread = Some((pos, *next_id));
 					}
 				}
 			}
-
-			// we could not spread in the 4 directions
-			SpreadResult { keep, spread }
-		} else {
-			SpreadResult {
-				keep:   true,
-				spread: None,
-			}
 		}
+
+		// we could not spread in the 4 directions
+		SpreadResult { keep, spread }
 	}
 }
 
@@ -61,9 +48,9 @@ pub struct SpreadResult {
 	pub spread: Option<(BlockPos, Id<BlockDesc>)>,
 }
 
+/// Which neighboring blocks get converted into what, `{ [from] = to }`.
 #[derive(Debug)]
 pub struct BlockSpreaderPrototype {
-	pub chance:        f32,
 	pub convert_table: HashMap<Identifier, Identifier>,
 }
 
@@ -81,19 +68,14 @@ impl BlockSpreaderPrototype {
 			);
 		}
 
-		Ok(BlockSpreader {
-			chance: self.chance,
-			convert_table,
-		})
+		Ok(BlockSpreader { convert_table })
 	}
 }
 
 impl FromLua for BlockSpreaderPrototype {
-	fn from_lua(lua_value: Value, _: &Lua) -> eyre::Result<Self> {
-		let table = lua_table(lua_value)?;
+	fn from_lua(lua_value: Value, lua: &Lua) -> eyre::Result<Self> {
 		Ok(BlockSpreaderPrototype {
-			chance:        table.get("chance")?,
-			convert_table: table.get("convert_table")?,
+			convert_table: FromLua::from_lua(lua_value, lua)?,
 		})
 	}
 }
@@ -0,0 +1,334 @@
+//! Typed block properties.
+//!
+//! Instead of registering a block for every variant, a block declares properties and every
+//! combination of their values becomes a [`BlockState`] carried by the [`Block`](super::block::Block).
+//! ```lua
+//! ["sapling"] = {
+//!     collision = false,
+//!     properties = {
+//!         stage = { type = "int", min = 0, max = 3 },
+//!         facing = { type = "enum", values = { "left", "right" }, default = "right" },
+//!         open = { type = "bool", default = false },
+//!     }
+//! }
+//! ```
+//! States are encoded relative to the defaults, so [`BlockState::DEFAULT`] is always the default state.
+use apollo::{FromLua, Lua, Value};
+use eyre::{ContextCompat, Result};
+
+use crate::api::util::lua_table;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct BlockState(pub u16);
+
+impl BlockState {
+	pub const DEFAULT: BlockState = BlockState(0);
+}
+
+#[derive(Clone, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+pub enum PropertyValue {
+	Bool(bool),
+	Int(i64),
+	Enum(String),
+}
+
+impl PropertyValue {
+	pub fn to_lua(&self, lua: &Lua) -> apollo::Result<Value> {
+		Ok(match self {
+			PropertyValue::Bool(value) => Value::Boolean(*value),
+			PropertyValue::Int(value) => Value::Integer(*value),
+			PropertyValue::Enum(value) => Value::String(lua.create_string(value)?),
+		})
+	}
+}
+
+impl FromLua for PropertyValue {
+	fn from_lua(lua_value: Value, _: &Lua) -> eyre::Result<Self> {
+		Ok(match lua_value {
+			Value::Boolean(value) => PropertyValue::Bool(value),
+			Value::Integer(value) => PropertyValue::Int(value),
+			Value::String(value) => PropertyValue::Enum(value.to_str()?.to_string()),
+			value => eyre::bail!("{value:?} is not a valid property value"),
+		})
+	}
+}
+
+#[derive(Clone, Debug)]
+pub enum PropertyKind {
+	Bool,
+	Int { min: i64, max: i64 },
+	Enum(Vec<String>),
+}
+
+impl PropertyKind {
+	fn len(&self) -> u32 {
+		match self {
+			PropertyKind::Bool => 2,
+			PropertyKind::Int { min, max } => {
+				(*max as i128 - *min as i128 + 1).clamp(0, u32::MAX as i128) as u32
+			}
+			PropertyKind::Enum(values) => values.len() as u32,
+		}
+	}
+
+	fn index_of(&self, value: &PropertyValue) -> Option<u32> {
+		match (self, value) {
+			(PropertyKind::Bool, PropertyValue::Bool(value)) => Some(*value as u32),
+			(PropertyKind::Int { min, max }, PropertyValue::Int(value))
+				if (*min..=*max).contains(value) =>
+			{
+				value
+					.checked_sub(*min)
+					.and_then(|index| u32::try_from(index).ok())
+			}
+			(PropertyKind::Enum(values), PropertyValue::Enum(value)) => values
+				.iter()
+				.position(|entry| entry == value)
+				.map(|index| index as u32),
+			_ => None,
+		}
+	}
+
+	fn value_at(&self, index: u32) -> PropertyValue {
+		match self {
+			PropertyKind::Bool => PropertyValue::Bool(index != 0),
+			PropertyKind::Int { min, .. } => PropertyValue::Int(min + index as i64),
+			PropertyKind::Enum(values) => PropertyValue::Enum(values[index as usize].clone()),
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct Property {
+	pub name: String,
+	pub kind: PropertyKind,
+	default: u32,
+	stride: u32,
+}
+
+impl Property {
+	pub fn new(name: String, kind: PropertyKind, default: PropertyValue) -> Result<Property> {
+		if kind.len() == 0 {
+			eyre::bail!("Property {name} has no possible values");
+		}
+		let default = kind
+			.index_of(&default)
+			.wrap_err_with(|| format!("Default {default:?} is not a valid value of {name}"))?;
+		Ok(Property {
+			name,
+			kind,
+			default,
+			stride: 1,
+		})
+	}
+
+	fn index(&self, state: BlockState) -> u32 {
+		let len = self.kind.len();
+		(state.0 as u32 / self.stride % len + self.default) % len
+	}
+}
+
+/// The properties of a single block, sorted by name so states stay stable between runs.
+#[derive(Clone, Debug, Default)]
+pub struct BlockProperties {
+	properties: Vec<Property>,
+}
+
+impl BlockProperties {
+	pub fn new(mut properties: Vec<Property>) -> Result<BlockProperties> {
+		properties.sort_by(|a, b| a.name.cmp(&b.name));
+		let mut stride = 1u32;
+		for property in &mut properties {
+			property.stride = stride;
+			stride = stride
+				.checked_mul(property.kind.len())
+				.filter(|count| *count <= u16::MAX as u32 + 1)
+				.wrap_err("Block has too many property combinations to fit in a state")?;
+		}
+		Ok(BlockProperties { properties })
+	}
+
+	pub fn is_empty(&self) -> bool { self.properties.is_empty() }
+
+	pub fn properties(&self) -> &[Property] { &self.properties }
+
+	/// How many distinct states this block has.
+	pub fn state_count(&self) -> u32 {
+		self.properties
+			.iter()
+			.map(|property| property.kind.len())
+			.product()
+	}
+
+	pub fn get(&self, state: BlockState, name: &str) -> Option<PropertyValue> {
+		let property = self.property(name)?;
+		Some(property.kind.value_at(property.index(state)))
+	}
+
+	/// Every property with its value in this state.
+	pub fn values(&self, state: BlockState) -> impl Iterator<Item = (&str, PropertyValue)> {
+		self.properties.iter().map(move |property| {
+			(
+				property.name.as_str(),
+				property.kind.value_at(property.index(state)),
+			)
+		})
+	}
+
+	/// The state with `name` changed to `value`.
+	pub fn with(&self, state: BlockState, name: &str, value: &PropertyValue) -> Result<BlockState> {
+		let property = self
+			.property(name)
+			.wrap_err_with(|| format!("Property {name} does not exist"))?;
+		let index = property
+			.kind
+			.index_of(value)
+			.wrap_err_with(|| format!("{value:?} is not a valid value of {name}"))?;
+
+		let len = property.kind.len();
+		let old = state.0 as u32 / property.stride % len;
+		let new = (index + len - property.default) % len;
+		Ok(BlockState(
+			(state.0 as u32 - old * property.stride + new * property.stride) as u16,
+		))
+	}
+
+	fn property(&self, name: &str) -> Option<&Property> {
+		self.properties.iter().find(|property| property.name == name)
+	}
+}
+
+impl FromLua for BlockProperties {
+	fn from_lua(lua_value: Value, _: &Lua) -> eyre::Result<Self> {
+		let mut properties = Vec::new();
+		for pair in lua_table(lua_value)?.pairs::<String, Value>() {
+			let (name, value) = pair?;
+			let table = lua_table(value)?;
+			let kind_name: String = table.get("type")?;
+			let (kind, default) = match kind_name.as_str() {
+				"bool" => {
+					let default: Option<bool> = table.get("default")?;
+					(
+						PropertyKind::Bool,
+						PropertyValue::Bool(default.unwrap_or(false)),
+					)
+				}
+				"int" => {
+					let min: i64 = table.get("min")?;
+					let max: i64 = table.get("max")?;
+					if min > max {
+						eyre::bail!("Property {name} has min {min} bigger than max {max}");
+					}
+					// Every value needs its own state.
+					if !matches!(max.checked_sub(min), Some(range) if range <= u16::MAX as i64) {
+						eyre::bail!("Property {name} ranges from {min} to {max}, too many values");
+					}
+					let default: Option<i64> = table.get("default")?;
+					(
+						PropertyKind::Int { min, max },
+						PropertyValue::Int(default.unwrap_or(min)),
+					)
+				}
+				"enum" => {
+					let mut values = Vec::new();
+					let list = lua_table(table.get::<_, Value>("values")?)?;
+					for pair in list.pairs::<i64, String>() {
+						values.push(pair?);
+					}
+					values.sort_by_key(|(i, _)| *i);
+					let values: Vec<String> = values.into_iter().map(|(_, value)| value).collect();
+					let default: Option<String> = table.get("default")?;
+					let default = default
+						.or_else(|| values.first().cloned())
+						.wrap_err_with(|| format!("Property {name} has no values"))?;
+					(PropertyKind::Enum(values), PropertyValue::Enum(default))
+				}
+				kind => eyre::bail!("Unknown property type {kind} for {name}"),
+			};
+			properties.push(Property::new(name, kind, default)?);
+		}
+
+		BlockProperties::new(properties)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn properties() -> BlockProperties {
+		BlockProperties::new(vec![
+			Property::new(
+				"stage".to_string(),
+				PropertyKind::Int { min: 0, max: 3 },
+				PropertyValue::Int(1),
+			)
+			.unwrap(),
+			Property::new(
+				"facing".to_string(),
+				PropertyKind::Enum(vec!["left".to_string(), "right".to_string()]),
+				PropertyValue::Enum("right".to_string()),
+			)
+			.unwrap(),
+			Property::new("open".to_string(), PropertyKind::Bool, PropertyValue::Bool(false))
+				.unwrap(),
+		])
+		.unwrap()
+	}
+
+	#[test]
+	fn default_state_holds_defaults() {
+		let properties = properties();
+		assert_eq!(properties.state_count(), 16);
+		assert_eq!(properties.get(BlockState::DEFAULT, "stage"), Some(PropertyValue::Int(1)));
+		assert_eq!(
+			properties.get(BlockState::DEFAULT, "facing"),
+			Some(PropertyValue::Enum("right".to_string()))
+		);
+		assert_eq!(properties.get(BlockState::DEFAULT, "open"), Some(PropertyValue::Bool(false)));
+		assert_eq!(properties.get(BlockState::DEFAULT, "missing"), None);
+	}
+
+	#[test]
+	fn every_combination_is_distinct() {
+		let properties = properties();
+		let mut seen = std::collections::HashSet::new();
+		for stage in 0..=3 {
+			for facing in ["left", "right"] {
+				for open in [false, true] {
+					let mut state = BlockState::DEFAULT;
+					state = properties.with(state, "stage", &PropertyValue::Int(stage)).unwrap();
+					state = properties
+						.with(state, "facing", &PropertyValue::Enum(facing.to_string()))
+						.unwrap();
+					state = properties.with(state, "open", &PropertyValue::Bool(open)).unwrap();
+					assert!((state.0 as u32) < properties.state_count());
+					assert!(seen.insert(state));
+					assert_eq!(properties.get(state, "stage"), Some(PropertyValue::Int(stage)));
+					assert_eq!(properties.get(state, "open"), Some(PropertyValue::Bool(open)));
+				}
+			}
+		}
+
+		assert!(properties
+			.with(BlockState::DEFAULT, "stage", &PropertyValue::Int(4))
+			.is_err());
+		assert!(properties
+			.with(BlockState::DEFAULT, "open", &PropertyValue::Int(1))
+			.is_err());
+	}
+
+	#[test]
+	fn rejects_int_ranges_wider_than_a_state() {
+		let lua = Lua::new();
+		let parse = |min: i64, max: i64| {
+			let code = format!("return {{ stage = {{ type = 'int', min = {min}, max = {max} }} }}");
+			let value: Value = lua.load(&code).eval().unwrap();
+			BlockProperties::from_lua(value, &lua)
+		};
+		assert!(parse(-40_000, 40_000).is_err());
+		assert!(parse(0, u16::MAX as i64 + 1).is_err());
+		assert_eq!(parse(0, u16::MAX as i64).unwrap().state_count(), u16::MAX as u32 + 1);
+		assert_eq!(parse(-5, 5).unwrap().state_count(), 11);
+	}
+}
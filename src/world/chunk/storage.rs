@@ -1,30 +1,97 @@
-use std::collections::hash_set::Iter;
-
 use fxhash::{FxHashMap, FxHashSet};
 
-use crate::{Chunk, ChunkPos};
+use crate::{
+	ty::{block_layer_pos::BlockLayerPos, block_pos::BlockPos, direction::Direction, id::Id},
+	world::chunk::{layer::BlockLayer, CHUNK_SIZE},
+	Chunk, ChunkPos,
+};
+
+/// A rectangle of chunks, `min` is inclusive and `max` exclusive.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+pub struct ChunkBounds {
+	pub min: ChunkPos,
+	pub max: ChunkPos,
+}
+
+impl ChunkBounds {
+	/// Bounds starting at the origin.
+	pub fn new(width: u32, height: u32) -> ChunkBounds {
+		ChunkBounds {
+			min: ChunkPos { x: 0, y: 0 },
+			max: ChunkPos {
+				x: width as i32,
+				y: height as i32,
+			},
+		}
+	}
+
+	pub fn width(&self) -> u32 { (self.max.x - self.min.x) as u32 }
+
+	pub fn height(&self) -> u32 { (self.max.y - self.min.y) as u32 }
+
+	#[inline(always)]
+	pub fn contains(&self, pos: ChunkPos) -> bool {
+		pos.x >= self.min.x && pos.y >= self.min.y && pos.x < self.max.x && pos.y < self.max.y
+	}
+}
+
+/// What changed in a chunk since the changes got reset, so renderers only redo what they must.
+#[derive(Clone, Default, Debug)]
+pub struct ChunkChanges {
+	/// The chunk got inserted, removed or borrowed through [`ChunkStorage::get_mut`].
+	pub all: bool,
+	/// The blocks which changed in every layer.
+	pub blocks: FxHashMap<Id<BlockLayer>, FxHashSet<BlockLayerPos>>,
+	pub liquids: bool,
+	pub light: bool,
+}
+
+impl ChunkChanges {
+	pub fn layer_changed(&self, layer_id: Id<BlockLayer>) -> bool {
+		self.all || self.blocks.contains_key(&layer_id)
+	}
+
+	/// The layers with changed blocks on the side facing `dir`. Blocks connect to their
+	/// neighbors, so the chunk on that side has to mesh these layers again.
+	pub fn border_layers(&self, dir: Direction) -> impl Iterator<Item = Id<BlockLayer>> + '_ {
+		let edge = CHUNK_SIZE as u8 - 1;
+		let on_border = move |entry: &BlockLayerPos| match dir {
+			Direction::Up => entry.y() == edge,
+			Direction::Down => entry.y() == 0,
+			Direction::Left => entry.x() == 0,
+			Direction::Right => entry.x() == edge,
+		};
+		self.blocks
+			.iter()
+			.filter(move |(_, entries)| entries.iter().any(on_border))
+			.map(|(layer_id, _)| *layer_id)
+	}
+}
 
 #[derive(Clone)]
 pub struct ChunkStorage {
-	width: u32,
-	height: u32,
+	/// [`None`] for an infinite world.
+	bounds: Option<ChunkBounds>,
 	chunks: FxHashMap<ChunkPos, Chunk>,
-	dirty: FxHashSet<ChunkPos>,
+	changes: FxHashMap<ChunkPos, ChunkChanges>,
 }
 
 impl ChunkStorage {
 	pub fn new(width: u32, height: u32) -> ChunkStorage {
+		ChunkStorage::with_bounds(Some(ChunkBounds::new(width, height)))
+	}
+
+	pub fn unbounded() -> ChunkStorage { ChunkStorage::with_bounds(None) }
+
+	pub fn with_bounds(bounds: Option<ChunkBounds>) -> ChunkStorage {
 		ChunkStorage {
-			width,
-			height,
+			bounds,
 			chunks: Default::default(),
-			dirty: Default::default(),
+			changes: Default::default(),
 		}
 	}
 
-	pub fn width(&self) -> u32 { self.width }
-
-	pub fn height(&self) -> u32 { self.height }
+	pub fn bounds(&self) -> Option<ChunkBounds> { self.bounds }
 
 	pub fn get(&self, pos: ChunkPos) -> Option<&Chunk> {
 		if !self.check_inbounds(pos) {
@@ -42,12 +109,20 @@ impl ChunkStorage {
 		self.chunks.contains_key(&pos)
 	}
 
+	/// Marks the whole chunk as changed. Code which knows what it changes should use
+	/// [`Self::get_mut_untracked`] and mark the changes itself.
 	pub fn get_mut(&mut self, pos: ChunkPos) -> Option<&mut Chunk> {
+		let chunk = self.get_mut_untracked(pos)?;
+		self.changes.entry(pos).or_default().all = true;
+		Some(chunk)
+	}
+
+	/// Borrows the chunk without marking anything as changed.
+	pub fn get_mut_untracked(&mut self, pos: ChunkPos) -> Option<&mut Chunk> {
 		if !self.check_inbounds(pos) {
 			return None;
 		}
 
-		self.dirty.insert(pos);
 		self.chunks.get_mut(&pos)
 	}
 
@@ -56,13 +131,45 @@ impl ChunkStorage {
 			return None;
 		}
 
-		self.dirty.insert(pos);
+		self.changes.entry(pos).or_default().all = true;
 		self.chunks.insert(pos, chunk)
 	}
 
-	pub fn get_dirty(&self) -> Iter<'_, ChunkPos> { self.dirty.iter() }
+	pub fn remove(&mut self, pos: ChunkPos) -> Option<Chunk> {
+		let chunk = self.chunks.remove(&pos)?;
+		self.changes.entry(pos).or_default().all = true;
+		Some(chunk)
+	}
 
-	pub fn reset_dirty(&mut self) { self.dirty.clear(); }
+	pub fn mark_block(&mut self, pos: BlockPos, layer_id: Id<BlockLayer>) {
+		if self.contains(pos.chunk) {
+			let changes = self.changes.entry(pos.chunk).or_default();
+			changes.blocks.entry(layer_id).or_default().insert(pos.entry);
+		}
+	}
+
+	pub fn mark_liquids(&mut self, pos: ChunkPos) {
+		if self.contains(pos) {
+			self.changes.entry(pos).or_default().liquids = true;
+		}
+	}
+
+	pub fn mark_light(&mut self, pos: ChunkPos) {
+		if self.contains(pos) {
+			self.changes.entry(pos).or_default().light = true;
+		}
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = (ChunkPos, &Chunk)> {
+		self.chunks.iter().map(|(pos, chunk)| (*pos, chunk))
+	}
+
+	/// Every chunk which changed since the last [`Self::reset_dirty`], including removed ones.
+	pub fn get_dirty(&self) -> impl Iterator<Item = (ChunkPos, &ChunkChanges)> + '_ {
+		self.changes.iter().map(|(pos, changes)| (*pos, changes))
+	}
+
+	pub fn reset_dirty(&mut self) { self.changes.clear(); }
 
 	pub fn reset(&mut self) {
 		self.reset_dirty();
@@ -70,5 +177,35 @@ impl ChunkStorage {
 	}
 
 	#[inline(always)]
-	fn check_inbounds(&self, pos: ChunkPos) -> bool { pos.x < self.width && pos.y < self.height }
+	fn check_inbounds(&self, pos: ChunkPos) -> bool {
+		self.bounds.map_or(true, |bounds| bounds.contains(pos))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::world::chunk::test::chunk;
+
+	#[test]
+	fn changes_track_blocks_and_borders() {
+		let layer_id = unsafe { Id::new(0) };
+		let mut chunks = ChunkStorage::unbounded();
+		chunks.insert(ChunkPos::default(), chunk(layer_id, 0));
+		chunks.reset_dirty();
+
+		// Reading does not count as a change.
+		let _ = chunks.get_mut_untracked(ChunkPos::default());
+		assert_eq!(chunks.get_dirty().count(), 0);
+
+		chunks.mark_block(BlockPos::from_block(15, 3).unwrap(), layer_id);
+		// Not loaded, so nothing to mark.
+		chunks.mark_block(BlockPos::from_block(-1, 3).unwrap(), layer_id);
+		let (pos, changes) = chunks.get_dirty().next().unwrap();
+		assert_eq!(pos, ChunkPos::default());
+		assert!(!changes.all && changes.layer_changed(layer_id));
+		assert_eq!(changes.border_layers(Direction::Right).count(), 1);
+		assert_eq!(changes.border_layers(Direction::Left).count(), 0);
+		assert_eq!(chunks.get_dirty().count(), 1);
+	}
 }
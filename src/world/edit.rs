@@ -0,0 +1,341 @@
+//! Editing large areas in one go.
+//!
+//! An [`EditOperation`] gets planned against the loaded chunks, applied as a single batch and
+//! remembered in the history of whoever did it, so it can be undone and redone as a whole.
+//! Operations touching more than [`MAX_EDIT_BLOCKS`] blocks get refused.
+use std::collections::{BTreeMap, VecDeque};
+
+use eyre::{ContextCompat, Result};
+use fxhash::FxHashSet;
+
+use crate::{
+	network::Token,
+	ty::{block_pos::BlockPos, direction::Direction, id::Id, Offset},
+	world::chunk::{
+		block::{Block, BlockDesc},
+		layer::BlockLayer,
+	},
+	ChunkStorage,
+};
+
+pub const MAX_EDIT_BLOCKS: usize = 65536;
+/// How many batches every actor can undo.
+pub const MAX_HISTORY: usize = 32;
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub enum EditOperation {
+	/// Every block in the rectangle between two corners.
+	Fill {
+		from: BlockPos,
+		to: BlockPos,
+		layer_id: Id<BlockLayer>,
+		block_id: Id<BlockDesc>,
+	},
+	/// Like [`EditOperation::Fill`], only replacing blocks which are `matching`.
+	Replace {
+		from: BlockPos,
+		to: BlockPos,
+		layer_id: Id<BlockLayer>,
+		matching: Id<BlockDesc>,
+		block_id: Id<BlockDesc>,
+	},
+	Line {
+		from: BlockPos,
+		to: BlockPos,
+		layer_id: Id<BlockLayer>,
+		block_id: Id<BlockDesc>,
+	},
+	/// Every block connected to `start` which is the same block as it.
+	FloodFill {
+		start: BlockPos,
+		layer_id: Id<BlockLayer>,
+		block_id: Id<BlockDesc>,
+	},
+}
+
+impl EditOperation {
+	pub fn layer_id(&self) -> Id<BlockLayer> {
+		match self {
+			EditOperation::Fill { layer_id, .. }
+			| EditOperation::Replace { layer_id, .. }
+			| EditOperation::Line { layer_id, .. }
+			| EditOperation::FloodFill { layer_id, .. } => *layer_id,
+		}
+	}
+
+	pub fn block_id(&self) -> Id<BlockDesc> {
+		match self {
+			EditOperation::Fill { block_id, .. }
+			| EditOperation::Replace { block_id, .. }
+			| EditOperation::Line { block_id, .. }
+			| EditOperation::FloodFill { block_id, .. } => *block_id,
+		}
+	}
+
+	/// The loaded positions this operation changes.
+	pub fn plan(&self, chunks: &ChunkStorage) -> Result<Vec<BlockPos>> {
+		let layer_id = self.layer_id();
+		let block_at = |pos: BlockPos| {
+			chunks
+				.get(pos.chunk)
+				.map(|chunk| chunk.layers.get(layer_id)[pos.entry].id)
+		};
+
+		let positions = match self {
+			EditOperation::Fill { from, to, .. } => rect(*from, *to)?,
+			EditOperation::Replace { from, to, matching, .. } => rect(*from, *to)?
+				.into_iter()
+				.filter(|pos| block_at(*pos) == Some(*matching))
+				.collect(),
+			EditOperation::Line { from, to, .. } => line(*from, *to)?,
+			EditOperation::FloodFill { start, block_id, .. } => match block_at(*start) {
+				Some(target) if target != *block_id => {
+					flood(*start, |pos| block_at(pos) == Some(target))?
+				}
+				_ => Vec::new(),
+			},
+		};
+
+		Ok(positions
+			.into_iter()
+			.filter(|pos| chunks.contains(pos.chunk))
+			.collect())
+	}
+}
+
+fn check_size(blocks: usize) -> Result<()> {
+	if blocks > MAX_EDIT_BLOCKS {
+		eyre::bail!("Edit of {blocks} blocks is larger than the limit of {MAX_EDIT_BLOCKS}");
+	}
+	Ok(())
+}
+
+/// How many blocks there are from `min` to `max`, [`None`] if that does not fit.
+fn span(min: i64, max: i64) -> Option<i64> { max.checked_sub(min)?.checked_add(1) }
+
+fn rect(from: BlockPos, to: BlockPos) -> Result<Vec<BlockPos>> {
+	let (min_x, max_x) = (from.x().min(to.x()), from.x().max(to.x()));
+	let (min_y, max_y) = (from.y().min(to.y()), from.y().max(to.y()));
+	let blocks = span(min_x, max_x)
+		.zip(span(min_y, max_y))
+		.and_then(|(width, height)| width.checked_mul(height))
+		.wrap_err("Edit area is too large")?;
+	check_size(usize::try_from(blocks).unwrap_or(usize::MAX))?;
+
+	let mut out = Vec::new();
+	for y in min_y..=max_y {
+		for x in min_x..=max_x {
+			out.extend(BlockPos::from_block(x, y));
+		}
+	}
+	Ok(out)
+}
+
+/// Bresenham, so every block touches the previous one on a side or corner.
+fn line(from: BlockPos, to: BlockPos) -> Result<Vec<BlockPos>> {
+	let dx = to.x().checked_sub(from.x()).and_then(i64::checked_abs);
+	let dy = to.y().checked_sub(from.y()).and_then(i64::checked_abs);
+	let (dx, dy) = dx.zip(dy).wrap_err("Edit line is too long")?;
+	check_size(span(0, dx.max(dy)).map_or(usize::MAX, |blocks| blocks as usize))?;
+	let dy = -dy;
+
+	let (step_x, step_y) = ((to.x() - from.x()).signum(), (to.y() - from.y()).signum());
+	let (mut x, mut y) = (from.x(), from.y());
+	let mut error = dx + dy;
+	let mut out = Vec::new();
+	loop {
+		out.extend(BlockPos::from_block(x, y));
+		if x == to.x() && y == to.y() {
+			break;
+		}
+
+		if 2 * error >= dy {
+			error += dy;
+			x += step_x;
+		}
+		if 2 * error <= dx {
+			error += dx;
+			y += step_y;
+		}
+	}
+	Ok(out)
+}
+
+fn flood(start: BlockPos, mut matches: impl FnMut(BlockPos) -> bool) -> Result<Vec<BlockPos>> {
+	let mut visited = FxHashSet::default();
+	let mut queue = VecDeque::new();
+	visited.insert(start);
+	queue.push_back(start);
+
+	let mut out = Vec::new();
+	while let Some(pos) = queue.pop_front() {
+		out.push(pos);
+		check_size(out.len())?;
+		for dir in Direction::values() {
+			if let Some(neighbor) = pos.checked_offset(dir.offset()) {
+				if matches(neighbor) && visited.insert(neighbor) {
+					queue.push_back(neighbor);
+				}
+			}
+		}
+	}
+	Ok(out)
+}
+
+#[derive(Clone, Copy)]
+pub struct BlockEdit {
+	pub pos: BlockPos,
+	pub layer_id: Id<BlockLayer>,
+	pub before: Block,
+	pub after: Block,
+}
+
+#[derive(Default)]
+struct EditHistory {
+	undo: VecDeque<Vec<BlockEdit>>,
+	redo: Vec<Vec<BlockEdit>>,
+}
+
+/// The undo and redo history of every actor.
+#[derive(Default)]
+pub struct EditSystem {
+	histories: BTreeMap<Token, EditHistory>,
+}
+
+impl EditSystem {
+	pub fn new() -> EditSystem { EditSystem::default() }
+
+	/// Remembers a batch, which throws away everything the actor could redo.
+	pub fn record(&mut self, actor: Token, batch: Vec<BlockEdit>) {
+		if batch.is_empty() {
+			return;
+		}
+
+		let history = self.histories.entry(actor).or_default();
+		history.redo.clear();
+		history.undo.push_back(batch);
+		if history.undo.len() > MAX_HISTORY {
+			history.undo.pop_front();
+		}
+	}
+
+	/// Forgets the history of an actor which left.
+	pub fn forget(&mut self, actor: Token) { self.histories.remove(&actor); }
+
+	/// The last batch of the actor, which the world has to revert to the `before` blocks.
+	pub fn undo(&mut self, actor: Token) -> Option<&[BlockEdit]> {
+		let history = self.histories.get_mut(&actor)?;
+		let batch = history.undo.pop_back()?;
+		history.redo.push(batch);
+		history.redo.last().map(Vec::as_slice)
+	}
+
+	/// The last undone batch of the actor, which the world has to set to the `after` blocks.
+	pub fn redo(&mut self, actor: Token) -> Option<&[BlockEdit]> {
+		let history = self.histories.get_mut(&actor)?;
+		let batch = history.redo.pop()?;
+		history.undo.push_back(batch);
+		history.undo.back().map(Vec::as_slice)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		world::chunk::test::{block, chunk},
+		ChunkPos,
+	};
+
+	fn pos(x: i64, y: i64) -> BlockPos { BlockPos::from_block(x, y).unwrap() }
+
+	#[test]
+	fn operations_plan_the_right_blocks() {
+		let layer_id = unsafe { Id::new(0) };
+		let mut chunks = ChunkStorage::unbounded();
+		let mut chunk = chunk(layer_id, 0);
+		// A wall at x = 4 closes off the left side of the chunk for the flood fill.
+		for y in 0..16 {
+			chunk
+				.layers
+				.get_mut(layer_id)
+				.set(pos(4, y).entry, block(1));
+		}
+		chunks.insert(ChunkPos::default(), chunk);
+
+		let fill = EditOperation::Fill {
+			from: pos(3, 5),
+			to: pos(1, 2),
+			layer_id,
+			block_id: unsafe { Id::new(2) },
+		};
+		assert_eq!(fill.plan(&chunks).unwrap().len(), 12);
+
+		let line = EditOperation::Line {
+			from: pos(0, 0),
+			to: pos(6, 3),
+			layer_id,
+			block_id: unsafe { Id::new(2) },
+		};
+		let planned = line.plan(&chunks).unwrap();
+		assert_eq!(planned.len(), 7);
+		assert_eq!(planned.first(), Some(&pos(0, 0)));
+		assert_eq!(planned.last(), Some(&pos(6, 3)));
+
+		let flood = EditOperation::FloodFill {
+			start: pos(0, 0),
+			layer_id,
+			block_id: unsafe { Id::new(2) },
+		};
+		assert_eq!(flood.plan(&chunks).unwrap().len(), 4 * 16);
+
+		let huge = EditOperation::Fill {
+			from: pos(0, 0),
+			to: pos(1000, 1000),
+			layer_id,
+			block_id: unsafe { Id::new(2) },
+		};
+		assert!(huge.plan(&chunks).is_err());
+
+		// Far enough apart that the size does not fit into an i64.
+		let far = 1 << 34;
+		let overflowing = EditOperation::Fill {
+			from: pos(-far, -far),
+			to: pos(far, far),
+			layer_id,
+			block_id: unsafe { Id::new(2) },
+		};
+		assert!(overflowing.plan(&chunks).is_err());
+		let long = EditOperation::Line {
+			from: pos(-far, 0),
+			to: pos(far, 0),
+			layer_id,
+			block_id: unsafe { Id::new(2) },
+		};
+		assert!(long.plan(&chunks).is_err());
+	}
+
+	#[test]
+	fn undo_and_redo_move_batches() {
+		let actor = Token();
+		let edit = BlockEdit {
+			pos: pos(0, 0),
+			layer_id: unsafe { Id::new(0) },
+			before: block(0),
+			after: block(1),
+		};
+		let mut edits = EditSystem::new();
+		edits.record(actor, vec![edit]);
+		assert_eq!(edits.undo(actor).map(<[_]>::len), Some(1));
+		assert!(edits.undo(actor).is_none());
+		assert_eq!(edits.redo(actor).map(<[_]>::len), Some(1));
+		assert!(edits.redo(actor).is_none());
+
+		edits.undo(actor);
+		edits.record(actor, vec![edit]);
+		assert!(edits.redo(actor).is_none());
+
+		edits.forget(actor);
+		assert!(edits.undo(actor).is_none());
+	}
+}
@@ -7,52 +7,23 @@ use crate::{
 };
 use crate::api::util::lua_table;
 
-/// Our lovely components
-#[macro_export]
-macro_rules! iter_components {
-	($BLOCK:block) => {{
-		type T = $crate::world::entity::component::PositionComponent;
-		$BLOCK;
-	}
-	{
-		type T = $crate::world::entity::component::PhysicsComponent;
-		$BLOCK;
-	}
-	{
-		type T = $crate::world::entity::component::CollisionComponent;
-		$BLOCK;
-	}
-	{
-		type T = $crate::world::entity::component::HumanoidComponent;
-		$BLOCK;
-	}
-	{
-		type T = $crate::world::entity::component::PrototypeComponent;
-		$BLOCK;
-	}
-	{
-		type T = $crate::world::entity::component::GravityComponent;
-		$BLOCK;
-	}};
-}
-
 #[derive(Clone)]
 pub struct PrototypeComponent {
 	pub id: Id<EntityDesc>,
 }
 
-#[derive(Debug, Clone, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct GravityComponent {
 	pub amount: f32,
 }
 
-#[derive(Debug, Clone, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct PhysicsComponent {
 	pub vel: Vector2D<f32, WS>,
 	pub accel: Vector2D<f32, WS>,
 }
 
-#[derive(Debug, Clone, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(transparent)]
 pub struct PositionComponent {
 	pub pos: Vector2D<f32, WS>,
@@ -79,7 +50,7 @@ impl FromLua for CollisionComponent {
 	}
 }
 
-#[derive(Debug, Clone, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct HumanoidComponent {
 	// Settings
 	pub jump_amount: f32,
@@ -4,12 +4,13 @@ use hecs::{Component, DynamicBundle, DynamicBundleClone, Entity, EntityBuilder,
 use crate::{
 	api::Api,
 	debug::DebugRendererImpl,
-	iter_components,
 	ty::id::Id,
 	world::entity::{
 		prototype::EntityDesc,
+		registry::ComponentRegistry,
 		system::{
-			collision::CollisionSystem, humanoid::HumanoidSystem, GravitySystem, VelocitySystem,
+			collision::CollisionSystem, humanoid::HumanoidSystem, pathfinding::PathfindingSystem,
+			GravitySystem, VelocitySystem,
 		},
 	},
 	ChunkStorage,
@@ -18,23 +19,48 @@ use crate::world::entity::system::network::{EntityPacket, NetworkSystem};
 
 pub mod component;
 pub mod prototype;
+pub mod registry;
 pub mod system;
 
 pub struct EntityStorage {
 	world: hecs::World,
+	components: ComponentRegistry,
 }
 
 impl EntityStorage {
 	pub fn new() -> EntityStorage {
 		EntityStorage {
 			world: Default::default(),
+			components: ComponentRegistry::new(),
 		}
 	}
 
+	/// The components which get saved, cloned and transferred.
+	pub fn components(&self) -> &ComponentRegistry { &self.components }
+
+	pub fn components_mut(&mut self) -> &mut ComponentRegistry { &mut self.components }
+
+	/// Puts a saved component onto an existing entity, on top of what its prototype gave it.
+	pub fn restore_component(
+		&mut self,
+		api: &Api,
+		entity: Entity,
+		name: &str,
+		data: &[u8],
+	) -> Result<()> {
+		let restore = self.components.restorer(name)?;
+		restore(api, self, entity, data)
+	}
+
 	pub fn push(&mut self, api: &Api, id: Id<EntityDesc>) -> Entity {
 		self.world.spawn(&api.carrier.entity.get(id).template)
 	}
 
+	/// Spawns an entity which is not based on a prototype.
+	pub fn spawn(&mut self, components: impl DynamicBundle) -> Entity {
+		self.world.spawn(components)
+	}
+
 	pub fn insert(&mut self, api: &Api, entity: Entity, id: Id<EntityDesc>) {
 		self.world
 			.spawn_at(entity, &api.carrier.entity.get(id).template)
@@ -44,6 +70,10 @@ impl EntityStorage {
 		self.world.spawn_at(entity, components)
 	}
 
+	pub fn insert_comp(&mut self, entity: Entity, component: impl Component) {
+		let _ = self.world.insert_one(entity, component);
+	}
+
 	pub fn remove(&mut self, entity: Entity) -> Option<TakenEntity<'_>> {
 		self.world.take(entity).ok()
 	}
@@ -66,25 +96,12 @@ impl EntityStorage {
 
 	pub fn clone(&self, entity: Entity) -> Option<EntityBuilder> {
 		let entity = self.world.entity(entity).ok()?;
-		let mut builder = EntityBuilder::new();
-		iter_components!({
-			if let Some(component) = entity.get::<T>() {
-				builder.add((*component).clone());
-			}
-		});
-
-		Some(builder)
+		Some(self.components.clone(&entity))
 	}
 
 	pub fn clone_to(&self, from: Entity, to: Entity, to_storage: &mut EntityStorage) -> Option<()> {
-		let entity = self.world.entity(from).ok()?;
-		iter_components!({
-			if let Some(component) = entity.get::<T>() {
-				to_storage.world.insert_one(to,  (*component).clone()).ok()?;
-			}
-		});
-
-		Some(())
+		let mut builder = self.clone(from)?;
+		to_storage.world.insert(to, builder.build()).ok()
 	}
 }
 
@@ -94,6 +111,7 @@ pub struct EntityWorld {
 	gravity: GravitySystem,
 	collision: CollisionSystem,
 	humanoid: HumanoidSystem,
+	pathfinding: PathfindingSystem,
 	network: NetworkSystem,
 }
 
@@ -105,12 +123,14 @@ impl EntityWorld {
 			gravity: GravitySystem,
 			collision: CollisionSystem,
 			humanoid: HumanoidSystem,
+			pathfinding: PathfindingSystem::default(),
 			network: NetworkSystem
 		})
 	}
 
 	pub fn tick(&mut self, api: &Api, chunks: &ChunkStorage, debug: &mut impl DebugRendererImpl) {
 		self.gravity.tick(&mut self.storage);
+		self.pathfinding.tick(api, &mut self.storage, chunks);
 		self.humanoid.tick(&mut self.storage);
 		self.collision.tick(api, &mut self.storage, chunks, debug);
 		self.velocity.tick(&mut self.storage, debug);
@@ -0,0 +1,275 @@
+//! Every component which survives saving, cloning and moving an entity to another world.
+//!
+//! Each [`PersistentComponent`] decides what of it gets saved. Parts which can not be saved, like
+//! the Lua callbacks of a [`CollisionComponent`], come back from the prototype of the entity,
+//! and raw ids get saved as identifiers so saves survive registry changes. Components get saved
+//! under their [`PersistentComponent::NAME`], so components which no longer exist get skipped.
+use std::collections::BTreeMap;
+
+use euclid::Rect;
+use eyre::{ContextCompat, Result};
+use hecs::{Entity, EntityBuilder, EntityRef};
+use serde::{de::DeserializeOwned, Serialize};
+use tracing::warn;
+
+use crate::{
+	api::Api,
+	ty::{block_pos::BlockPos, identifier::Identifier, WS},
+	world::{
+		chunk::portable::PortableBlock,
+		entity::{
+			component::{
+				CollisionComponent, GravityComponent, HumanoidComponent, PhysicsComponent,
+				PositionComponent, PrototypeComponent,
+			},
+			system::pathfinding::PathfinderComponent,
+			EntityStorage,
+		},
+		falling::FallingBlockComponent,
+	},
+};
+
+pub trait PersistentComponent: hecs::Component + Clone {
+	/// What the component gets saved under, which has to stay the same across versions.
+	const NAME: &'static str;
+	type Saved: Serialize + DeserializeOwned;
+
+	fn save(&self, api: &Api) -> Self::Saved;
+
+	/// `template` is the component the prototype gave the entity, if any.
+	/// [`None`] leaves the entity like the prototype made it.
+	fn restore(saved: Self::Saved, api: &Api, template: Option<&Self>) -> Option<Self>;
+}
+
+/// Components which are saved as they are.
+macro_rules! serde_components {
+	($($COMPONENT:ty => $NAME:literal),* $(,)?) => {$(
+		impl PersistentComponent for $COMPONENT {
+			const NAME: &'static str = $NAME;
+			type Saved = Self;
+
+			fn save(&self, _: &Api) -> Self { self.clone() }
+
+			fn restore(saved: Self, _: &Api, _: Option<&Self>) -> Option<Self> { Some(saved) }
+		}
+	)*};
+}
+
+serde_components! {
+	PositionComponent => "position",
+	PhysicsComponent => "physics",
+	HumanoidComponent => "humanoid",
+	GravityComponent => "gravity",
+}
+
+impl PersistentComponent for PrototypeComponent {
+	const NAME: &'static str = "prototype";
+	type Saved = Identifier;
+
+	fn save(&self, api: &Api) -> Identifier { api.carrier.entity.get_identifier(self.id).clone() }
+
+	fn restore(saved: Identifier, api: &Api, _: Option<&Self>) -> Option<Self> {
+		Some(PrototypeComponent {
+			id: api.carrier.entity.get_id(&saved)?,
+		})
+	}
+}
+
+impl PersistentComponent for CollisionComponent {
+	const NAME: &'static str = "collision";
+	type Saved = Rect<f32, WS>;
+
+	fn save(&self, _: &Api) -> Rect<f32, WS> { self.collision_box }
+
+	fn restore(saved: Rect<f32, WS>, _: &Api, template: Option<&Self>) -> Option<Self> {
+		Some(CollisionComponent {
+			collision_box: saved,
+			hit_callback: template.and_then(|template| template.hit_callback.clone()),
+			collided: Default::default(),
+			collisions: vec![],
+		})
+	}
+}
+
+impl PersistentComponent for PathfinderComponent {
+	const NAME: &'static str = "pathfinder";
+	type Saved = Option<BlockPos>;
+
+	fn save(&self, _: &Api) -> Option<BlockPos> { self.goal() }
+
+	/// The path gets searched again.
+	fn restore(saved: Option<BlockPos>, _: &Api, _: Option<&Self>) -> Option<Self> {
+		let mut pathfinder = PathfinderComponent::default();
+		if let Some(goal) = saved {
+			pathfinder.go_to(goal);
+		}
+		Some(pathfinder)
+	}
+}
+
+impl PersistentComponent for FallingBlockComponent {
+	const NAME: &'static str = "falling_block";
+	type Saved = (Identifier, PortableBlock);
+
+	fn save(&self, api: &Api) -> (Identifier, PortableBlock) {
+		let layer = api.carrier.block_layer.get(self.layer_id);
+		let block = layer.blocks.get(self.block_id).create_with_state(self.block_id, self.state);
+		(
+			api.carrier.block_layer.get_identifier(self.layer_id).clone(),
+			PortableBlock::new(layer, block),
+		)
+	}
+
+	fn restore(
+		(layer, block): (Identifier, PortableBlock),
+		api: &Api,
+		_: Option<&Self>,
+	) -> Option<Self> {
+		let layer_id = api.carrier.block_layer.get_id(&layer)?;
+		let block = block.bake(api.carrier.block_layer.get(layer_id), |err| {
+			warn!("Falling block {} has an invalid property: {err}", block.block)
+		})?;
+		Some(FallingBlockComponent {
+			layer_id,
+			block_id: block.id,
+			state: block.state,
+		})
+	}
+}
+
+pub(super) type RestoreFn = fn(&Api, &mut EntityStorage, Entity, &[u8]) -> Result<()>;
+
+struct ComponentEntry {
+	name: &'static str,
+	save: fn(&Api, &EntityRef) -> Option<Result<Vec<u8>>>,
+	restore: RestoreFn,
+	clone: fn(&EntityRef, &mut EntityBuilder),
+}
+
+fn save_component<C: PersistentComponent>(
+	api: &Api,
+	entity: &EntityRef,
+) -> Option<Result<Vec<u8>>> {
+	let component = entity.get::<C>()?;
+	Some(bincode::serialize(&component.save(api)).map_err(Into::into))
+}
+
+fn restore_component<C: PersistentComponent>(
+	api: &Api,
+	storage: &mut EntityStorage,
+	entity: Entity,
+	data: &[u8],
+) -> Result<()> {
+	let saved: C::Saved = bincode::deserialize(data)?;
+	let template = storage.get_comp::<C>(entity).map(|component| (*component).clone());
+	if let Some(component) = C::restore(saved, api, template.as_ref()) {
+		storage.insert_comp(entity, component);
+	}
+	Ok(())
+}
+
+fn clone_component<C: PersistentComponent>(entity: &EntityRef, builder: &mut EntityBuilder) {
+	if let Some(component) = entity.get::<C>() {
+		builder.add((*component).clone());
+	}
+}
+
+pub struct ComponentRegistry {
+	entries: Vec<ComponentEntry>,
+}
+
+impl ComponentRegistry {
+	/// A registry with every component of the kernel.
+	pub fn new() -> ComponentRegistry {
+		let mut registry = ComponentRegistry {
+			entries: Vec::new(),
+		};
+		registry.register::<PrototypeComponent>();
+		registry.register::<PositionComponent>();
+		registry.register::<PhysicsComponent>();
+		registry.register::<CollisionComponent>();
+		registry.register::<HumanoidComponent>();
+		registry.register::<GravityComponent>();
+		registry.register::<PathfinderComponent>();
+		registry.register::<FallingBlockComponent>();
+		registry
+	}
+
+	pub fn register<C: PersistentComponent>(&mut self) {
+		if self.contains(C::NAME) {
+			warn!("Component {} is already registered", C::NAME);
+			return;
+		}
+
+		self.entries.push(ComponentEntry {
+			name: C::NAME,
+			save: save_component::<C>,
+			restore: restore_component::<C>,
+			clone: clone_component::<C>,
+		});
+	}
+
+	pub fn contains(&self, name: &str) -> bool {
+		self.entries.iter().any(|entry| entry.name == name)
+	}
+
+	/// Every registered component the entity has, by name.
+	pub fn save(&self, api: &Api, entity: &EntityRef) -> Result<BTreeMap<String, Vec<u8>>> {
+		let mut out = BTreeMap::new();
+		for entry in &self.entries {
+			if let Some(data) = (entry.save)(api, entity) {
+				out.insert(entry.name.to_string(), data?);
+			}
+		}
+		Ok(out)
+	}
+
+	/// How to restore a saved component, see [`EntityStorage::restore_component`].
+	pub(super) fn restorer(&self, name: &str) -> Result<RestoreFn> {
+		let entry = self
+			.entries
+			.iter()
+			.find(|entry| entry.name == name)
+			.wrap_err_with(|| format!("Component {name} does not exist"))?;
+		Ok(entry.restore)
+	}
+
+	/// Copies every registered component of the entity, for storages using the same registries.
+	pub fn clone(&self, entity: &EntityRef) -> EntityBuilder {
+		let mut builder = EntityBuilder::new();
+		for entry in &self.entries {
+			(entry.clone)(entity, &mut builder);
+		}
+		builder
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use euclid::{rect, vec2};
+
+	use super::*;
+
+	#[test]
+	fn clone_copies_registered_components() {
+		let mut storage = EntityStorage::new();
+		let entity = storage.spawn((
+			PositionComponent { pos: vec2(1.0, 2.0) },
+			CollisionComponent {
+				collision_box: rect(-0.5, -0.5, 1.0, 1.0),
+				hit_callback: None,
+				collided: Default::default(),
+				collisions: vec![],
+			},
+			// Not registered, so it stays behind.
+			7u32,
+		));
+
+		let registry = ComponentRegistry::new();
+		let mut builder = registry.clone(&storage.get(entity).unwrap());
+		let copy = storage.spawn(builder.build());
+		assert_eq!(storage.get_comp::<PositionComponent>(copy).unwrap().pos, vec2(1.0, 2.0));
+		assert!(storage.get_comp::<CollisionComponent>(copy).is_some());
+		assert!(storage.get_comp::<u32>(copy).is_none());
+	}
+}
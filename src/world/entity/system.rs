@@ -13,6 +13,10 @@ use crate::{
 pub mod collision;
 pub mod humanoid;
 pub mod network;
+pub mod pathfinding;
+
+/// How much faster entities with gravity fall every second, in blocks per tick.
+pub const GRAVITY: f32 = 0.8;
 
 pub struct VelocitySystem;
 
@@ -44,7 +48,7 @@ impl GravitySystem {
 		for (_, (velocity, gravity)) in
 			world.query_mut::<(&mut PhysicsComponent, &GravityComponent)>()
 		{
-			velocity.vel.y -= (0.8) / TPS as f32;
+			velocity.vel.y -= GRAVITY / TPS as f32;
 			// terminal velocity
 			velocity.vel.y = velocity.vel.y.max(-(37.5 / TPS as f32));
 		}
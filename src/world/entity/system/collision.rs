@@ -1,13 +1,13 @@
-use euclid::{rect, vec2, Rect, Size2D, Vector2D};
+use euclid::{rect, vec2, Rect, Vector2D};
 use apollo::{LuaSerdeExt, Value};
 
 use crate::{
 	debug::{DebugCategory, DebugRendererImpl},
 	draw_debug,
-	ty::{block_pos::BlockPos, direction::DirMap, WS},
+	ty::{direction::DirMap, WS},
 	util::aabb,
 	world::{
-		chunk::CHUNK_SIZE,
+		chunk::{query::BlockArea, CHUNK_SIZE_F32},
 		entity::{
 			component::{CollisionComponent, PhysicsComponent, PositionComponent},
 			EntityStorage,
@@ -40,73 +40,47 @@ impl CollisionSystem {
 			let mut new_rect = old_rect;
 			new_rect.origin += physics.vel;
 
-			let x1 = new_rect.min_x().min(old_rect.min_x()).floor() as i64;
-			let y1 = new_rect.min_y().min(old_rect.min_y()).floor() as i64;
-			let x2 = new_rect.max_x().max(old_rect.max_x()).ceil() as i64;
-			let y2 = new_rect.max_y().max(old_rect.max_y()).ceil() as i64;
-			draw_debug!(
-				debug,
-				DebugCategory::EntityCollision,
-				rect(
-					x1 as f32,
-					y1 as f32,
-					x2 as f32 - x1 as f32,
-					y2 as f32 - y1 as f32,
-				)
-			);
+			let area = BlockArea::from_rect(new_rect.union(&old_rect));
+			draw_debug!(debug, DebugCategory::EntityCollision, area.to_rect());
 			draw_debug!(debug, DebugCategory::EntityCollision, old_rect, 0xfcfcfa);
 
 			collision.collisions.clear();
-			for x in x1..x2 {
-				for y in y1..y2 {
-					let pos = vec2(x as f32, y as f32);
-					if let Ok(world_pos) = BlockPos::try_from(pos) {
-						if let Some(chunk) = chunks.get(world_pos.chunk) {
-							for (id, layer) in chunk.layers.iter() {
-								let prototype = api.carrier.block_layer.get(id);
-								if !prototype.collision || !layer[world_pos.entry].collision {
-									// dont move.
-									continue;
-								}
-								let tile = Rect::new(pos.to_point(), Size2D::new(1.0, 1.0));
-								test_collision(physics.vel, old_rect, tile, &mut collision.collisions, debug);
-							}
-						}
+			for (layer_id, _, prototype) in api.carrier.block_layer.entries() {
+				if !prototype.collision {
+					continue;
+				}
+
+				for (pos, block) in chunks.blocks_in(area, layer_id) {
+					if !block.collision {
+						// dont move.
+						continue;
 					}
+					let tile = rect(pos.x() as f32, pos.y() as f32, 1.0, 1.0);
+					test_collision(physics.vel, old_rect, tile, &mut collision.collisions, debug);
 				}
 			}
 
 			// world border
-			let w = chunks.width() as f32 * CHUNK_SIZE as f32;
-			let h = chunks.height() as f32 * CHUNK_SIZE as f32;
-			test_collision(
-				physics.vel,
-				old_rect,
-				rect(0.0, -2.0, w, 2.0),
-				&mut collision.collisions,
-				debug,
-			);
-			test_collision(
-				physics.vel,
-				old_rect,
-				rect(-2.0, 0.0, 2.0, h),
-				&mut collision.collisions,
-				debug,
-			);
-			test_collision(
-				physics.vel,
-				old_rect,
-				rect(w, 0.0, 2.0, h),
-				&mut collision.collisions,
-				debug,
-			);
-			test_collision(
-				physics.vel,
-				old_rect,
-				rect(0.0, h, w, 2.0),
-				&mut collision.collisions,
-				debug,
-			);
+			if let Some(bounds) = chunks.bounds() {
+				let x = bounds.min.x as f32 * CHUNK_SIZE_F32;
+				let y = bounds.min.y as f32 * CHUNK_SIZE_F32;
+				let w = bounds.width() as f32 * CHUNK_SIZE_F32;
+				let h = bounds.height() as f32 * CHUNK_SIZE_F32;
+				for border in [
+					rect(x, y - 2.0, w, 2.0),
+					rect(x - 2.0, y, 2.0, h),
+					rect(x + w, y, 2.0, h),
+					rect(x, y + h, w, 2.0),
+				] {
+					test_collision(
+						physics.vel,
+						old_rect,
+						border,
+						&mut collision.collisions,
+						debug,
+					);
+				}
+			}
 
 			collision.collisions.sort_by(|v0, v1| v0.1.total_cmp(&v1.1));
 
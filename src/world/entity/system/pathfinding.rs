@@ -0,0 +1,534 @@
+//! Steering humanoids through the block grid.
+//!
+//! Paths get searched with A* over the tiles an entity can stand on. From one tile it can walk
+//! to the next, drop down a ledge up to [`MAX_FALL`] tiles or jump up as high as its
+//! [`HumanoidComponent`] allows. Searches run spread over several ticks, at most
+//! [`MAX_NODES_PER_TICK`] tiles per tick for all entities together, and give up after
+//! [`MAX_NODES_PER_SEARCH`] tiles. Changing or clearing the goal cancels a running search.
+use std::{
+	cmp::Reverse,
+	collections::{BinaryHeap, VecDeque},
+};
+
+use euclid::{vec2, Vector2D};
+use fxhash::FxHashMap;
+use hecs::Entity;
+
+use crate::{
+	ty::{block_pos::BlockPos, direction::Direction, WS},
+	world::entity::{
+		component::{CollisionComponent, HumanoidComponent, PositionComponent},
+		system::GRAVITY,
+		EntityStorage,
+	},
+	Api, ChunkStorage, TPS,
+};
+
+pub const MAX_NODES_PER_TICK: usize = 2048;
+pub const MAX_NODES_PER_SEARCH: usize = 16384;
+/// How far an entity is willing to drop.
+pub const MAX_FALL: i64 = 16;
+/// Ticks without reaching the next waypoint before searching a new path.
+const STUCK_TICKS: u32 = TPS as u32 * 3;
+/// How close to the middle of a waypoint counts as being on it.
+const ARRIVE_DISTANCE: f32 = 0.2;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PathState {
+	Idle,
+	Searching,
+	Following,
+	Arrived,
+	Unreachable,
+}
+
+/// Makes a humanoid walk to a goal, replacing whatever sets its `dir` and `jumping`.
+#[derive(Debug, Clone)]
+pub struct PathfinderComponent {
+	goal: Option<BlockPos>,
+	changed: bool,
+	state: PathState,
+	/// The tiles the entity still has to stand on, the goal last.
+	path: VecDeque<BlockPos>,
+	stuck_ticks: u32,
+}
+
+impl Default for PathfinderComponent {
+	fn default() -> Self {
+		PathfinderComponent {
+			goal: None,
+			changed: false,
+			state: PathState::Idle,
+			path: VecDeque::new(),
+			stuck_ticks: 0,
+		}
+	}
+}
+
+impl PathfinderComponent {
+	/// Starts searching a path to the tile the entity should stand on.
+	pub fn go_to(&mut self, goal: BlockPos) {
+		self.goal = Some(goal);
+		self.changed = true;
+	}
+
+	pub fn stop(&mut self) {
+		self.goal = None;
+		self.changed = true;
+	}
+
+	pub fn goal(&self) -> Option<BlockPos> { self.goal }
+
+	pub fn state(&self) -> PathState { self.state }
+
+	pub fn path(&self) -> &VecDeque<BlockPos> { &self.path }
+}
+
+/// How many tiles high a humanoid gets by holding jump.
+pub fn jump_height(humanoid: &HumanoidComponent) -> i64 {
+	// Holding jump keeps the speed up for `jump_amount` seconds, after that gravity slows it down.
+	let speed = humanoid.jump_speed / TPS as f32;
+	let gravity = GRAVITY / TPS as f32;
+	let held = humanoid.jump_amount * humanoid.jump_speed;
+	(held + speed * speed / (2.0 * gravity)).floor() as i64
+}
+
+enum Search {
+	Pending,
+	Found(VecDeque<BlockPos>),
+	NotFound,
+}
+
+type Node = (i64, i64);
+
+/// An A* search which can pause whenever it runs out of budget.
+struct PathSearch {
+	goal: Node,
+	/// The size of the entity in tiles.
+	size: (i64, i64),
+	jump: i64,
+	open: BinaryHeap<Reverse<(u32, Node)>>,
+	cost: FxHashMap<Node, u32>,
+	came_from: FxHashMap<Node, Node>,
+	expanded: usize,
+}
+
+impl PathSearch {
+	fn new(start: Node, goal: Node, size: (i64, i64), jump: i64) -> PathSearch {
+		let mut search = PathSearch {
+			goal,
+			size,
+			jump,
+			open: BinaryHeap::new(),
+			cost: FxHashMap::default(),
+			came_from: FxHashMap::default(),
+			expanded: 0,
+		};
+		search.cost.insert(start, 0);
+		search.open.push(Reverse((search.estimate(start), start)));
+		search
+	}
+
+	fn estimate(&self, (x, y): Node) -> u32 {
+		((x - self.goal.0).abs() + (y - self.goal.1).abs()) as u32
+	}
+
+	/// Runs until the path is found or `budget` tiles have been looked at.
+	fn step(&mut self, passable: &impl Fn(i64, i64) -> bool, budget: &mut usize) -> Search {
+		while *budget > 0 {
+			let (estimate, node) = match self.open.pop() {
+				Some(Reverse(entry)) => entry,
+				None => return Search::NotFound,
+			};
+			let cost = self.cost[&node];
+			if estimate > cost + self.estimate(node) {
+				// A cheaper way to this tile was found after this one got queued.
+				continue;
+			}
+			if node == self.goal {
+				return Search::Found(self.path(node));
+			}
+
+			*budget -= 1;
+			self.expanded += 1;
+			if self.expanded > MAX_NODES_PER_SEARCH {
+				return Search::NotFound;
+			}
+
+			for (next, step_cost) in self.moves(passable, node) {
+				let next_cost = cost + step_cost;
+				if self.cost.get(&next).map_or(true, |old| next_cost < *old) {
+					self.cost.insert(next, next_cost);
+					self.came_from.insert(next, node);
+					self.open.push(Reverse((next_cost + self.estimate(next), next)));
+				}
+			}
+		}
+		Search::Pending
+	}
+
+	fn path(&self, mut node: Node) -> VecDeque<BlockPos> {
+		let mut path = VecDeque::new();
+		while let Some(previous) = self.came_from.get(&node) {
+			path.extend(BlockPos::from_block(node.0, node.1));
+			node = *previous;
+		}
+		path.make_contiguous().reverse();
+		path
+	}
+
+	/// Whether the entity fits with its bottom left corner in this tile.
+	fn fits(&self, passable: &impl Fn(i64, i64) -> bool, (x, y): Node) -> bool {
+		(x..x + self.size.0).all(|x| (y..y + self.size.1).all(|y| passable(x, y)))
+	}
+
+	fn standing(&self, passable: &impl Fn(i64, i64) -> bool, (x, y): Node) -> bool {
+		self.fits(passable, (x, y)) && (x..x + self.size.0).any(|x| !passable(x, y - 1))
+	}
+
+	/// The first tile to stand on when falling from `node`.
+	fn land(&self, passable: &impl Fn(i64, i64) -> bool, (x, y): Node) -> Option<(Node, u32)> {
+		for fall in 1..=MAX_FALL {
+			if !self.fits(passable, (x, y - fall)) {
+				return None;
+			}
+			if self.standing(passable, (x, y - fall)) {
+				return Some(((x, y - fall), fall as u32));
+			}
+		}
+		None
+	}
+
+	fn moves(&self, passable: &impl Fn(i64, i64) -> bool, (x, y): Node) -> Vec<(Node, u32)> {
+		let mut out = Vec::new();
+		if !self.standing(passable, (x, y)) {
+			// Only happens when starting in the air.
+			out.extend(self.land(passable, (x, y)));
+			return out;
+		}
+
+		for dir in [Direction::Left, Direction::Right] {
+			let next_x = x + dir.offset_x() as i64;
+			if self.fits(passable, (next_x, y)) {
+				if self.standing(passable, (next_x, y)) {
+					out.push(((next_x, y), 1));
+				} else if let Some((landed, fall)) = self.land(passable, (next_x, y)) {
+					out.push((landed, 1 + fall));
+				}
+			}
+
+			for up in 1..=self.jump {
+				if !self.fits(passable, (x, y + up)) {
+					break;
+				}
+				if self.standing(passable, (next_x, y + up)) {
+					out.push(((next_x, y + up), 1 + up as u32));
+				}
+			}
+		}
+		out
+	}
+}
+
+#[derive(Default)]
+pub struct PathfindingSystem {
+	searches: VecDeque<(Entity, PathSearch)>,
+}
+
+impl PathfindingSystem {
+	pub fn tick(&mut self, api: &Api, storage: &mut EntityStorage, chunks: &ChunkStorage) {
+		for (entity, (pathfinder, position, collision, humanoid)) in storage.query_mut::<(
+			&mut PathfinderComponent,
+			&PositionComponent,
+			&CollisionComponent,
+			&mut HumanoidComponent,
+		)>() {
+			if !pathfinder.changed {
+				continue;
+			}
+
+			// Lets go of the humanoid, so whatever else moves it is not left with our steering.
+			if pathfinder.state == PathState::Following {
+				humanoid.dir = Vector2D::zero();
+				humanoid.jumping = false;
+			}
+			pathfinder.changed = false;
+			pathfinder.path.clear();
+			pathfinder.stuck_ticks = 0;
+			self.searches.retain(|(searching, _)| *searching != entity);
+			pathfinder.state = match pathfinder.goal {
+				Some(goal) => {
+					let size = collision.collision_box.size;
+					let size = (tiles(size.width), tiles(size.height));
+					// Nodes are the bottom left tile of the entity, which is centered on its feet.
+					let feet = feet(position, collision);
+					let left = (feet.x - size.0 as f32 / 2.0).round() as i64;
+					let start = (left, (feet.y + 0.01).floor() as i64);
+					let goal = (goal.x(), goal.y());
+					let search = PathSearch::new(start, goal, size, jump_height(humanoid));
+					self.searches.push_back((entity, search));
+					PathState::Searching
+				}
+				None => PathState::Idle,
+			};
+		}
+
+		// Entities which despawned or lost their pathfinder cancel their search.
+		self.searches
+			.retain(|(entity, _)| storage.get_comp::<PathfinderComponent>(*entity).is_some());
+
+		let passable = |x: i64, y: i64| {
+			let pos = match BlockPos::from_block(x, y) {
+				Some(pos) => pos,
+				None => return false,
+			};
+			let chunk = match chunks.get(pos.chunk) {
+				Some(chunk) => chunk,
+				None => return false,
+			};
+			chunk.layers.iter().all(|(layer_id, layer)| {
+				!api.carrier.block_layer.get(layer_id).collision || !layer[pos.entry].collision
+			})
+		};
+		let mut budget = MAX_NODES_PER_TICK;
+		while budget > 0 {
+			let (entity, mut search) = match self.searches.pop_front() {
+				Some(search) => search,
+				None => break,
+			};
+			let result = search.step(&passable, &mut budget);
+			let mut pathfinder = match storage.get_mut_comp::<PathfinderComponent>(entity) {
+				Some(pathfinder) => pathfinder,
+				None => continue,
+			};
+			match result {
+				Search::Pending => self.searches.push_back((entity, search)),
+				Search::Found(path) => {
+					pathfinder.path = path;
+					pathfinder.state = PathState::Following;
+				}
+				Search::NotFound => pathfinder.state = PathState::Unreachable,
+			}
+		}
+
+		self.follow(storage);
+	}
+
+	/// Steers every entity towards its next waypoint. Humanoids which do not follow a path are
+	/// left alone.
+	fn follow(&mut self, storage: &mut EntityStorage) {
+		for (_, (pathfinder, position, collision, humanoid)) in storage.query_mut::<(
+			&mut PathfinderComponent,
+			&PositionComponent,
+			&CollisionComponent,
+			&mut HumanoidComponent,
+		)>() {
+			if pathfinder.state != PathState::Following {
+				continue;
+			}
+
+			humanoid.dir = Vector2D::zero();
+			humanoid.jumping = false;
+
+			let next = match pathfinder.path.front() {
+				Some(next) => *next,
+				None => {
+					pathfinder.state = PathState::Arrived;
+					continue;
+				}
+			};
+
+			let feet = feet(position, collision);
+			let width = tiles(collision.collision_box.size.width) as f32;
+			let target = vec2::<_, WS>(next.x() as f32 + width / 2.0, next.y() as f32);
+			let delta = target - feet;
+			if delta.x.abs() < ARRIVE_DISTANCE && delta.y.abs() < ARRIVE_DISTANCE {
+				pathfinder.path.pop_front();
+				pathfinder.stuck_ticks = 0;
+				if pathfinder.path.is_empty() {
+					pathfinder.state = PathState::Arrived;
+				}
+				continue;
+			}
+
+			pathfinder.stuck_ticks += 1;
+			if pathfinder.stuck_ticks > STUCK_TICKS {
+				pathfinder.changed = true;
+				continue;
+			}
+
+			if delta.x.abs() >= ARRIVE_DISTANCE {
+				humanoid.dir.x = delta.x.signum();
+			}
+			humanoid.jumping = delta.y > 0.5;
+		}
+	}
+}
+
+/// The bottom middle of the collision box.
+fn feet(position: &PositionComponent, collision: &CollisionComponent) -> Vector2D<f32, WS> {
+	let bounds = collision.collision_box;
+	position.pos + vec2(bounds.center().x, bounds.min_y())
+}
+
+fn tiles(size: f32) -> i64 { ((size - 0.001).ceil() as i64).max(1) }
+
+#[cfg(test)]
+mod tests {
+	use euclid::rect;
+
+	use super::*;
+	use crate::{
+		api::test::{api, block_desc},
+		ty::{block_layer_pos::BlockLayerPos, id::Id},
+		world::chunk::{
+			test::{block, chunk},
+			CHUNK_SIZE,
+		},
+		ChunkPos,
+	};
+
+	/// A floor below y = 1, a two block high wall at x = 5 and a two block deep pit at x = 8.
+	fn passable(x: i64, y: i64) -> bool {
+		match (x, y) {
+			(8, -1..=0) => true,
+			(5, 1..=2) => false,
+			(_, y) => y > 0,
+		}
+	}
+
+	fn search(jump: i64) -> Search {
+		let mut search = PathSearch::new((1, 1), (10, 1), (1, 2), jump);
+		let mut budget = usize::MAX;
+		search.step(&passable, &mut budget)
+	}
+
+	#[test]
+	fn jumps_over_walls_and_into_pits() {
+		let path = match search(2) {
+			Search::Found(path) => path,
+			_ => panic!("No path found"),
+		};
+		let path: Vec<_> = path.iter().map(|pos| (pos.x(), pos.y())).collect();
+		assert!(path.contains(&(5, 3)));
+		assert!(path.contains(&(8, -1)));
+		assert_eq!(path.last(), Some(&(10, 1)));
+
+		assert!(matches!(search(1), Search::NotFound));
+	}
+
+	#[test]
+	fn searches_pause_when_out_of_budget() {
+		let mut search = PathSearch::new((1, 1), (10, 1), (1, 2), 2);
+		let mut budget = 3;
+		assert!(matches!(search.step(&passable, &mut budget), Search::Pending));
+		assert_eq!(budget, 0);
+
+		let mut budget = usize::MAX;
+		assert!(matches!(search.step(&passable, &mut budget), Search::Found(_)));
+	}
+
+	/// A two tile high humanoid standing on the floor of chunk 0, 0 with its feet at `x`.
+	fn walker(storage: &mut EntityStorage, x: f32) -> Entity {
+		storage.spawn((
+			PathfinderComponent::default(),
+			PositionComponent { pos: vec2(x, 2.0) },
+			CollisionComponent {
+				collision_box: rect(-0.4, -1.0, 0.8, 2.0),
+				hit_callback: None,
+				collided: Default::default(),
+				collisions: vec![],
+			},
+			HumanoidComponent {
+				jump_amount: 0.1,
+				jump_speed: 20.0,
+				run_acceleration: 1.0,
+				run_slowdown: 1.0,
+				run_max_speed: 1.0,
+				dir: Vector2D::zero(),
+				jumping: false,
+				jumped: false,
+				jump_ticks_remaining: 0,
+			},
+		))
+	}
+
+	/// `chunks` chunks in a row with a floor at y = 0 and a step at `step`.
+	fn floor(chunks: i32, step: Option<BlockPos>) -> ChunkStorage {
+		let layer_id = unsafe { Id::new(0) };
+		let mut storage = ChunkStorage::unbounded();
+		for x in 0..chunks {
+			let mut floor = chunk(layer_id, 0);
+			for entry_x in 0..CHUNK_SIZE {
+				floor.layers.get_mut(layer_id).set(BlockLayerPos::new(entry_x as u8, 0), block(1));
+			}
+			storage.insert(ChunkPos { x, y: 0 }, floor);
+		}
+		if let Some(step) = step {
+			storage.get_mut(step.chunk).unwrap().layers.get_mut(layer_id).set(step.entry, block(1));
+		}
+		storage
+	}
+
+	fn state(storage: &EntityStorage, entity: Entity) -> PathState {
+		storage.get_comp::<PathfinderComponent>(entity).unwrap().state()
+	}
+
+	#[test]
+	fn steers_humanoids_along_the_path() {
+		let api = api(vec![("air", block_desc(false)), ("stone", block_desc(true))], vec![]);
+		let chunks = floor(1, BlockPos::from_block(3, 1));
+		let mut storage = EntityStorage::new();
+		let entity = walker(&mut storage, 2.5);
+		let mut system = PathfindingSystem::default();
+
+		let goal = BlockPos::from_block(6, 1).unwrap();
+		storage.get_mut_comp::<PathfinderComponent>(entity).unwrap().go_to(goal);
+		system.tick(&api, &mut storage, &chunks);
+		assert_eq!(state(&storage, entity), PathState::Following);
+		{
+			let pathfinder = storage.get_comp::<PathfinderComponent>(entity).unwrap();
+			let first = pathfinder.path().front().unwrap();
+			assert_eq!((first.x(), first.y()), (3, 2));
+			let humanoid = storage.get_comp::<HumanoidComponent>(entity).unwrap();
+			assert_eq!(humanoid.dir.x, 1.0);
+			assert!(humanoid.jumping);
+		}
+
+		storage.get_mut_comp::<PathfinderComponent>(entity).unwrap().stop();
+		system.tick(&api, &mut storage, &chunks);
+		assert_eq!(state(&storage, entity), PathState::Idle);
+		{
+			let humanoid = storage.get_comp::<HumanoidComponent>(entity).unwrap();
+			assert_eq!(humanoid.dir, Vector2D::zero());
+			assert!(!humanoid.jumping);
+		}
+
+		// Idle pathfinders leave the humanoid to something else.
+		storage.get_mut_comp::<HumanoidComponent>(entity).unwrap().dir.x = -1.0;
+		system.tick(&api, &mut storage, &chunks);
+		assert_eq!(storage.get_comp::<HumanoidComponent>(entity).unwrap().dir.x, -1.0);
+	}
+
+	#[test]
+	fn searches_share_a_budget_and_stop_cancels_them() {
+		let api = api(vec![("air", block_desc(false)), ("stone", block_desc(true))], vec![]);
+		// More floor tiles than one tick can look at, and no way to reach the goal.
+		let chunks = floor((MAX_NODES_PER_TICK / CHUNK_SIZE) as i32 + 8, None);
+		let mut storage = EntityStorage::new();
+		let entity = walker(&mut storage, 2.5);
+		let mut system = PathfindingSystem::default();
+
+		let goal = BlockPos::from_block(1 << 20, 1).unwrap();
+		storage.get_mut_comp::<PathfinderComponent>(entity).unwrap().go_to(goal);
+		system.tick(&api, &mut storage, &chunks);
+		assert_eq!(state(&storage, entity), PathState::Searching);
+		assert_eq!(system.searches.len(), 1);
+		assert_eq!(system.searches[0].1.expanded, MAX_NODES_PER_TICK);
+
+		storage.get_mut_comp::<PathfinderComponent>(entity).unwrap().stop();
+		system.tick(&api, &mut storage, &chunks);
+		assert_eq!(state(&storage, entity), PathState::Idle);
+		assert!(system.searches.is_empty());
+	}
+}
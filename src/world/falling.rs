@@ -0,0 +1,123 @@
+//! Blocks which fall when nothing holds them up.
+//!
+//! A block with `falls` turns into that entity once the block below it stops colliding.
+//! The entity falls using the normal gravity and collision systems, and turns back into the
+//! block wherever it lands. If that cell got filled in the meantime it takes the first free cell
+//! above, or drops like a mined block when there is none close by.
+//! ```lua
+//! reload.stargate.entity:register {
+//!     ["falling_sand"] = {
+//!         position = { 0.0, 0.0 },
+//!         velocity = { vel = { 0.0, 0.0 }, accel = { 0.0, 0.0 } },
+//!         collision = { collision_box = { origin = { -0.49, -0.49 }, size = { 0.98, 0.98 } } },
+//!         gravity = { amount = 1.0 }
+//!     }
+//! }
+//! -- In the tile layer
+//! ["sand"] = {
+//!     collision = true,
+//!     falls = "falling_sand"
+//! }
+//! ```
+use hecs::Entity;
+
+use crate::{
+	ty::{block_pos::BlockPos, direction::Direction, id::Id},
+	world::{
+		chunk::{block::BlockDesc, layer::BlockLayer, state::BlockState},
+		entity::{
+			component::{CollisionComponent, PositionComponent},
+			EntityStorage,
+		},
+	},
+};
+
+/// The block a falling entity turns back into.
+#[derive(Clone, Copy)]
+pub struct FallingBlockComponent {
+	pub layer_id: Id<BlockLayer>,
+	pub block_id: Id<BlockDesc>,
+	pub state: BlockState,
+}
+
+pub struct LandedBlock {
+	pub entity: Entity,
+	pub pos: BlockPos,
+	pub block: FallingBlockComponent,
+}
+
+pub struct FallingBlockSystem;
+
+impl FallingBlockSystem {
+	/// Removes the falling blocks which hit the ground, returning where they landed.
+	pub fn tick(&mut self, storage: &mut EntityStorage) -> Vec<LandedBlock> {
+		let mut landed = Vec::new();
+		for (entity, (block, position, collision)) in storage.query_mut::<(
+			&FallingBlockComponent,
+			&PositionComponent,
+			&CollisionComponent,
+		)>() {
+			if !collision.collided[Direction::Up] {
+				continue;
+			}
+
+			let x = position.pos.x.floor() as i64;
+			let y = position.pos.y.floor() as i64;
+			if let Some(pos) = BlockPos::from_block(x, y) {
+				landed.push(LandedBlock {
+					entity,
+					pos,
+					block: *block,
+				});
+			}
+		}
+
+		for landed in &landed {
+			storage.remove(landed.entity);
+		}
+		landed
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use euclid::{rect, vec2};
+
+	use super::*;
+
+	fn falling(storage: &mut EntityStorage, grounded: bool) -> Entity {
+		let mut collision = CollisionComponent {
+			collision_box: rect(-0.49, -0.49, 0.98, 0.98),
+			hit_callback: None,
+			collided: Default::default(),
+			collisions: vec![],
+		};
+		collision.collided[Direction::Up] = grounded;
+		storage.spawn((
+			FallingBlockComponent {
+				layer_id: unsafe { Id::new(0) },
+				block_id: unsafe { Id::new(3) },
+				state: BlockState::DEFAULT,
+			},
+			PositionComponent {
+				pos: vec2(4.5, 10.49),
+			},
+			collision,
+		))
+	}
+
+	#[test]
+	fn lands_where_it_hits_the_ground() {
+		let mut storage = EntityStorage::new();
+		let grounded = falling(&mut storage, true);
+		let airborne = falling(&mut storage, false);
+
+		let landed = FallingBlockSystem.tick(&mut storage);
+		assert_eq!(landed.len(), 1);
+		assert_eq!(landed[0].entity, grounded);
+		assert!(landed[0].pos == BlockPos::from_block(4, 10).unwrap());
+		assert!(landed[0].block.block_id == unsafe { Id::new(3) });
+		assert!(!storage.contains(grounded));
+		assert!(storage.contains(airborne));
+	}
+}
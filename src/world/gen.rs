@@ -0,0 +1,425 @@
+//! Procedural terrain generation.
+//!
+//! Chunks are generated on demand from the world seed.
+//! The generator only reads the seed and its block set, so the same seed and registry always
+//! produce the exact same chunks no matter in which order they get requested.
+//! Plugins can add their own [passes](pass) which run after the builtin terrain.
+//! Every column belongs to the [biome](crate::world::biome) closest to its climate noise, which
+//! decides the surface, filler and underground blocks of that column.
+use eyre::{ContextCompat, Result};
+use tracing::warn;
+
+use crate::{
+	api::{id_table::IdTable, Api},
+	ty::{block_layer_pos::BlockLayerPos, id::Id, identifier::Identifier},
+	world::{
+		biome::{BiomeDesc, ChunkBiomes},
+		chunk::{block::Block, layer::BlockLayer, Chunk, ChunkLayer, CHUNK_SIZE},
+		gen::noise::{fractal_1d, fractal_2d},
+	},
+	ChunkPos,
+};
+
+pub mod noise;
+pub mod pass;
+
+// Salts so every feature samples its own noise.
+const SURFACE_SALT: u64 = 0x5355_5246;
+const DIRT_SALT: u64 = 0x4449_5254;
+const CAVE_SALT: u64 = 0x4341_5645;
+const ORE_SALT: u64 = 0x4f52_4553;
+const TEMPERATURE_SALT: u64 = 0x5445_4d50;
+const HUMIDITY_SALT: u64 = 0x4855_4d49;
+
+/// Stored in the world save, so a world keeps generating the same terrain.
+#[derive(Clone, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+pub struct GenSettings {
+	/// The average surface height in blocks.
+	pub surface_level: f32,
+	/// How far the surface may deviate from the `surface_level`.
+	pub surface_amplitude: f32,
+	/// The horizontal stretch of the hills.
+	pub surface_scale: f32,
+	/// The average thickness of the dirt strata.
+	pub dirt_depth: f32,
+	/// The scale of the cave noise. Bigger means larger caves.
+	pub cave_scale: f32,
+	/// Noise above this value becomes a cave.
+	pub cave_threshold: f32,
+	/// Caves will not cut into the first few blocks under the surface.
+	pub cave_min_depth: f32,
+	/// The horizontal stretch of the climate noise, bigger means wider biomes.
+	pub biome_scale: f32,
+	pub ores: Vec<OreSettings>,
+}
+
+#[derive(Clone, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+pub struct OreSettings {
+	pub scale: f32,
+	/// Noise above this value becomes the ore.
+	pub threshold: f32,
+	pub min_depth: f32,
+	// Last, as toml can not write values after a table.
+	pub block: Identifier,
+}
+
+impl Default for GenSettings {
+	fn default() -> GenSettings {
+		GenSettings {
+			surface_level: 160.0,
+			surface_amplitude: 24.0,
+			surface_scale: 96.0,
+			dirt_depth: 8.0,
+			cave_scale: 24.0,
+			cave_threshold: 0.68,
+			cave_min_depth: 6.0,
+			biome_scale: 384.0,
+			ores: vec![
+				OreSettings {
+					block: Identifier::new("copper_ore"),
+					scale: 6.0,
+					threshold: 0.78,
+					min_depth: 10.0,
+				},
+				OreSettings {
+					block: Identifier::new("iron_ore"),
+					scale: 5.0,
+					threshold: 0.82,
+					min_depth: 30.0,
+				},
+			],
+		}
+	}
+}
+
+/// The blocks the generator places, resolved from the registries.
+#[derive(Clone)]
+pub struct TerrainBlocks {
+	pub tile: Id<BlockLayer>,
+	pub wall: Option<Id<BlockLayer>>,
+	pub air: Block,
+	pub dirt: Block,
+	pub grass: Block,
+	pub stone: Block,
+	pub wall_dirt: Option<Block>,
+	pub ores: Vec<(Block, OreSettings)>,
+	/// The biomes the generator places, empty to only use grass, dirt and stone.
+	pub biomes: Vec<GenBiome>,
+	/// What every layer is filled with before generating.
+	pub defaults: IdTable<BlockLayer, Block>,
+}
+
+#[derive(Clone)]
+pub struct GenBiome {
+	pub id: Id<BiomeDesc>,
+	pub temperature: f32,
+	pub humidity: f32,
+	pub blocks: ColumnBlocks,
+}
+
+/// The tile blocks of a column from top to bottom.
+#[derive(Clone, Copy)]
+pub struct ColumnBlocks {
+	pub surface: Block,
+	pub filler: Block,
+	pub underground: Block,
+}
+
+impl TerrainBlocks {
+	pub fn new(api: &Api, settings: &GenSettings) -> Result<TerrainBlocks> {
+		let layers = &api.carrier.block_layer;
+		let tile = layers
+			.get_id(&Identifier::new("tile"))
+			.wrap_err("Could not find tile layer")?;
+		let wall = layers.get_id(&Identifier::new("wall"));
+
+		let tile_layer = layers.get(tile);
+		let get = |layer: &BlockLayer, name: &'static str| -> Option<Block> {
+			layer
+				.blocks
+				.get_id(&Identifier::new(name))
+				.map(|id| layer.blocks.get(id).create(id))
+		};
+
+		let mut ores = Vec::new();
+		for ore in &settings.ores {
+			match tile_layer.blocks.get_id(&ore.block) {
+				Some(id) => ores.push((tile_layer.blocks.get(id).create(id), ore.clone())),
+				None => warn!("Ore {} does not exist, skipping it", ore.block),
+			}
+		}
+
+		let dirt = get(tile_layer, "dirt").wrap_err("Could not find dirt")?;
+		let grass = get(tile_layer, "grass").wrap_err("Could not find grass")?;
+		let stone = get(tile_layer, "stone").wrap_err("Could not find stone")?;
+		let mut biomes = Vec::new();
+		for (id, identifier, desc) in api.carrier.biome.entries() {
+			if !desc.generate {
+				continue;
+			}
+
+			let block = |block: &Option<Identifier>, fallback: Block| match block {
+				Some(block) => match tile_layer.blocks.get_id(block) {
+					Some(block_id) => tile_layer.blocks.get(block_id).create(block_id),
+					None => {
+						warn!("Biome {identifier} uses unknown block {block}, using the default");
+						fallback
+					}
+				},
+				None => fallback,
+			};
+			biomes.push(GenBiome {
+				id,
+				temperature: desc.temperature,
+				humidity: desc.humidity,
+				blocks: ColumnBlocks {
+					surface: block(&desc.surface, grass),
+					filler: block(&desc.filler, dirt),
+					underground: block(&desc.underground, stone),
+				},
+			});
+		}
+
+		Ok(TerrainBlocks {
+			tile,
+			wall,
+			air: get(tile_layer, "air").wrap_err("Could not find air")?,
+			dirt,
+			grass,
+			stone,
+			wall_dirt: wall.and_then(|wall| get(layers.get(wall), "dirt")),
+			ores,
+			biomes,
+			defaults: layers
+				.table
+				.iter()
+				.map(|(id, layer)| (id, layer.create_default()))
+				.collect(),
+		})
+	}
+}
+
+#[derive(Clone)]
+pub struct WorldGenerator {
+	seed: u64,
+	settings: GenSettings,
+	blocks: TerrainBlocks,
+	passes: Option<GenPasses>,
+}
+
+impl WorldGenerator {
+	pub fn new(seed: u64, settings: GenSettings, blocks: TerrainBlocks) -> WorldGenerator {
+		WorldGenerator {
+			seed,
+			settings,
+			blocks,
+			passes: None,
+		}
+	}
+
+	pub fn from_api(api: &Api, seed: u64, settings: GenSettings) -> Result<WorldGenerator> {
+		let blocks = TerrainBlocks::new(api, &settings)?;
+		let passes = GenPasses::new(api);
+		Ok(WorldGenerator {
+			passes: (!passes.is_empty()).then_some(passes),
+			..WorldGenerator::new(seed, settings, blocks)
+		})
+	}
+
+	pub fn seed(&self) -> u64 { self.seed }
+
+	pub fn settings(&self) -> &GenSettings { &self.settings }
+
+	/// The height of the highest solid block in this column.
+	pub fn surface_height(&self, x: i64) -> i64 {
+		let noise = fractal_1d(
+			self.seed ^ SURFACE_SALT,
+			x as f32 / self.settings.surface_scale,
+			4,
+		);
+		(self.settings.surface_level + (noise - 0.5) * 2.0 * self.settings.surface_amplitude)
+			as i64
+	}
+
+	/// The biome of this column and its blocks.
+	pub fn column(&self, x: i64) -> (Option<Id<BiomeDesc>>, ColumnBlocks) {
+		let scale = self.settings.biome_scale;
+		let temperature = fractal_1d(self.seed ^ TEMPERATURE_SALT, x as f32 / scale, 3);
+		let humidity = fractal_1d(self.seed ^ HUMIDITY_SALT, x as f32 / scale, 3);
+		let closest = self.blocks.biomes.iter().min_by(|a, b| {
+			let distance = |biome: &GenBiome| {
+				(biome.temperature - temperature).powi(2) + (biome.humidity - humidity).powi(2)
+			};
+			distance(a).total_cmp(&distance(b))
+		});
+
+		match closest {
+			Some(biome) => (Some(biome.id), biome.blocks),
+			None => (
+				None,
+				ColumnBlocks {
+					surface: self.blocks.grass,
+					filler: self.blocks.dirt,
+					underground: self.blocks.stone,
+				},
+			),
+		}
+	}
+
+	pub fn generate(&self, pos: ChunkPos) -> Chunk {
+		let mut layers: IdTable<BlockLayer, ChunkLayer<Block>> = self
+			.blocks
+			.defaults
+			.iter()
+			.map(|(id, block)| (id, ChunkLayer::new_copy(*block)))
+			.collect();
+
+		let mut biomes = ChunkBiomes::default();
+		for local_x in 0..CHUNK_SIZE {
+			let x = pos.x as i64 * CHUNK_SIZE as i64 + local_x as i64;
+			let (biome, column) = self.column(x);
+			biomes.set(local_x as u8, biome);
+			let surface = self.surface_height(x);
+			let dirt_depth = self.settings.dirt_depth
+				* (0.5 + fractal_1d(self.seed ^ DIRT_SALT, x as f32 / 16.0, 2));
+
+			for local_y in 0..CHUNK_SIZE {
+				let y = pos.y as i64 * CHUNK_SIZE as i64 + local_y as i64;
+				let entry = BlockLayerPos::new(local_x as u8, local_y as u8);
+				let depth = (surface - y) as f32;
+
+				layers
+					.get_mut(self.blocks.tile)
+					.set(entry, self.tile(x, y, depth, dirt_depth, column));
+				if let (Some(wall), Some(wall_dirt)) = (self.blocks.wall, self.blocks.wall_dirt) {
+					if depth >= 1.0 {
+						layers.get_mut(wall).set(entry, wall_dirt);
+					}
+				}
+			}
+		}
+
+		let mut chunk = Chunk::new(layers);
+		chunk.biomes = biomes;
+		match &self.passes {
+			Some(passes) => passes.apply(self.seed, pos, chunk),
+			None => chunk,
+		}
+	}
+
+	fn tile(&self, x: i64, y: i64, depth: f32, dirt_depth: f32, column: ColumnBlocks) -> Block {
+		if depth < 0.0 {
+			return self.blocks.air;
+		}
+
+		if depth >= self.settings.cave_min_depth {
+			let scale = self.settings.cave_scale;
+			let cave = fractal_2d(self.seed ^ CAVE_SALT, x as f32 / scale, y as f32 / scale, 3);
+			if cave > self.settings.cave_threshold {
+				return self.blocks.air;
+			}
+		}
+
+		if depth < 1.0 {
+			column.surface
+		} else if depth < dirt_depth {
+			column.filler
+		} else {
+			for (i, (block, ore)) in self.blocks.ores.iter().enumerate() {
+				if depth >= ore.min_depth {
+					let seed = (self.seed ^ ORE_SALT).wrapping_add(i as u64);
+					let noise = fractal_2d(seed, x as f32 / ore.scale, y as f32 / ore.scale, 2);
+					if noise > ore.threshold {
+						return *block;
+					}
+				}
+			}
+			column.underground
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::world::chunk::test::block;
+
+	fn generator(seed: u64) -> WorldGenerator {
+		let mut settings = GenSettings::default();
+		settings.ores.truncate(1);
+		let ore = settings.ores[0].clone();
+		WorldGenerator::new(
+			seed,
+			settings,
+			TerrainBlocks {
+				tile: unsafe { Id::new(0) },
+				wall: None,
+				air: block(0),
+				dirt: block(1),
+				grass: block(2),
+				stone: block(3),
+				wall_dirt: None,
+				ores: vec![(block(4), ore)],
+				biomes: vec![],
+				defaults: [(unsafe { Id::new(0) }, block(0))].into_iter().collect(),
+			},
+		)
+	}
+
+	fn snapshot(generator: &WorldGenerator) -> Vec<u8> {
+		let mut out = Vec::new();
+		for y in 6..12 {
+			for x in 0..4 {
+				let chunk = generator.generate(ChunkPos { x, y });
+				out.extend(bincode::serialize(&chunk).unwrap());
+			}
+		}
+		out
+	}
+
+	#[test]
+	fn deterministic() {
+		assert!(snapshot(&generator(420)) == snapshot(&generator(420)));
+		assert!(snapshot(&generator(420)) != snapshot(&generator(69)));
+	}
+
+	#[test]
+	fn surface_is_grass_over_dirt() {
+		let generator = generator(7);
+		for x in 0..64 {
+			let surface = generator.surface_height(x);
+			let (_, column) = generator.column(x);
+			assert!(generator.tile(x, surface, 0.0, 4.0, column).id == block(2).id);
+			assert!(generator.tile(x, surface + 1, -1.0, 4.0, column).id == block(0).id);
+			assert!(generator.tile(x, surface - 1, 1.0, 4.0, column).id == block(1).id);
+		}
+	}
+
+	#[test]
+	fn columns_use_the_closest_biome() {
+		let mut generator = generator(7);
+		let biome = |id: usize, temperature: f32, surface: usize| GenBiome {
+			id: unsafe { Id::new(id) },
+			temperature,
+			humidity: 0.5,
+			blocks: ColumnBlocks {
+				surface: block(surface),
+				filler: block(1),
+				underground: block(3),
+			},
+		};
+		generator.blocks.biomes = vec![biome(0, 0.0, 2), biome(1, 1.0, 5)];
+
+		let mut seen = [false; 2];
+		for x in (0..100_000).step_by(97) {
+			let (id, column) = generator.column(x);
+			let id = id.unwrap().index();
+			seen[id] = true;
+			assert!(column.surface.id == block([2, 5][id]).id);
+		}
+		assert_eq!(seen, [true, true]);
+
+		let chunk = generator.generate(ChunkPos { x: 0, y: 10 });
+		assert!(chunk.biomes.get(3) == generator.column(3).0);
+	}
+}
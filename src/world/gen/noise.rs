@@ -0,0 +1,83 @@
+//! Seeded value noise.
+//!
+//! Everything here is pure integer hashing plus a bit of interpolation,
+//! so the same seed and coordinates always give the same value.
+
+#[inline]
+pub fn hash(seed: u64, x: i64, y: i64) -> u64 {
+	// splitmix64 finalizer over the combined inputs.
+	let mut v = seed
+		^ (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+		^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+	v = (v ^ (v >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+	v = (v ^ (v >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+	v ^ (v >> 31)
+}
+
+/// A value between 0.0 and 1.0 for this exact lattice point.
+#[inline]
+pub fn value(seed: u64, x: i64, y: i64) -> f32 {
+	(hash(seed, x, y) >> 40) as f32 / (1u64 << 24) as f32
+}
+
+#[inline]
+fn smooth(t: f32) -> f32 { t * t * (3.0 - 2.0 * t) }
+
+#[inline]
+fn lerp(a: f32, b: f32, t: f32) -> f32 { a + (b - a) * t }
+
+/// Smooth 1D noise between 0.0 and 1.0.
+pub fn noise_1d(seed: u64, x: f32) -> f32 {
+	let x0 = x.floor();
+	let t = smooth(x - x0);
+	let x0 = x0 as i64;
+	lerp(value(seed, x0, 0), value(seed, x0 + 1, 0), t)
+}
+
+/// Smooth 2D noise between 0.0 and 1.0.
+pub fn noise_2d(seed: u64, x: f32, y: f32) -> f32 {
+	let x0 = x.floor();
+	let y0 = y.floor();
+	let tx = smooth(x - x0);
+	let ty = smooth(y - y0);
+	let x0 = x0 as i64;
+	let y0 = y0 as i64;
+
+	let bottom = lerp(value(seed, x0, y0), value(seed, x0 + 1, y0), tx);
+	let top = lerp(value(seed, x0, y0 + 1), value(seed, x0 + 1, y0 + 1), tx);
+	lerp(bottom, top, ty)
+}
+
+/// Layers multiple octaves of [`noise_1d`], the result stays between 0.0 and 1.0.
+pub fn fractal_1d(seed: u64, x: f32, octaves: u32) -> f32 {
+	let mut total = 0.0;
+	let mut amplitude = 1.0;
+	let mut frequency = 1.0;
+	let mut max = 0.0;
+	for octave in 0..octaves {
+		let seed = seed.wrapping_add(octave as u64);
+		total += noise_1d(seed, x * frequency) * amplitude;
+		max += amplitude;
+		amplitude *= 0.5;
+		frequency *= 2.0;
+	}
+
+	total / max
+}
+
+/// Layers multiple octaves of [`noise_2d`], the result stays between 0.0 and 1.0.
+pub fn fractal_2d(seed: u64, x: f32, y: f32, octaves: u32) -> f32 {
+	let mut total = 0.0;
+	let mut amplitude = 1.0;
+	let mut frequency = 1.0;
+	let mut max = 0.0;
+	for octave in 0..octaves {
+		let seed = seed.wrapping_add(octave as u64);
+		total += noise_2d(seed, x * frequency, y * frequency) * amplitude;
+		max += amplitude;
+		amplitude *= 0.5;
+		frequency *= 2.0;
+	}
+
+	total / max
+}
@@ -0,0 +1,292 @@
+//! Lua defined generation passes.
+//!
+//! Plugins register entries in the `world_gen` registry, each entry is a pass that runs after the
+//! builtin terrain. Passes run in registry order, so the registry `priority` decides which pass goes first.
+//! ```lua
+//! reload.stargate.world_gen:register {
+//!     [{ name = "ores", priority = 10 }] = {
+//!         generate = function(view, rng)
+//!             if rng:chance(0.1) then
+//!                 view:set_block("tile", rng:range(0, 15), rng:range(0, 15), "iron_ore")
+//!             end
+//!         end
+//!     }
+//! }
+//! ```
+use std::sync::Arc;
+
+use apollo::{impl_macro::*, Function, LuaScope, Value};
+use eyre::{ContextCompat, Result};
+use fxhash::FxHashMap;
+use rand::Rng;
+use tracing::{error, error_span};
+
+use crate::{
+	api::{id_table::IdTable, luna::table::LunaTable, prototype::Prototype, Api},
+	ty::{block_layer_pos::BlockLayerPos, id::Id, identifier::Identifier},
+	world::{
+		biome::BiomeDesc,
+		chunk::{
+			block::{Block, BlockDesc},
+			block_entity::BlockEntity,
+			layer::BlockLayer,
+			Chunk, CHUNK_SIZE,
+		},
+		random::{self, WorldRng},
+	},
+	ChunkPos,
+};
+
+pub struct WorldGenDesc {
+	pub generate: Function,
+}
+
+#[lua_impl]
+impl WorldGenDesc {}
+
+pub struct WorldGenPrototype {
+	pub generate: Function,
+}
+
+impl WorldGenPrototype {
+	pub fn bake(self) -> WorldGenDesc {
+		WorldGenDesc {
+			generate: self.generate,
+		}
+	}
+}
+
+impl Prototype for WorldGenPrototype {
+	type Output = WorldGenDesc;
+
+	fn get_name() -> &'static str { "world_gen" }
+
+	fn from_lua(table: LunaTable) -> Result<Self> {
+		let _span = error_span!(target: "lua", "world_gen").entered();
+		Ok(WorldGenPrototype {
+			generate: table.get("generate")?,
+		})
+	}
+}
+
+/// Resolves identifiers used by Lua passes to the blocks and biomes of the current registries.
+pub struct BlockLookup {
+	layers: FxHashMap<Identifier, Id<BlockLayer>>,
+	blocks: IdTable<BlockLayer, FxHashMap<Identifier, (Block, Option<BlockEntity>)>>,
+	identifiers: IdTable<BlockLayer, IdTable<BlockDesc, String>>,
+	biomes: FxHashMap<Identifier, Id<BiomeDesc>>,
+	biome_identifiers: IdTable<BiomeDesc, String>,
+}
+
+impl BlockLookup {
+	pub fn new(api: &Api) -> BlockLookup {
+		let layers = &api.carrier.block_layer;
+		BlockLookup {
+			layers: layers.ident_to_id.clone(),
+			blocks: layers
+				.table
+				.iter()
+				.map(|(id, layer)| {
+					(
+						id,
+						layer
+							.blocks
+							.entries()
+							.map(|(block_id, ident, desc)| {
+								let block = desc.create(block_id);
+								(ident.clone(), (block, desc.create_block_entity()))
+							})
+							.collect(),
+					)
+				})
+				.collect(),
+			identifiers: layers
+				.table
+				.iter()
+				.map(|(id, layer)| {
+					(
+						id,
+						layer
+							.blocks
+							.entries()
+							.map(|(block_id, ident, _)| (block_id, ident.to_string()))
+							.collect(),
+					)
+				})
+				.collect(),
+			biomes: api.carrier.biome.ident_to_id.clone(),
+			biome_identifiers: api
+				.carrier
+				.biome
+				.entries()
+				.map(|(id, ident, _)| (id, ident.to_string()))
+				.collect(),
+		}
+	}
+
+	pub(crate) fn layer(&self, layer: &Identifier) -> Result<Id<BlockLayer>> {
+		self.layers
+			.get(layer)
+			.copied()
+			.wrap_err_with(|| format!("Layer {layer} does not exist"))
+	}
+
+	/// A freshly placed block and its block entity.
+	pub(crate) fn block(
+		&self,
+		layer: &Identifier,
+		block: &Identifier,
+	) -> Result<(Id<BlockLayer>, &(Block, Option<BlockEntity>))> {
+		let layer_id = self.layer(layer)?;
+		let entry = self
+			.blocks
+			.get(layer_id)
+			.get(block)
+			.wrap_err_with(|| format!("Block {block} does not exist in {layer}"))?;
+		Ok((layer_id, entry))
+	}
+
+	pub(crate) fn identifier(&self, layer_id: Id<BlockLayer>, block_id: Id<BlockDesc>) -> &str {
+		self.identifiers.get(layer_id).get(block_id)
+	}
+
+	pub(crate) fn biome(&self, biome: &Identifier) -> Result<Id<BiomeDesc>> {
+		self.biomes
+			.get(biome)
+			.copied()
+			.wrap_err_with(|| format!("Biome {biome} does not exist"))
+	}
+
+	pub(crate) fn biome_identifier(&self, biome: Id<BiomeDesc>) -> &str {
+		self.biome_identifiers.get(biome)
+	}
+}
+
+#[derive(Clone)]
+pub struct GenPasses {
+	passes: Vec<(Identifier, Function)>,
+	lookup: Arc<BlockLookup>,
+}
+
+impl GenPasses {
+	pub fn new(api: &Api) -> GenPasses {
+		GenPasses {
+			passes: api
+				.carrier
+				.world_gen
+				.entries()
+				.map(|(_, identifier, desc)| (identifier.clone(), desc.generate.clone()))
+				.collect(),
+			lookup: Arc::new(BlockLookup::new(api)),
+		}
+	}
+
+	pub fn is_empty(&self) -> bool { self.passes.is_empty() }
+
+	pub fn apply(&self, seed: u64, pos: ChunkPos, chunk: Chunk) -> Chunk {
+		let mut view = ChunkGenView {
+			pos,
+			chunk,
+			lookup: self.lookup.clone(),
+		};
+
+		for (identifier, generate) in &self.passes {
+			// Every pass gets a stream named after it so adding or reordering passes does not
+			// shift the others.
+			let mut rng = GenRandom {
+				rand: random::chunk_stream(seed, &identifier.to_string(), pos),
+			};
+
+			let view_scope = LuaScope::from(&mut view);
+			let rng_scope = LuaScope::from(&mut rng);
+			if let Err(err) = generate.call::<_, Value>((view_scope.lua(), rng_scope.lua())) {
+				error!(target: "lua", "World gen pass {identifier} failed at {pos:?}: {err}");
+			}
+		}
+
+		view.chunk
+	}
+}
+
+/// The writable chunk handed to Lua passes.
+/// Positions are local to the chunk, ranging from 0 to 15.
+pub struct ChunkGenView {
+	pos: ChunkPos,
+	chunk: Chunk,
+	lookup: Arc<BlockLookup>,
+}
+
+impl ChunkGenView {
+	fn entry(x: u8, y: u8) -> Result<BlockLayerPos> {
+		BlockLayerPos::try_new(x, y).wrap_err_with(|| format!("{x}, {y} is outside of the chunk"))
+	}
+}
+
+#[lua_impl]
+impl ChunkGenView {
+	/// The world x of the leftmost column.
+	#[lua_field(get x)]
+	pub fn get_x(&mut self) -> Result<i64> { Ok(self.pos.x as i64 * CHUNK_SIZE as i64) }
+
+	/// The world y of the bottom row.
+	#[lua_field(get y)]
+	pub fn get_y(&mut self) -> Result<i64> { Ok(self.pos.y as i64 * CHUNK_SIZE as i64) }
+
+	#[lua_field(get size)]
+	pub fn get_size(&mut self) -> Result<u8> { Ok(CHUNK_SIZE as u8) }
+
+	#[lua_method]
+	pub fn get_block(&self, layer: Identifier, x: u8, y: u8) -> Result<String> {
+		let layer_id = self.lookup.layer(&layer)?;
+		let block = self.chunk.layers.get(layer_id)[Self::entry(x, y)?];
+		Ok(self.lookup.identifier(layer_id, block.id).to_string())
+	}
+
+	#[lua_method]
+	pub fn set_block(&mut self, layer: Identifier, x: u8, y: u8, block: Identifier) -> Result<()> {
+		let entry = Self::entry(x, y)?;
+		let (layer_id, (block, block_entity)) = self.lookup.block(&layer, &block)?;
+		self.chunk.set_block(layer_id, entry, *block, block_entity.clone());
+		Ok(())
+	}
+
+	/// The biome identifier of a column, nil if it has none.
+	#[lua_method]
+	pub fn get_biome(&self, x: u8) -> Result<Option<String>> {
+		let entry = Self::entry(x, 0)?;
+		let biome = self.chunk.biomes.get(entry.x());
+		Ok(biome.map(|biome| self.lookup.biome_identifier(biome).to_string()))
+	}
+
+	#[lua_method]
+	pub fn set_biome(&mut self, x: u8, biome: Identifier) -> Result<()> {
+		let entry = Self::entry(x, 0)?;
+		let biome = self.lookup.biome(&biome)?;
+		self.chunk.biomes.set(entry.x(), Some(biome));
+		Ok(())
+	}
+}
+
+/// A random stream seeded from the world seed, the chunk position and the pass.
+pub struct GenRandom {
+	rand: WorldRng,
+}
+
+#[lua_impl]
+impl GenRandom {
+	/// A number from 0.0 up to 1.0.
+	#[lua_method]
+	pub fn next(&mut self) -> f64 { self.rand.gen_range(0.0..1.0) }
+
+	/// A whole number from `min` up to and including `max`.
+	#[lua_method]
+	pub fn range(&mut self, min: i64, max: i64) -> Result<i64> {
+		if min > max {
+			eyre::bail!("min {min} is bigger than max {max}");
+		}
+		Ok(self.rand.gen_range(min..=max))
+	}
+
+	#[lua_method]
+	pub fn chance(&mut self, chance: f64) -> bool { self.rand.gen_range(0.0..1.0) < chance }
+}
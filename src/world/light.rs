@@ -0,0 +1,452 @@
+//! Tile lighting.
+//!
+//! Every block position has two light values from 0 to [`MAX_LIGHT`], which are stored per chunk
+//! next to the block layers:
+//! - Sky light which enters from the top of the world and falls straight down through open blocks.
+//! - Block light which floods out of emissive blocks like torches.
+//!
+//! Light loses one level per block it travels, and the `light_opacity` of the block it leaves.
+//! Blocks declare how they interact with light in their prototype:
+//! ```lua
+//! ["torch"] = { collision = false, light_emission = 14 },
+//! ["glass"] = { collision = true, light_opacity = 0 },
+//! ```
+//! Solid blocks in layers with collision default to an opacity of [`DEFAULT_SOLID_OPACITY`],
+//! everything else lets light through.
+//!
+//! Light gets updated incrementally, only the blocks around a change get recalculated,
+//! even when that spills over into other chunks.
+use std::collections::VecDeque;
+
+use fxhash::FxHashSet;
+
+use crate::{
+	api::{id_table::IdTable, Api},
+	ty::{block_layer_pos::BlockLayerPos, block_pos::BlockPos, Offset},
+	world::chunk::{block::BlockDesc, layer::BlockLayer, Chunk, ChunkLayer, CHUNK_SIZE},
+	ChunkPos, ChunkStorage,
+};
+
+pub const MAX_LIGHT: u8 = 15;
+pub const DEFAULT_SOLID_OPACITY: u8 = 4;
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct ChunkLight {
+	pub sky: ChunkLayer<u8>,
+	pub block: ChunkLayer<u8>,
+}
+
+impl ChunkLight {
+	pub fn dark() -> ChunkLight {
+		ChunkLight {
+			sky: ChunkLayer::new_copy(0),
+			block: ChunkLayer::new_copy(0),
+		}
+	}
+
+	/// The brightest of both channels.
+	pub fn get(&self, entry: BlockLayerPos) -> u8 {
+		(*self.sky.get(entry)).max(*self.block.get(entry))
+	}
+
+	fn channel(&self, channel: LightChannel) -> &ChunkLayer<u8> {
+		match channel {
+			LightChannel::Sky => &self.sky,
+			LightChannel::Block => &self.block,
+		}
+	}
+
+	fn channel_mut(&mut self, channel: LightChannel) -> &mut ChunkLayer<u8> {
+		match channel {
+			LightChannel::Sky => &mut self.sky,
+			LightChannel::Block => &mut self.block,
+		}
+	}
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LightChannel {
+	Sky,
+	Block,
+}
+
+impl LightChannel {
+	const ALL: [LightChannel; 2] = [LightChannel::Sky, LightChannel::Block];
+}
+
+/// How a single block interacts with light.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct BlockLight {
+	pub emission: u8,
+	pub opacity: u8,
+}
+
+impl BlockLight {
+	pub fn new(desc: &BlockDesc) -> BlockLight {
+		BlockLight {
+			emission: desc.light_emission,
+			opacity: desc.light_opacity,
+		}
+	}
+}
+
+const NEIGHBORS: [(i8, i8); 4] = [(0, -1), (0, 1), (-1, 0), (1, 0)];
+const EDGE: u8 = CHUNK_SIZE as u8 - 1;
+
+pub struct LightEngine {
+	/// Blocks at or above this height see the sky when the chunk above them is not loaded.
+	sky_level: i64,
+	blocks: IdTable<BlockLayer, IdTable<BlockDesc, BlockLight>>,
+	changed: FxHashSet<ChunkPos>,
+}
+
+impl LightEngine {
+	pub fn new(
+		sky_level: i64,
+		blocks: IdTable<BlockLayer, IdTable<BlockDesc, BlockLight>>,
+	) -> LightEngine {
+		LightEngine {
+			sky_level,
+			blocks,
+			changed: Default::default(),
+		}
+	}
+
+	pub fn from_api(api: &Api, sky_level: i64) -> LightEngine {
+		LightEngine::new(
+			sky_level,
+			api.carrier
+				.block_layer
+				.table
+				.iter()
+				.map(|(id, layer)| {
+					(
+						id,
+						layer
+							.blocks
+							.table
+							.iter()
+							.map(|(block_id, desc)| (block_id, BlockLight::new(desc)))
+							.collect(),
+					)
+				})
+				.collect(),
+		)
+	}
+
+	pub fn get(&self, chunks: &ChunkStorage, channel: LightChannel, pos: BlockPos) -> Option<u8> {
+		Some(*chunks.get(pos.chunk)?.light.channel(channel).get(pos.entry))
+	}
+
+	/// Lights a chunk which just got inserted, and updates the chunks around it.
+	pub fn light_chunk(&mut self, chunks: &mut ChunkStorage, pos: ChunkPos) {
+		match chunks.get_mut_untracked(pos) {
+			Some(chunk) => chunk.light = ChunkLight::dark(),
+			None => return,
+		}
+		chunks.mark_light(pos);
+		self.changed.insert(pos);
+
+		for channel in LightChannel::ALL {
+			let mut increase = VecDeque::new();
+
+			// The chunk below saw the sky through this chunk while it was missing.
+			if channel == LightChannel::Sky {
+				if let Some(below) = pos.checked_offset((0, -1)) {
+					if chunks.contains(below) {
+						let top = (0..CHUNK_SIZE as u8)
+							.map(|x| BlockPos::new(below, BlockLayerPos::new(x, EDGE)))
+							.collect();
+						increase = self.decrease(chunks, channel, top);
+					}
+				}
+			}
+
+			for y in 0..CHUNK_SIZE as u8 {
+				for x in 0..CHUNK_SIZE as u8 {
+					let block = BlockPos::new(pos, BlockLayerPos::new(x, y));
+					let source = self.source(chunks, channel, block);
+					if source > 0 {
+						self.set(chunks, channel, block, source);
+						increase.push_back(block);
+					}
+
+					// Let the neighbors shine in.
+					if x == 0 || y == 0 || x == EDGE || y == EDGE {
+						for offset in NEIGHBORS {
+							if let Some(neighbor) = block.checked_offset(offset) {
+								if neighbor.chunk != pos {
+									increase.push_back(neighbor);
+								}
+							}
+						}
+					}
+				}
+			}
+
+			self.increase(chunks, channel, increase);
+		}
+	}
+
+	/// Updates the light around a block which just changed.
+	pub fn block_changed(&mut self, chunks: &mut ChunkStorage, pos: BlockPos) {
+		for channel in LightChannel::ALL {
+			let mut increase = self.decrease(chunks, channel, vec![pos]);
+			for offset in NEIGHBORS {
+				if let Some(neighbor) = pos.checked_offset(offset) {
+					increase.push_back(neighbor);
+				}
+			}
+			self.increase(chunks, channel, increase);
+		}
+	}
+
+	/// Chunks whose light changed since the last call.
+	pub fn drain_changes(&mut self) -> impl Iterator<Item = ChunkPos> + '_ { self.changed.drain() }
+
+	/// Darkens everything which might have been lit through `start`.
+	/// Returns the positions which need to spread their light again.
+	fn decrease(
+		&mut self,
+		chunks: &mut ChunkStorage,
+		channel: LightChannel,
+		start: Vec<BlockPos>,
+	) -> VecDeque<BlockPos> {
+		let mut queue = VecDeque::new();
+		for pos in start {
+			if let Some(light) = self.get(chunks, channel, pos) {
+				self.set(chunks, channel, pos, 0);
+				queue.push_back((pos, light));
+			}
+		}
+
+		let mut increase = VecDeque::new();
+		while let Some((pos, old)) = queue.pop_front() {
+			let source = self.source(chunks, channel, pos);
+			if source > 0 {
+				self.set(chunks, channel, pos, source);
+				increase.push_back(pos);
+			}
+
+			for offset in NEIGHBORS {
+				let neighbor = match pos.checked_offset(offset) {
+					Some(neighbor) => neighbor,
+					None => continue,
+				};
+				let light = match self.get(chunks, channel, neighbor) {
+					Some(light) if light > 0 => light,
+					_ => continue,
+				};
+
+				// We do not know the old opacity, so assume the most light could have passed.
+				if light <= propagate(channel, old, 0, offset) {
+					self.set(chunks, channel, neighbor, 0);
+					queue.push_back((neighbor, light));
+				} else {
+					increase.push_back(neighbor);
+				}
+			}
+		}
+
+		increase
+	}
+
+	fn increase(
+		&mut self,
+		chunks: &mut ChunkStorage,
+		channel: LightChannel,
+		mut queue: VecDeque<BlockPos>,
+	) {
+		while let Some(pos) = queue.pop_front() {
+			let (light, opacity) = match chunks.get(pos.chunk) {
+				Some(chunk) => (
+					*chunk.light.channel(channel).get(pos.entry),
+					self.block_light(chunk, pos.entry).opacity,
+				),
+				None => continue,
+			};
+			if light == 0 {
+				continue;
+			}
+
+			for offset in NEIGHBORS {
+				if let Some(neighbor) = pos.checked_offset(offset) {
+					if let Some(current) = self.get(chunks, channel, neighbor) {
+						let light = propagate(channel, light, opacity, offset);
+						if light > current {
+							self.set(chunks, channel, neighbor, light);
+							queue.push_back(neighbor);
+						}
+					}
+				}
+			}
+		}
+	}
+
+	/// The light a block produces on its own.
+	fn source(&self, chunks: &ChunkStorage, channel: LightChannel, pos: BlockPos) -> u8 {
+		match channel {
+			LightChannel::Block => chunks
+				.get(pos.chunk)
+				.map_or(0, |chunk| self.block_light(chunk, pos.entry).emission),
+			LightChannel::Sky => {
+				let above_loaded = pos
+					.checked_offset((0, 1))
+					.map_or(false, |above| chunks.contains(above.chunk));
+				if !above_loaded && pos.y() >= self.sky_level {
+					MAX_LIGHT
+				} else {
+					0
+				}
+			}
+		}
+	}
+
+	/// Combines every layer, the brightest emission and the strongest opacity win.
+	fn block_light(&self, chunk: &Chunk, entry: BlockLayerPos) -> BlockLight {
+		let mut out = BlockLight::default();
+		for (layer_id, layer) in chunk.layers.iter() {
+			let block = layer.get(entry);
+			let light = self.blocks.get(layer_id).get(block.id);
+			out.emission = out.emission.max(light.emission);
+			out.opacity = out.opacity.max(light.opacity);
+		}
+		out
+	}
+
+	fn set(&mut self, chunks: &mut ChunkStorage, channel: LightChannel, pos: BlockPos, light: u8) {
+		if let Some(chunk) = chunks.get_mut_untracked(pos.chunk) {
+			chunk.light.channel_mut(channel).set(pos.entry, light);
+			chunks.mark_light(pos.chunk);
+			self.changed.insert(pos.chunk);
+		}
+	}
+}
+
+/// The light that reaches a neighbor at `offset`.
+fn propagate(channel: LightChannel, light: u8, opacity: u8, offset: (i8, i8)) -> u8 {
+	// Unobstructed sky light falls down forever.
+	if channel == LightChannel::Sky && offset == (0, -1) && light == MAX_LIGHT && opacity == 0 {
+		return MAX_LIGHT;
+	}
+
+	light.saturating_sub(opacity).saturating_sub(1)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		ty::id::Id,
+		world::chunk::test::{block, chunk},
+	};
+
+	const AIR: usize = 0;
+	const STONE: usize = 1;
+	const TORCH: usize = 2;
+
+	fn layer() -> Id<BlockLayer> { unsafe { Id::new(0) } }
+
+	fn engine(sky_level: i64) -> LightEngine {
+		let blocks: IdTable<BlockDesc, BlockLight> = vec![
+			BlockLight::default(),
+			BlockLight {
+				emission: 0,
+				opacity: MAX_LIGHT,
+			},
+			BlockLight {
+				emission: 12,
+				opacity: 0,
+			},
+		]
+		.into_iter()
+		.enumerate()
+		.map(|(id, light)| (unsafe { Id::new(id) }, light))
+		.collect();
+		LightEngine::new(sky_level, vec![(layer(), blocks)].into_iter().collect())
+	}
+
+	fn insert(engine: &mut LightEngine, chunks: &mut ChunkStorage, pos: ChunkPos, fill: usize) {
+		chunks.insert(pos, chunk(layer(), fill));
+		engine.light_chunk(chunks, pos);
+	}
+
+	fn place(engine: &mut LightEngine, chunks: &mut ChunkStorage, x: i64, y: i64, id: usize) {
+		let pos = BlockPos::from_block(x, y).unwrap();
+		chunks
+			.get_mut(pos.chunk)
+			.unwrap()
+			.layers
+			.get_mut(layer())
+			.set(pos.entry, block(id));
+		engine.block_changed(chunks, pos);
+	}
+
+	fn light(
+		engine: &LightEngine,
+		chunks: &ChunkStorage,
+		channel: LightChannel,
+		x: i64,
+		y: i64,
+	) -> u8 {
+		engine
+			.get(chunks, channel, BlockPos::from_block(x, y).unwrap())
+			.unwrap()
+	}
+
+	#[test]
+	fn torch_light_crosses_chunks_and_goes_away() {
+		let mut engine = engine(i64::MAX);
+		let mut chunks = ChunkStorage::unbounded();
+		insert(&mut engine, &mut chunks, ChunkPos { x: 0, y: 0 }, AIR);
+		insert(&mut engine, &mut chunks, ChunkPos { x: 1, y: 0 }, AIR);
+
+		place(&mut engine, &mut chunks, 15, 8, TORCH);
+		assert_eq!(light(&engine, &chunks, LightChannel::Block, 15, 8), 12);
+		assert_eq!(light(&engine, &chunks, LightChannel::Block, 16, 8), 11);
+		assert_eq!(light(&engine, &chunks, LightChannel::Block, 20, 8), 7);
+		assert_eq!(light(&engine, &chunks, LightChannel::Block, 14, 9), 10);
+		assert_eq!(light(&engine, &chunks, LightChannel::Sky, 15, 8), 0);
+
+		// Blocked by stone on one side.
+		place(&mut engine, &mut chunks, 16, 8, STONE);
+		assert_eq!(light(&engine, &chunks, LightChannel::Block, 17, 8), 8);
+
+		place(&mut engine, &mut chunks, 15, 8, AIR);
+		for x in 0..32 {
+			for y in 0..16 {
+				assert_eq!(light(&engine, &chunks, LightChannel::Block, x, y), 0);
+			}
+		}
+	}
+
+	#[test]
+	fn sky_light_falls_through_holes() {
+		let mut engine = engine(0);
+		let mut chunks = ChunkStorage::unbounded();
+		insert(&mut engine, &mut chunks, ChunkPos { x: 0, y: 0 }, AIR);
+		for x in 0..16 {
+			place(&mut engine, &mut chunks, x, 8, STONE);
+		}
+		assert_eq!(light(&engine, &chunks, LightChannel::Sky, 4, 9), MAX_LIGHT);
+		assert_eq!(light(&engine, &chunks, LightChannel::Sky, 4, 8), MAX_LIGHT);
+		assert_eq!(light(&engine, &chunks, LightChannel::Sky, 4, 7), 0);
+
+		place(&mut engine, &mut chunks, 4, 8, AIR);
+		assert_eq!(light(&engine, &chunks, LightChannel::Sky, 4, 0), MAX_LIGHT);
+		assert_eq!(light(&engine, &chunks, LightChannel::Sky, 6, 0), MAX_LIGHT - 2);
+	}
+
+	#[test]
+	fn loading_a_chunk_above_blocks_the_sky() {
+		let mut engine = engine(0);
+		let mut chunks = ChunkStorage::unbounded();
+		insert(&mut engine, &mut chunks, ChunkPos { x: 0, y: 0 }, AIR);
+		assert_eq!(light(&engine, &chunks, LightChannel::Sky, 3, 0), MAX_LIGHT);
+
+		insert(&mut engine, &mut chunks, ChunkPos { x: 0, y: 1 }, STONE);
+		assert_eq!(light(&engine, &chunks, LightChannel::Sky, 3, 31), MAX_LIGHT);
+		assert_eq!(light(&engine, &chunks, LightChannel::Sky, 3, 15), 0);
+		assert_eq!(light(&engine, &chunks, LightChannel::Sky, 3, 0), 0);
+	}
+}
@@ -0,0 +1,481 @@
+//! Liquids flowing through the world.
+//!
+//! Every block position can hold a single liquid and an amount of it, stored per chunk next to the block layers.
+//! Liquids are registered from Lua:
+//! ```lua
+//! reload.stargate.liquid:register {
+//!     ["water"] = {},
+//!     ["honey"] = { viscosity = 8 },
+//!     ["lava"] = {
+//!         viscosity = 4,
+//!         reactions = {
+//!             ["water"] = { layer = "tile", block = "stone" }
+//!         }
+//!     }
+//! }
+//! ```
+//! Each tick a liquid first flows down and then spreads sideways, `viscosity` is how many ticks it waits between moves.
+//! Only cells which changed or had a neighbor change get simulated, so settled liquids cost nothing.
+//! When two liquids meet and either one has a reaction for the other, the cell they met at turns into the reaction block.
+use std::collections::HashMap;
+
+use apollo::{impl_macro::*, FromLua, Lua, Value};
+use eyre::{ContextCompat, Result, WrapErr};
+use fxhash::{FxHashMap, FxHashSet};
+use tracing::error_span;
+
+use crate::{
+	api::{luna::table::LunaTable, prototype::Prototype, registry::Registry, util::lua_table},
+	ty::{
+		block_layer_pos::BlockLayerPos, block_pos::BlockPos, id::Id, identifier::Identifier, Offset,
+	},
+	world::chunk::{block::BlockDesc, layer::BlockLayer, Chunk},
+	ChunkPos, ChunkStorage,
+};
+
+/// The amount of a completely filled cell.
+pub const MAX_LIQUID: u8 = 255;
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+pub struct LiquidCell {
+	pub liquid: Option<Id<LiquidDesc>>,
+	pub amount: u8,
+}
+
+impl LiquidCell {
+	pub const EMPTY: LiquidCell = LiquidCell {
+		liquid: None,
+		amount: 0,
+	};
+
+	pub fn new(liquid: Id<LiquidDesc>, amount: u8) -> LiquidCell {
+		if amount == 0 {
+			LiquidCell::EMPTY
+		} else {
+			LiquidCell {
+				liquid: Some(liquid),
+				amount,
+			}
+		}
+	}
+}
+
+pub struct LiquidDesc {
+	pub viscosity: u32,
+	/// The block placed when this liquid meets another one.
+	pub reactions: FxHashMap<Id<LiquidDesc>, (Id<BlockLayer>, Id<BlockDesc>)>,
+}
+
+#[lua_impl]
+impl LiquidDesc {
+	#[lua_method]
+	pub fn get_viscosity(&self) -> u32 { self.viscosity }
+}
+
+pub struct LiquidPrototype {
+	pub viscosity: u32,
+	pub reactions: HashMap<Identifier, LiquidReactionPrototype>,
+}
+
+impl LiquidPrototype {
+	pub fn bake(
+		self,
+		liquids: &HashMap<Identifier, Id<LiquidDesc>>,
+		block_layers: &Registry<BlockLayer>,
+	) -> Result<LiquidDesc> {
+		if self.viscosity == 0 {
+			eyre::bail!("Viscosity must be at least 1");
+		}
+
+		let mut reactions = FxHashMap::default();
+		for (liquid, reaction) in self.reactions {
+			let liquid_id = *liquids
+				.get(&liquid)
+				.wrap_err_with(|| format!("Could not find liquid {liquid}"))?;
+			let layer_id = block_layers
+				.get_id(&reaction.layer)
+				.wrap_err_with(|| format!("Could not find layer {}", reaction.layer))?;
+			let block_id = block_layers
+				.get(layer_id)
+				.blocks
+				.get_id(&reaction.block)
+				.wrap_err_with(|| format!("Could not find block {}", reaction.block))?;
+			reactions.insert(liquid_id, (layer_id, block_id));
+		}
+
+		Ok(LiquidDesc {
+			viscosity: self.viscosity,
+			reactions,
+		})
+	}
+}
+
+impl Prototype for LiquidPrototype {
+	type Output = LiquidDesc;
+
+	fn get_name() -> &'static str { "liquid" }
+
+	fn from_lua(table: LunaTable) -> Result<Self> {
+		let _span = error_span!(target: "lua", "liquid").entered();
+		let viscosity: Option<u32> = table.get("viscosity")?;
+		let reactions: Option<HashMap<Identifier, LiquidReactionPrototype>> =
+			table.get("reactions")?;
+		Ok(LiquidPrototype {
+			viscosity: viscosity.unwrap_or(1),
+			reactions: reactions.unwrap_or_default(),
+		})
+	}
+}
+
+pub struct LiquidReactionPrototype {
+	pub layer: Identifier,
+	pub block: Identifier,
+}
+
+impl FromLua for LiquidReactionPrototype {
+	fn from_lua(lua_value: Value, _: &Lua) -> Result<Self> {
+		let table = lua_table(lua_value)?;
+		Ok(LiquidReactionPrototype {
+			layer: table.get("layer")?,
+			block: table.get("block")?,
+		})
+	}
+}
+
+pub struct LiquidSystem {
+	ticks: u64,
+	active: FxHashSet<BlockPos>,
+	changed: FxHashSet<ChunkPos>,
+}
+
+impl LiquidSystem {
+	pub fn new() -> LiquidSystem {
+		LiquidSystem {
+			ticks: 0,
+			active: Default::default(),
+			changed: Default::default(),
+		}
+	}
+
+	/// If no liquid is going to move until something changes.
+	pub fn is_settled(&self) -> bool { self.active.is_empty() }
+
+	pub fn get(&self, chunks: &ChunkStorage, pos: BlockPos) -> Option<LiquidCell> {
+		Some(*chunks.get(pos.chunk)?.liquids.get(pos.entry))
+	}
+
+	pub fn set(&mut self, chunks: &mut ChunkStorage, pos: BlockPos, cell: LiquidCell) {
+		if let Some(chunk) = chunks.get_mut_untracked(pos.chunk) {
+			chunk.liquids.set(pos.entry, cell);
+			chunks.mark_liquids(pos.chunk);
+			self.changed.insert(pos.chunk);
+			self.wake(pos);
+		}
+	}
+
+	/// Lets the liquids at and around `pos` move again, used when something next to them changed.
+	pub fn wake(&mut self, pos: BlockPos) {
+		self.active.insert(pos);
+		for offset in [(0, 1), (0, -1), (-1, 0), (1, 0)] {
+			if let Some(pos) = pos.checked_offset(offset) {
+				self.active.insert(pos);
+			}
+		}
+	}
+
+	/// Wakes every liquid in a chunk which just got loaded or generated.
+	pub fn wake_chunk(&mut self, pos: ChunkPos, chunk: &Chunk) {
+		if chunk.liquids.is_uniform() && chunk.liquids.palette()[0] == LiquidCell::EMPTY {
+			return;
+		}
+
+		chunk.liquids.entries(|entry, cell| {
+			if cell.liquid.is_some() {
+				self.active.insert(BlockPos::new(pos, entry));
+			}
+		});
+	}
+
+	pub(crate) fn mark_changed(&mut self, pos: ChunkPos) { self.changed.insert(pos); }
+
+	/// Chunks whose liquids changed since the last call.
+	pub fn drain_changes(&mut self) -> impl Iterator<Item = ChunkPos> + '_ { self.changed.drain() }
+
+	/// Moves every active liquid once. `solid` decides which blocks liquids can not flow into.
+	/// Returns the blocks created by reactions, which the caller should place.
+	pub fn tick(
+		&mut self,
+		liquids: &Registry<LiquidDesc>,
+		chunks: &mut ChunkStorage,
+		solid: impl Fn(&Chunk, BlockLayerPos) -> bool,
+	) -> Vec<(BlockPos, Id<BlockLayer>, Id<BlockDesc>)> {
+		self.ticks += 1;
+
+		// Bottom up so liquids falling in the same column do not get moved twice.
+		let mut cells: Vec<BlockPos> = self.active.drain().collect();
+		cells.sort_by_key(|pos| (pos.y(), pos.x()));
+
+		// Alternate which side goes first so liquids do not lean to one side.
+		let sides: [(i8, i8); 2] = if self.ticks % 2 == 0 {
+			[(-1, 0), (1, 0)]
+		} else {
+			[(1, 0), (-1, 0)]
+		};
+
+		let mut flow = LiquidFlow {
+			liquids,
+			chunks,
+			solid,
+			system: self,
+			reactions: Vec::new(),
+		};
+
+		for pos in cells {
+			let cell = match flow.cell(pos) {
+				Some(cell) => cell,
+				None => continue,
+			};
+			let liquid = match cell.liquid {
+				Some(liquid) => liquid,
+				None => continue,
+			};
+
+			if flow.system.ticks % liquids.get(liquid).viscosity as u64 != 0 {
+				flow.system.active.insert(pos);
+				continue;
+			}
+
+			let mut amount = cell.amount;
+			if let Some(below) = pos.checked_offset((0, -1)) {
+				amount -= flow.transfer(liquid, below, amount);
+			}
+
+			// Only ever move a third of the difference, so the levels always get closer and settle.
+			for side in sides {
+				if let Some(side) = pos.checked_offset(side) {
+					match flow.open_cell(liquid, side) {
+						Some(target) if amount > target.amount => {
+							amount -= flow.transfer(liquid, side, (amount - target.amount) / 3);
+						}
+						Some(_) => {}
+						None => flow.react(liquid, side),
+					}
+				}
+			}
+
+			if amount != cell.amount {
+				flow.system.set(flow.chunks, pos, LiquidCell::new(liquid, amount));
+			}
+		}
+
+		flow.reactions
+	}
+}
+
+struct LiquidFlow<'a, S> {
+	liquids: &'a Registry<LiquidDesc>,
+	chunks: &'a mut ChunkStorage,
+	solid: S,
+	system: &'a mut LiquidSystem,
+	reactions: Vec<(BlockPos, Id<BlockLayer>, Id<BlockDesc>)>,
+}
+
+impl<S: Fn(&Chunk, BlockLayerPos) -> bool> LiquidFlow<'_, S> {
+	fn cell(&self, pos: BlockPos) -> Option<LiquidCell> { self.system.get(self.chunks, pos) }
+
+	/// The cell at `pos` if `liquid` could flow into it without reacting.
+	fn open_cell(&self, liquid: Id<LiquidDesc>, pos: BlockPos) -> Option<LiquidCell> {
+		let chunk = self.chunks.get(pos.chunk)?;
+		if (self.solid)(chunk, pos.entry) || self.reacted(pos) {
+			return None;
+		}
+
+		let cell = *chunk.liquids.get(pos.entry);
+		match cell.liquid {
+			Some(other) if other != liquid => None,
+			_ => Some(cell),
+		}
+	}
+
+	fn reacted(&self, pos: BlockPos) -> bool {
+		self.reactions.iter().any(|(reacted, _, _)| *reacted == pos)
+	}
+
+	/// Moves up to `amount` of `liquid` into `pos`, returning how much actually moved.
+	fn transfer(&mut self, liquid: Id<LiquidDesc>, pos: BlockPos, amount: u8) -> u8 {
+		if amount == 0 {
+			return 0;
+		}
+
+		let target = match self.open_cell(liquid, pos) {
+			Some(target) => target,
+			None => {
+				self.react(liquid, pos);
+				return 0;
+			}
+		};
+
+		let moved = amount.min(MAX_LIQUID - target.amount);
+		if moved > 0 {
+			self.system
+				.set(self.chunks, pos, LiquidCell::new(liquid, target.amount + moved));
+		}
+		moved
+	}
+
+	fn react(&mut self, liquid: Id<LiquidDesc>, pos: BlockPos) {
+		let other = match self.cell(pos).and_then(|cell| cell.liquid) {
+			Some(other) if other != liquid && !self.reacted(pos) => other,
+			_ => return,
+		};
+
+		let reaction = self
+			.liquids
+			.get(liquid)
+			.reactions
+			.get(&other)
+			.or_else(|| self.liquids.get(other).reactions.get(&liquid));
+		if let Some((layer_id, block_id)) = reaction {
+			self.reactions.push((pos, *layer_id, *block_id));
+			self.system.set(self.chunks, pos, LiquidCell::EMPTY);
+		}
+	}
+}
+
+/// Bakes the liquid registry, which needs the block layers for its reactions.
+pub fn bake_liquids(
+	registry: Registry<LiquidPrototype>,
+	block_layers: &Registry<BlockLayer>,
+) -> Result<Registry<LiquidDesc>> {
+	let lookup = registry
+		.ident_to_id
+		.iter()
+		.map(|(ident, id)| (ident.clone(), id.build()))
+		.collect();
+
+	let mut out = Vec::new();
+	for (id, ident, prototype) in registry.into_entries() {
+		let desc = prototype
+			.bake(&lookup, block_layers)
+			.wrap_err_with(|| format!("Failed to bake liquid {ident}"))?;
+		out.push((id.build(), ident, desc));
+	}
+	Ok(out.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{api::id_table::IdTable, world::chunk::ChunkLayer};
+
+	const WATER: usize = 0;
+	const LAVA: usize = 1;
+
+	fn liquids() -> Registry<LiquidDesc> {
+		let water = unsafe { Id::new(WATER) };
+		let mut reactions = FxHashMap::default();
+		reactions.insert(water, (unsafe { Id::new(0) }, unsafe { Id::new(1) }));
+		vec![
+			(
+				water,
+				Identifier::new("water"),
+				LiquidDesc {
+					viscosity: 1,
+					reactions: Default::default(),
+				},
+			),
+			(
+				unsafe { Id::new(LAVA) },
+				Identifier::new("lava"),
+				LiquidDesc {
+					viscosity: 2,
+					reactions,
+				},
+			),
+		]
+		.into_iter()
+		.collect()
+	}
+
+	fn chunks() -> ChunkStorage {
+		let mut chunks = ChunkStorage::new(1, 1);
+		chunks.insert(ChunkPos { x: 0, y: 0 }, Chunk::new(IdTable::default()));
+		chunks
+	}
+
+	fn pos(x: i64, y: i64) -> BlockPos { BlockPos::from_block(x, y).unwrap() }
+
+	fn run(system: &mut LiquidSystem, chunks: &mut ChunkStorage) {
+		let liquids = liquids();
+		for _ in 0..10_000 {
+			if system.is_settled() {
+				return;
+			}
+			system.tick(&liquids, chunks, |_, _| false);
+		}
+		panic!("Liquids did not settle");
+	}
+
+	fn layer(chunks: &ChunkStorage) -> &ChunkLayer<LiquidCell> {
+		&chunks.get(ChunkPos { x: 0, y: 0 }).unwrap().liquids
+	}
+
+	#[test]
+	fn falls_spreads_and_settles() {
+		let mut system = LiquidSystem::new();
+		let mut chunks = chunks();
+		let water = unsafe { Id::new(WATER) };
+		system.set(&mut chunks, pos(8, 12), LiquidCell::new(water, MAX_LIQUID));
+		run(&mut system, &mut chunks);
+
+		let mut total = 0u32;
+		let mut highest = 0;
+		layer(&chunks).entries(|entry, cell| {
+			if cell.liquid.is_some() {
+				total += cell.amount as u32;
+				highest = highest.max(entry.y());
+			}
+		});
+		assert_eq!(total, MAX_LIQUID as u32);
+		assert_eq!(highest, 0);
+		assert!(layer(&chunks).get(BlockLayerPos::new(7, 0)).amount > 0);
+		assert!(layer(&chunks).get(BlockLayerPos::new(9, 0)).amount > 0);
+	}
+
+	#[test]
+	fn deterministic() {
+		let water = unsafe { Id::new(WATER) };
+		let simulate = || {
+			let mut system = LiquidSystem::new();
+			let mut chunks = chunks();
+			for x in 3..9 {
+				system.set(&mut chunks, pos(x, 10 + x % 3), LiquidCell::new(water, 200));
+			}
+			run(&mut system, &mut chunks);
+			let mut cells = Vec::new();
+			layer(&chunks).entries(|entry, cell| cells.push((entry, *cell)));
+			cells
+		};
+
+		assert!(simulate() == simulate());
+	}
+
+	#[test]
+	fn liquids_react() {
+		let mut system = LiquidSystem::new();
+		let mut chunks = chunks();
+		system.set(&mut chunks, pos(4, 0), LiquidCell::new(unsafe { Id::new(WATER) }, 100));
+		system.set(&mut chunks, pos(4, 1), LiquidCell::new(unsafe { Id::new(LAVA) }, 100));
+
+		let liquids = liquids();
+		let mut reactions = Vec::new();
+		while reactions.is_empty() && !system.is_settled() {
+			reactions = system.tick(&liquids, &mut chunks, |_, _| false);
+		}
+
+		assert_eq!(reactions.len(), 1);
+		assert_eq!(reactions[0].0, pos(4, 0));
+		assert_eq!(reactions[0].2.index(), 1);
+		assert!(system.get(&chunks, pos(4, 0)) == Some(LiquidCell::EMPTY));
+	}
+}
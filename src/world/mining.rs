@@ -0,0 +1,149 @@
+//! Mining blocks over several ticks.
+//!
+//! Players start and stop mining a block, and the server advances their progress every tick.
+//! Everyone watching the chunk gets told how cracked the block is, up to [`CRACK_STAGES`].
+//! A finished block gets replaced by the layer default and drops what its prototype says.
+use std::{collections::BTreeMap, vec::Drain};
+
+use fxhash::FxHashMap;
+
+use crate::{
+	network::Token,
+	ty::{block_pos::BlockPos, id::Id},
+	world::chunk::{
+		block::BlockDesc,
+		layer::BlockLayer,
+		mining::{mining_progress, Tool},
+	},
+	Api, ChunkStorage,
+};
+
+pub const CRACK_STAGES: u8 = 10;
+
+struct Mining {
+	pos: BlockPos,
+	layer_id: Id<BlockLayer>,
+	block_id: Id<BlockDesc>,
+	progress: f32,
+	stage: u8,
+}
+
+pub struct MinedBlock {
+	pub pos: BlockPos,
+	pub layer_id: Id<BlockLayer>,
+	pub block_id: Id<BlockDesc>,
+	/// If the block was mined with the tool it asks for.
+	pub drops: bool,
+}
+
+pub struct MiningSystem {
+	miners: BTreeMap<Token, Mining>,
+	tools: FxHashMap<Token, Tool>,
+	cracks: Vec<(BlockPos, Id<BlockLayer>, Option<u8>)>,
+}
+
+impl MiningSystem {
+	pub fn new() -> MiningSystem {
+		MiningSystem {
+			miners: Default::default(),
+			tools: Default::default(),
+			cracks: vec![],
+		}
+	}
+
+	/// The tool this player mines with, [`None`] being their hands.
+	pub fn set_tool(&mut self, token: Token, tool: Option<Tool>) {
+		match tool {
+			Some(tool) => {
+				self.tools.insert(token, tool);
+			}
+			None => {
+				self.tools.remove(&token);
+			}
+		}
+	}
+
+	pub fn start(
+		&mut self,
+		chunks: &ChunkStorage,
+		token: Token,
+		pos: BlockPos,
+		layer_id: Id<BlockLayer>,
+	) {
+		self.stop(token);
+		if let Some(chunk) = chunks.get(pos.chunk) {
+			self.miners.insert(
+				token,
+				Mining {
+					pos,
+					layer_id,
+					block_id: chunk.layers.get(layer_id)[pos.entry].id,
+					progress: 0.0,
+					stage: 0,
+				},
+			);
+		}
+	}
+
+	pub fn stop(&mut self, token: Token) {
+		if let Some(mining) = self.miners.remove(&token) {
+			if mining.stage > 0 {
+				self.cracks.push((mining.pos, mining.layer_id, None));
+			}
+		}
+	}
+
+	/// Returns the blocks which got mined this tick.
+	pub fn tick(&mut self, api: &Api, chunks: &ChunkStorage) -> Vec<MinedBlock> {
+		let mut mined = Vec::new();
+		let tools = &self.tools;
+		let cracks = &mut self.cracks;
+		self.miners.retain(|token, mining| {
+			let layer = api.carrier.block_layer.get(mining.layer_id);
+			let current = chunks
+				.get(mining.pos.chunk)
+				.map(|chunk| chunk.layers.get(mining.layer_id)[mining.pos.entry].id);
+
+			// Something else changed the block, or there is nothing to mine.
+			if current != Some(mining.block_id) || mining.block_id == layer.default {
+				if mining.stage > 0 {
+					cracks.push((mining.pos, mining.layer_id, None));
+				}
+				return false;
+			}
+
+			let desc = layer.blocks.get(mining.block_id);
+			let tool = tools.get(token);
+			mining.progress += mining_progress(desc.hardness, desc.tool.as_ref(), tool);
+			if mining.progress >= 1.0 {
+				if mining.stage > 0 {
+					cracks.push((mining.pos, mining.layer_id, None));
+				}
+				mined.push(MinedBlock {
+					pos: mining.pos,
+					layer_id: mining.layer_id,
+					block_id: mining.block_id,
+					drops: desc
+						.tool
+						.as_ref()
+						.map_or(true, |requirement| requirement.is_met(tool)),
+				});
+				return false;
+			}
+
+			let stage = ((mining.progress * CRACK_STAGES as f32) as u8).min(CRACK_STAGES - 1);
+			if stage != mining.stage {
+				mining.stage = stage;
+				cracks.push((mining.pos, mining.layer_id, Some(stage)));
+			}
+			true
+		});
+
+		mined
+	}
+
+	/// Crack stages which changed since the last call, [`None`] meaning the crack is gone.
+	pub fn drain_cracks(&mut self) -> Drain<(BlockPos, Id<BlockLayer>, Option<u8>)> {
+		self.cracks.drain(..)
+	}
+}
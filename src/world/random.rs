@@ -0,0 +1,58 @@
+//! Deterministic randomness.
+//!
+//! Every world has a seed, and each system which needs randomness takes its own named stream
+//! derived from it. Systems never share a stream, so adding random calls to one system does not
+//! change what another one rolls, and the same world ticked twice ends up in the same state.
+//!
+//! Where a stream got to is not saved. A loaded world starts its streams from the seed again,
+//! so it rolls differently from the world which kept running instead of being saved.
+use rand::SeedableRng;
+use rand_xoshiro::Xoroshiro64Star;
+
+use crate::{world::gen::noise, ChunkPos};
+
+pub type WorldRng = Xoroshiro64Star;
+
+/// A fresh seed for a new world.
+pub fn random_seed() -> u64 { rand::random() }
+
+/// The stream called `name` of the world with `seed`.
+pub fn stream(seed: u64, name: &str) -> WorldRng {
+	WorldRng::seed_from_u64(noise::hash(seed, name_hash(name) as i64, 0))
+}
+
+/// The stream called `name` of a single chunk, so chunks roll the same in any order.
+pub fn chunk_stream(seed: u64, name: &str, pos: ChunkPos) -> WorldRng {
+	let seed = noise::hash(seed, name_hash(name) as i64, 0);
+	WorldRng::seed_from_u64(noise::hash(seed, pos.x as i64, pos.y as i64))
+}
+
+/// FNV-1a, which unlike the std hasher is the same on every platform and every run.
+fn name_hash(name: &str) -> u64 {
+	name.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+		(hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::Rng;
+
+	use super::*;
+
+	fn roll(rng: &mut WorldRng) -> Vec<u32> { (0..8).map(|_| rng.gen()).collect() }
+
+	#[test]
+	fn streams_are_reproducible_and_independent() {
+		let first = roll(&mut stream(42, "random_tick"));
+		assert_eq!(first, roll(&mut stream(42, "random_tick")));
+		assert_ne!(first, roll(&mut stream(43, "random_tick")));
+		assert_ne!(first, roll(&mut stream(42, "liquid")));
+
+		let origin = ChunkPos { x: 0, y: 0 };
+		let chunk = roll(&mut chunk_stream(42, "rustaria:ores", origin));
+		assert_eq!(chunk, roll(&mut chunk_stream(42, "rustaria:ores", origin)));
+		assert_ne!(chunk, roll(&mut chunk_stream(42, "rustaria:ores", ChunkPos { x: 1, y: 0 })));
+		assert_ne!(chunk, roll(&mut chunk_stream(42, "rustaria:caves", origin)));
+	}
+}
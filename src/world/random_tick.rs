@@ -0,0 +1,159 @@
+//! Schedules the random ticks of every loaded block which has one.
+//!
+//! The ticking blocks are found by scanning chunks when they get loaded or generated,
+//! so nothing has to be saved for blocks to keep ticking after a restart.
+//! Blocks tick in position order with the world's `random_tick` stream, so the same world
+//! always ticks the same way until it gets reloaded, the stream starts over after a load.
+use std::{collections::BTreeMap, sync::Arc};
+
+use apollo::{LuaScope, Value};
+use rand::Rng;
+use tracing::error;
+
+use crate::{
+	debug::{DebugCategory, DebugRendererImpl},
+	draw_debug,
+	ty::{block_pos::BlockPos, id::Id},
+	world::{
+		chunk::{
+			block::BlockDesc,
+			layer::BlockLayer,
+			random_tick::{TickBehavior, TickChange},
+			Chunk,
+		},
+		block_view::BlockView,
+		gen::pass::BlockLookup,
+		random::{self, WorldRng},
+	},
+	Api, ChunkPos, ChunkStorage, TPS,
+};
+
+pub struct RandomTickSystem {
+	rand: WorldRng,
+	active: BTreeMap<(BlockPos, Id<BlockLayer>), Id<BlockDesc>>,
+	lookup: Arc<BlockLookup>,
+}
+
+impl RandomTickSystem {
+	pub fn new(api: &Api, seed: u64) -> RandomTickSystem {
+		RandomTickSystem {
+			rand: random::stream(seed, "random_tick"),
+			active: Default::default(),
+			lookup: Arc::new(BlockLookup::new(api)),
+		}
+	}
+
+	/// Starts ticking every block of a chunk which just got loaded.
+	pub fn wake_chunk(&mut self, api: &Api, pos: ChunkPos, chunk: &Chunk) {
+		for (layer_id, layer) in chunk.layers.iter() {
+			let blocks = &api.carrier.block_layer.get(layer_id).blocks;
+			let ticking = |id: Id<BlockDesc>| blocks.get(id).random_tick.is_some();
+			if !layer.palette().iter().any(|block| ticking(block.id)) {
+				continue;
+			}
+
+			layer.entries(|entry, block| {
+				if ticking(block.id) {
+					self.active
+						.insert((BlockPos::new(pos, entry), layer_id), block.id);
+				}
+			});
+		}
+	}
+
+	pub fn place_block(
+		&mut self,
+		pos: BlockPos,
+		layer_id: Id<BlockLayer>,
+		block_id: Id<BlockDesc>,
+		prototype: &BlockDesc,
+	) {
+		self.active.remove(&(pos, layer_id));
+		if prototype.random_tick.is_some() {
+			self.active.insert((pos, layer_id), block_id);
+		}
+	}
+
+	/// Returns the blocks the ticks want placed.
+	pub fn tick(
+		&mut self,
+		api: &Api,
+		chunks: &mut ChunkStorage,
+		debug: &mut impl DebugRendererImpl,
+	) -> Vec<TickChange> {
+		let mut changes = Vec::new();
+		let mut remove = Vec::new();
+		let mut callbacks = Vec::new();
+		for (&(pos, layer_id), &block_id) in &self.active {
+			// The chunk got unloaded or the block got replaced behind our back.
+			let (block, biome) = match chunks.get(pos.chunk) {
+				Some(chunk) if chunk.layers.get(layer_id)[pos.entry].id == block_id => {
+					(chunk.layers.get(layer_id)[pos.entry], chunk.biomes.get(pos.entry.x()))
+				}
+				_ => {
+					remove.push((pos, layer_id));
+					continue;
+				}
+			};
+
+			let desc = api.carrier.block_layer.get(layer_id).blocks.get(block_id);
+			let random_tick = match &desc.random_tick {
+				Some(random_tick) => random_tick,
+				None => {
+					remove.push((pos, layer_id));
+					continue;
+				}
+			};
+			if (random_tick.chance_in(biome) / TPS as f32) < self.rand.gen_range(0.0..1.0) as f32 {
+				continue;
+			}
+
+			if let TickBehavior::Lua(callback) = &random_tick.behavior {
+				callbacks.push((pos, layer_id, block_id, callback.clone()));
+				continue;
+			}
+
+			let outcome = random_tick
+				.behavior
+				.tick(pos, layer_id, block, desc, chunks);
+			if let Some(change) = outcome.change {
+				draw_debug!(debug, DebugCategory::TileSpread, change.pos, 0xfcfcfa, 10.0, 1.0);
+				changes.push(change);
+			}
+
+			if !outcome.keep {
+				draw_debug!(debug, DebugCategory::TileSpread, pos, 0xbf5570, 1.0, 1.0);
+				remove.push((pos, layer_id));
+			} else {
+				draw_debug!(debug, DebugCategory::TileSpread, pos, 0x5b595c);
+			}
+		}
+
+		if !callbacks.is_empty() {
+			let lookup = self.lookup.clone();
+			changes.extend(BlockView::lend(chunks, lookup, |view| {
+				for (pos, layer_id, block_id, callback) in callbacks {
+					let view_scope = LuaScope::from(&mut *view);
+					match callback.call::<_, Value>((view_scope.lua(), pos.x(), pos.y())) {
+						Ok(Value::Boolean(false)) => remove.push((pos, layer_id)),
+						Ok(_) => {}
+						Err(err) => {
+							error!(
+								target: "lua",
+								"Random tick of {} at {pos:?} failed: {err}",
+								self.lookup.identifier(layer_id, block_id)
+							);
+							remove.push((pos, layer_id));
+						}
+					}
+				}
+			}));
+		}
+
+		for key in remove {
+			self.active.remove(&key);
+		}
+
+		changes
+	}
+}
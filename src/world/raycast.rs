@@ -0,0 +1,285 @@
+//! Asking what a ray hits, for line of sight, reach checks and hitscan weapons.
+//!
+//! Blocks get walked one by one with a DDA, so the first block hit is exact no matter how long
+//! the ray is. Like [`CollisionSystem`] only blocks with collision in layers with collision stop
+//! a ray. Entities get hit by their collision box.
+//!
+//! [`CollisionSystem`]: crate::world::entity::system::collision::CollisionSystem
+use euclid::{Rect, Vector2D};
+use hecs::Entity;
+
+use crate::{
+	ty::{block_pos::BlockPos, direction::Direction, id::Id, WS},
+	util::aabb,
+	world::{
+		chunk::{block::Block, layer::BlockLayer},
+		entity::{
+			component::{CollisionComponent, PositionComponent},
+			EntityStorage,
+		},
+		World,
+	},
+	Api, ChunkStorage,
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ray {
+	pub origin: Vector2D<f32, WS>,
+	/// Always normalized, so distances along the ray are in blocks.
+	pub dir: Vector2D<f32, WS>,
+	pub max_distance: f32,
+}
+
+#[derive(Clone, Copy)]
+pub struct BlockHit {
+	pub pos: BlockPos,
+	pub layer_id: Id<BlockLayer>,
+	pub block: Block,
+	/// The side of the block the ray entered through, [`None`] if the ray starts inside it.
+	pub face: Option<Direction>,
+	pub distance: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct EntityHit {
+	pub entity: Entity,
+	/// The side of the collision box the ray entered through, [`None`] if the ray starts
+	/// inside it or hits a corner exactly.
+	pub face: Option<Direction>,
+	pub distance: f32,
+}
+
+#[derive(Clone, Copy)]
+pub enum RayHit {
+	Block(BlockHit),
+	Entity(EntityHit),
+}
+
+impl RayHit {
+	pub fn distance(&self) -> f32 {
+		match self {
+			RayHit::Block(hit) => hit.distance,
+			RayHit::Entity(hit) => hit.distance,
+		}
+	}
+}
+
+impl Ray {
+	pub fn new(origin: Vector2D<f32, WS>, dir: Vector2D<f32, WS>, max_distance: f32) -> Ray {
+		let dir = if dir == Vector2D::zero() {
+			dir
+		} else {
+			dir.normalize()
+		};
+		Ray {
+			origin,
+			dir,
+			max_distance,
+		}
+	}
+
+	/// The ray from one point to another, for line of sight and reach checks.
+	pub fn between(from: Vector2D<f32, WS>, to: Vector2D<f32, WS>) -> Ray {
+		Ray::new(from, to - from, (to - from).length())
+	}
+
+	pub fn at(&self, distance: f32) -> Vector2D<f32, WS> { self.origin + self.dir * distance }
+
+	/// The first block which collides. Unloaded chunks and the edge of the world stop the ray
+	/// without a hit.
+	pub fn cast_blocks(&self, api: &Api, chunks: &ChunkStorage) -> Option<BlockHit> {
+		let mut out = None;
+		self.walk(|pos, face, distance| {
+			let chunk = match chunks.get(pos.chunk) {
+				Some(chunk) => chunk,
+				None => return true,
+			};
+			for (layer_id, layer) in chunk.layers.iter() {
+				let block = layer[pos.entry];
+				if api.carrier.block_layer.get(layer_id).collision && block.collision {
+					out = Some(BlockHit {
+						pos,
+						layer_id,
+						block,
+						face,
+						distance,
+					});
+					return true;
+				}
+			}
+			false
+		});
+		out
+	}
+
+	/// The closest entity with a collision box, except for `ignore` which is usually whoever
+	/// casts the ray.
+	pub fn cast_entities(
+		&self,
+		storage: &EntityStorage,
+		ignore: Option<Entity>,
+	) -> Option<EntityHit> {
+		let mut out: Option<EntityHit> = None;
+		for (entity, (position, collision)) in storage
+			.query::<(&PositionComponent, &CollisionComponent)>()
+			.iter()
+		{
+			if Some(entity) == ignore {
+				continue;
+			}
+
+			let mut target = collision.collision_box;
+			target.origin += position.pos;
+			if let Some((face, distance)) = self.cast_rect(target) {
+				if out.map_or(true, |out| distance < out.distance) {
+					out = Some(EntityHit {
+						entity,
+						face,
+						distance,
+					});
+				}
+			}
+		}
+		out
+	}
+
+	/// Whatever the ray hits first, so walls stop hitscan weapons.
+	pub fn cast(&self, api: &Api, world: &World, ignore: Option<Entity>) -> Option<RayHit> {
+		let block = self.cast_blocks(api, &world.chunks);
+		let ray = Ray {
+			max_distance: block.map_or(self.max_distance, |hit| hit.distance),
+			..*self
+		};
+		match ray.cast_entities(&world.entities.storage, ignore) {
+			Some(hit) => Some(RayHit::Entity(hit)),
+			None => block.map(RayHit::Block),
+		}
+	}
+
+	fn cast_rect(&self, target: Rect<f32, WS>) -> Option<(Option<Direction>, f32)> {
+		if aabb::point_vs_rect(self.origin, target) {
+			return Some((None, 0.0));
+		}
+
+		let hit = aabb::ray_vs_rect(self.origin, self.dir, target)?;
+		if hit.contact_time < 0.0 || hit.contact_time > self.max_distance {
+			return None;
+		}
+		Some((hit.contact_normal, hit.contact_time))
+	}
+
+	/// Visits every block the ray passes through in order, until `visit` returns true.
+	fn walk(&self, mut visit: impl FnMut(BlockPos, Option<Direction>, f32) -> bool) {
+		// Per axis, how far along the ray one block is and where the next block starts.
+		let axis = |origin: f32, dir: f32| -> (i64, f32, f32) {
+			let block = origin.floor();
+			if dir > 0.0 {
+				(1, 1.0 / dir, (block + 1.0 - origin) / dir)
+			} else if dir < 0.0 {
+				(-1, -1.0 / dir, (origin - block) / -dir)
+			} else {
+				(0, f32::INFINITY, f32::INFINITY)
+			}
+		};
+		let (step_x, delta_x, mut next_x) = axis(self.origin.x, self.dir.x);
+		let (step_y, delta_y, mut next_y) = axis(self.origin.y, self.dir.y);
+
+		let mut x = self.origin.x.floor() as i64;
+		let mut y = self.origin.y.floor() as i64;
+		let mut face = None;
+		let mut distance = 0.0;
+		loop {
+			match BlockPos::from_block(x, y) {
+				Some(pos) if !visit(pos, face, distance) => {}
+				_ => return,
+			}
+
+			if next_x < next_y {
+				distance = next_x;
+				x += step_x;
+				next_x += delta_x;
+				face = Some(if step_x > 0 { Direction::Left } else { Direction::Right });
+			} else {
+				distance = next_y;
+				y += step_y;
+				next_y += delta_y;
+				face = Some(if step_y > 0 { Direction::Down } else { Direction::Up });
+			}
+
+			if distance > self.max_distance {
+				return;
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use euclid::{rect, vec2};
+
+	use super::*;
+
+	#[test]
+	fn walk_visits_blocks_in_order() {
+		let ray = Ray::new(vec2(0.5, 0.5), vec2(1.0, 0.5), 10.0);
+		let mut visited = Vec::new();
+		ray.walk(|pos, face, distance| {
+			visited.push((pos.x(), pos.y(), face, distance));
+			pos.x() == 3
+		});
+
+		let blocks: Vec<_> = visited.iter().map(|(x, y, face, _)| (*x, *y, *face)).collect();
+		assert_eq!(
+			blocks,
+			vec![
+				(0, 0, None),
+				(1, 0, Some(Direction::Left)),
+				(1, 1, Some(Direction::Down)),
+				(2, 1, Some(Direction::Left)),
+				(3, 1, Some(Direction::Left)),
+			]
+		);
+		// Entering x = 1 after half a block to the right.
+		let length = vec2::<f32, WS>(1.0, 0.5).length();
+		assert!((visited[1].3 - 0.5 * length).abs() < 1e-5);
+	}
+
+	#[test]
+	fn walk_stops_at_max_distance() {
+		let ray = Ray::new(vec2(0.5, 0.5), vec2(0.0, -1.0), 2.0);
+		let mut visited = Vec::new();
+		ray.walk(|pos, _, _| {
+			visited.push(pos.y());
+			false
+		});
+		assert_eq!(visited, vec![0, -1, -2]);
+	}
+
+	#[test]
+	fn entities_hit_closest_first() {
+		let mut storage = EntityStorage::new();
+		let mut spawn = |x: f32| {
+			storage.spawn((
+				PositionComponent { pos: vec2(x, 0.0) },
+				CollisionComponent {
+					collision_box: rect(-0.5, -0.5, 1.0, 1.0),
+					hit_callback: None,
+					collided: Default::default(),
+					collisions: vec![],
+				},
+			))
+		};
+		let near = spawn(3.0);
+		let far = spawn(6.0);
+
+		let ray = Ray::new(vec2(0.0, 0.0), vec2(1.0, 0.0), 10.0);
+		let hit = ray.cast_entities(&storage, None).unwrap();
+		assert_eq!(hit.entity, near);
+		assert_eq!(hit.face, Some(Direction::Left));
+		assert!((hit.distance - 2.5).abs() < 1e-5);
+
+		assert_eq!(ray.cast_entities(&storage, Some(near)).unwrap().entity, far);
+		let short = Ray::new(vec2(0.0, 0.0), vec2(1.0, 0.0), 2.0);
+		assert!(short.cast_entities(&storage, None).is_none());
+	}
+}
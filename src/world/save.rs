@@ -0,0 +1,471 @@
+//! World persistence.
+//!
+//! A save is a directory containing:
+//! - `world.toml` the world metadata.
+//! - `regions/r.{x}.{y}.bin` groups of [`region::REGION_SIZE`] squared chunks.
+//! - `entities.bin` every entity living in the world.
+use std::{
+	collections::{BTreeMap, BTreeSet, HashSet},
+	fs,
+	path::{Path, PathBuf},
+};
+
+use eyre::{bail, Result, WrapErr};
+use tracing::{info, warn};
+
+use crate::{
+	api::Api,
+	ty::identifier::Identifier,
+	world::{
+		chunk::{portable::PortableChunk, storage::ChunkBounds},
+		entity::registry::ComponentRegistry,
+		gen::{GenSettings, WorldGenerator},
+		save::{
+			entity::EntitySnapshot,
+			region::{Region, RegionPos},
+		},
+		time::WorldTime,
+		World,
+	},
+	ChunkPos, ChunkStorage,
+};
+
+pub mod entity;
+pub mod region;
+
+pub const SAVE_VERSION: u32 = 1;
+
+#[derive(Clone, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+pub struct WorldMeta {
+	pub version: u32,
+	/// The world seed, stored as i64 as toml integers are signed.
+	pub seed: i64,
+	/// If missing chunks get generated from the seed.
+	pub generate: bool,
+	/// [`None`] for an infinite world.
+	pub bounds: Option<ChunkBounds>,
+	pub time: WorldTime,
+	/// What the generator of the world uses, saves from before default to [`GenSettings::default`].
+	#[serde(default)]
+	pub settings: GenSettings,
+}
+
+#[derive(Debug)]
+pub struct SaveInfo {
+	pub meta: WorldMeta,
+	pub regions: usize,
+	pub chunks: usize,
+	pub entities: usize,
+}
+
+#[derive(Debug)]
+pub enum SaveIssue {
+	UnsupportedVersion(u32),
+	CorruptRegion(RegionPos, String),
+	CorruptEntities(String),
+	MisplacedChunk(RegionPos, ChunkPos),
+	DuplicateChunk(ChunkPos),
+	OutOfBounds(ChunkPos),
+	UnknownLayer(ChunkPos, Identifier),
+	MissingLayer(ChunkPos, Identifier),
+	UnknownBlock(ChunkPos, Identifier, Identifier),
+	UnknownLiquid(ChunkPos, Identifier),
+	UnknownBiome(ChunkPos, Identifier),
+	UnknownEntity(Identifier),
+	UnknownComponent(Identifier, String),
+}
+
+pub struct WorldSave {
+	path: PathBuf,
+}
+
+impl WorldSave {
+	pub fn new(path: impl Into<PathBuf>) -> WorldSave { WorldSave { path: path.into() } }
+
+	pub fn path(&self) -> &Path { &self.path }
+
+	pub fn exists(&self) -> bool { self.meta_path().exists() }
+
+	pub fn save(&self, api: &Api, world: &World) -> Result<()> {
+		info!("Saving world to {}", self.path.display());
+		fs::create_dir_all(self.region_dir()).wrap_err("Could not create save directories.")?;
+
+		let meta = WorldMeta {
+			version: SAVE_VERSION,
+			bounds: world.chunks.bounds(),
+			seed: world.seed() as i64,
+			generate: world.generator.is_some(),
+			time: world.time(),
+			settings: world
+				.generator
+				.as_ref()
+				.map(|generator| generator.settings().clone())
+				.unwrap_or_default(),
+		};
+		write_atomic(&self.meta_path(), toml::to_string(&meta)?.as_bytes())
+			.wrap_err("Could not write world metadata.")?;
+
+		// Regions
+		let mut regions: BTreeMap<RegionPos, Region> = BTreeMap::new();
+		for (pos, chunk) in world.chunks.iter() {
+			regions
+				.entry(RegionPos::from(pos))
+				.or_default()
+				.chunks
+				.push((pos, PortableChunk::new(api, chunk)));
+		}
+
+		for (pos, region) in &mut regions {
+			region.chunks.sort_by_key(|(pos, _)| *pos);
+			region
+				.write(&self.region_dir().join(pos.file_name()))
+				.wrap_err_with(|| format!("Failed to save region {pos:?}"))?;
+		}
+
+		// Regions which no longer hold any chunks.
+		for (pos, path) in self.regions()? {
+			if !regions.contains_key(&pos) {
+				fs::remove_file(path).wrap_err("Could not remove stale region.")?;
+			}
+		}
+
+		// Entities
+		let entities = EntitySnapshot::collect(api, &world.entities.storage);
+		write_atomic(&self.entities_path(), &bincode::serialize(&entities)?)
+			.wrap_err("Could not write entities.")?;
+		Ok(())
+	}
+
+	pub fn load(&self, api: &Api) -> Result<World> {
+		info!("Loading world from {}", self.path.display());
+		let meta = self.read_meta()?;
+		if meta.version != SAVE_VERSION {
+			bail!(
+				"Save version {} is not supported, expected {}",
+				meta.version,
+				SAVE_VERSION
+			);
+		}
+
+		let mut chunks = ChunkStorage::with_bounds(meta.bounds);
+		for (region_pos, path) in self.regions()? {
+			let region = Region::read(&path)
+				.wrap_err_with(|| format!("Failed to load region {region_pos:?}"))?;
+			for (pos, chunk) in region.chunks {
+				chunks.insert(pos, chunk.bake(api, pos));
+			}
+		}
+		chunks.reset_dirty();
+
+		let mut world = World::new(api, chunks, meta.seed as u64)?;
+		*world.time_mut() = meta.time;
+		if meta.generate {
+			world.generator = Some(WorldGenerator::from_api(api, world.seed(), meta.settings)?);
+		}
+		world.enable_light(api);
+
+		for snapshot in self.read_entities()? {
+			if let Err(error) = snapshot.spawn(api, &mut world.entities.storage) {
+				warn!("Skipping entity: {error}");
+			}
+		}
+
+		Ok(world)
+	}
+
+	/// Counts what is stored in the save without building a world out of it.
+	pub fn inspect(&self) -> Result<SaveInfo> {
+		let meta = self.read_meta()?;
+		let mut regions = 0;
+		let mut chunks = 0;
+		for (_, path) in self.regions()? {
+			regions += 1;
+			chunks += Region::read(&path)?.chunks.len();
+		}
+
+		Ok(SaveInfo {
+			meta,
+			regions,
+			chunks,
+			entities: self.read_entities()?.len(),
+		})
+	}
+
+	/// Checks the save against the currently loaded registries and reports everything that would
+	/// not load cleanly. `components` are the components of the storage the entities load into.
+	/// An empty list means the save is healthy.
+	pub fn validate(&self, api: &Api, components: &ComponentRegistry) -> Result<Vec<SaveIssue>> {
+		let meta = self.read_meta()?;
+		let mut issues = Vec::new();
+		if meta.version != SAVE_VERSION {
+			issues.push(SaveIssue::UnsupportedVersion(meta.version));
+			return Ok(issues);
+		}
+
+		let mut seen = HashSet::new();
+		for (region_pos, path) in self.regions()? {
+			let region = match Region::read(&path) {
+				Ok(region) => region,
+				Err(error) => {
+					issues.push(SaveIssue::CorruptRegion(region_pos, format!("{error:?}")));
+					continue;
+				}
+			};
+
+			for (pos, chunk) in &region.chunks {
+				if !region_pos.contains(*pos) {
+					issues.push(SaveIssue::MisplacedChunk(region_pos, *pos));
+				}
+				if !seen.insert(*pos) {
+					issues.push(SaveIssue::DuplicateChunk(*pos));
+				}
+				if meta.bounds.map_or(false, |bounds| !bounds.contains(*pos)) {
+					issues.push(SaveIssue::OutOfBounds(*pos));
+				}
+
+				for layer in &chunk.layers {
+					match api.carrier.block_layer.get_id(&layer.layer) {
+						Some(layer_id) => {
+							let prototype = api.carrier.block_layer.get(layer_id);
+							for block in layer.unknown_blocks(prototype) {
+								issues.push(SaveIssue::UnknownBlock(
+									*pos,
+									layer.layer.clone(),
+									block.clone(),
+								));
+							}
+						}
+						None => issues.push(SaveIssue::UnknownLayer(*pos, layer.layer.clone())),
+					}
+				}
+
+				for (_, identifier, _) in api.carrier.block_layer.entries() {
+					if !chunk.layers.iter().any(|layer| &layer.layer == identifier) {
+						issues.push(SaveIssue::MissingLayer(*pos, identifier.clone()));
+					}
+				}
+
+				for identifier in &chunk.liquids.palette {
+					if api.carrier.liquid.get_id(identifier).is_none() {
+						issues.push(SaveIssue::UnknownLiquid(*pos, identifier.clone()));
+					}
+				}
+
+				let biomes: BTreeSet<&Identifier> = chunk.biomes.iter().flatten().collect();
+				for identifier in biomes {
+					if api.carrier.biome.get_id(identifier).is_none() {
+						issues.push(SaveIssue::UnknownBiome(*pos, identifier.clone()));
+					}
+				}
+			}
+		}
+
+		match self.read_entities() {
+			Ok(entities) => {
+				for snapshot in entities {
+					for name in snapshot.components.keys() {
+						if !components.contains(name) {
+							issues.push(SaveIssue::UnknownComponent(
+								snapshot.prototype.clone(),
+								name.clone(),
+							));
+						}
+					}
+					if api.carrier.entity.get_id(&snapshot.prototype).is_none() {
+						issues.push(SaveIssue::UnknownEntity(snapshot.prototype));
+					}
+				}
+			}
+			Err(error) => issues.push(SaveIssue::CorruptEntities(format!("{error:?}"))),
+		}
+
+		Ok(issues)
+	}
+
+	fn read_meta(&self) -> Result<WorldMeta> {
+		let data = fs::read(self.meta_path()).wrap_err("Could not read world metadata.")?;
+		toml::from_slice(&data).wrap_err("Could not parse world metadata.")
+	}
+
+	fn read_entities(&self) -> Result<Vec<EntitySnapshot>> {
+		let path = self.entities_path();
+		if !path.exists() {
+			return Ok(Vec::new());
+		}
+
+		let data = fs::read(path).wrap_err("Could not read entities.")?;
+		bincode::deserialize(&data).wrap_err("Could not decode entities.")
+	}
+
+	fn regions(&self) -> Result<Vec<(RegionPos, PathBuf)>> {
+		let dir = self.region_dir();
+		if !dir.exists() {
+			return Ok(Vec::new());
+		}
+
+		let mut out = Vec::new();
+		for entry in fs::read_dir(dir).wrap_err("Could not read regions directory.")? {
+			let path = entry?.path();
+			let pos = path
+				.file_name()
+				.and_then(|name| name.to_str())
+				.and_then(RegionPos::from_file_name);
+			match pos {
+				Some(pos) => out.push((pos, path)),
+				None => warn!("Unknown file in regions directory {}", path.display()),
+			}
+		}
+
+		out.sort_by_key(|(pos, _)| *pos);
+		Ok(out)
+	}
+
+	fn meta_path(&self) -> PathBuf { self.path.join("world.toml") }
+
+	fn region_dir(&self) -> PathBuf { self.path.join("regions") }
+
+	fn entities_path(&self) -> PathBuf { self.path.join("entities.bin") }
+}
+
+/// Writes next to the old file first, so a crash does not leave it half written.
+fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+	let temp = path.with_extension("tmp");
+	fs::write(&temp, data)?;
+	fs::rename(&temp, path)?;
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use euclid::{rect, vec2};
+
+	use super::*;
+	use crate::{
+		api::test::{api, block_desc, entity},
+		ty::{block_layer_pos::BlockLayerPos, block_pos::BlockPos, id::Id},
+		world::{
+			chunk::{
+				state::BlockState,
+				test::{block, chunk},
+			},
+			entity::{
+				component::{CollisionComponent, PositionComponent},
+				prototype::EntityPrototype,
+			},
+			falling::FallingBlockComponent,
+		},
+		Api,
+	};
+
+	#[test]
+	fn worlds_survive_a_round_trip() {
+		let api = api(
+			vec![("air", block_desc(false)), ("stone", block_desc(true))],
+			vec![("dummy", entity())],
+		);
+		let layer_id = unsafe { Id::new(0) };
+		let stone = BlockPos::new(ChunkPos { x: -1, y: 2 }, BlockLayerPos::new(3, 4));
+		let mut chunks = ChunkStorage::unbounded();
+		let mut with_stone = chunk(layer_id, 0);
+		with_stone.layers.get_mut(layer_id).set(stone.entry, block(1));
+		chunks.insert(stone.chunk, with_stone);
+		// Lands in another region.
+		chunks.insert(ChunkPos { x: 9, y: 0 }, chunk(layer_id, 0));
+
+		let mut world = World::new(&api, chunks, 42).unwrap();
+		world.time_mut().set_time_of_day(0.75);
+		let entity = world.spawn_entity(&api, unsafe { Id::new(0) }, vec2(3.0, 40.0));
+
+		let dir = std::env::temp_dir().join("rustaria_worlds_survive_a_round_trip");
+		let _ = fs::remove_dir_all(&dir);
+		let save = WorldSave::new(&dir);
+		save.save(&api, &world).unwrap();
+		let components = world.entities.storage.components();
+		assert!(save.validate(&api, components).unwrap().is_empty());
+		assert_eq!(save.inspect().unwrap().regions, 2);
+
+		let loaded = save.load(&api).unwrap();
+		fs::remove_dir_all(&dir).unwrap();
+		assert_eq!(loaded.seed(), 42);
+		assert_eq!(loaded.time().ticks(), world.time().ticks());
+		assert_eq!(loaded.chunks.iter().count(), 2);
+		let layer = loaded.chunks.get(stone.chunk).unwrap().layers.get(layer_id);
+		assert!(layer[stone.entry].id == block(1).id);
+		let position = loaded.entities.storage.get_comp::<PositionComponent>(entity).unwrap();
+		assert_eq!(position.pos, vec2(3.0, 40.0));
+	}
+
+	#[test]
+	fn metadata_keeps_the_generator_settings() {
+		let mut settings = GenSettings::default();
+		settings.surface_level = 64.0;
+		settings.ores.truncate(1);
+		let meta = WorldMeta {
+			version: SAVE_VERSION,
+			seed: -7,
+			generate: true,
+			bounds: None,
+			time: WorldTime::default(),
+			settings,
+		};
+		let read: WorldMeta = toml::from_str(&toml::to_string(&meta).unwrap()).unwrap();
+		assert_eq!(read, meta);
+	}
+
+	/// An api with `blocks` followed by `sand`, which falls as `falling_sand`. The entity has a
+	/// hit callback, which only exists in Lua.
+	fn falling_api(blocks: Vec<&'static str>) -> Api {
+		let mut blocks: Vec<_> = blocks.into_iter().map(|name| (name, block_desc(false))).collect();
+		let mut sand = block_desc(true);
+		sand.falls = Some(unsafe { Id::new(0) });
+		blocks.push(("sand", sand));
+		let mut api = api(blocks, vec![]);
+
+		let hit_callback = api.luna.lua.load("return function() end").eval().unwrap();
+		let mut prototype = entity();
+		prototype.collision = Some(CollisionComponent {
+			collision_box: rect(-0.49, -0.49, 0.98, 0.98),
+			hit_callback: Some(hit_callback),
+			collided: Default::default(),
+			collisions: vec![],
+		});
+		let id: Id<EntityPrototype> = unsafe { Id::new(0) };
+		api.carrier.entity = vec![(id.build(), Identifier::new("falling_sand"), prototype.bake(id))]
+			.into_iter()
+			.collect();
+		api
+	}
+
+	#[test]
+	fn entities_restore_from_their_prototype() {
+		let api = falling_api(vec!["air"]);
+		let layer_id = unsafe { Id::new(0) };
+		let mut chunks = ChunkStorage::unbounded();
+		chunks.insert(ChunkPos { x: 0, y: 0 }, chunk(layer_id, 0));
+		let mut world = World::new(&api, chunks, 42).unwrap();
+		let entity = world.spawn_entity(&api, unsafe { Id::new(0) }, vec2(4.5, 8.5));
+		world.entities.storage.insert_comp(entity, FallingBlockComponent {
+			layer_id,
+			block_id: unsafe { Id::new(1) },
+			state: BlockState::DEFAULT,
+		});
+
+		let dir = std::env::temp_dir().join("rustaria_entities_restore_from_their_prototype");
+		let _ = fs::remove_dir_all(&dir);
+		let save = WorldSave::new(&dir);
+		save.save(&api, &world).unwrap();
+
+		// Gravel pushes sand to another id.
+		let api = falling_api(vec!["air", "gravel"]);
+		let components = ComponentRegistry::new();
+		assert!(save.validate(&api, &components).unwrap().is_empty());
+		let loaded = save.load(&api).unwrap();
+		fs::remove_dir_all(&dir).unwrap();
+
+		let storage = &loaded.entities.storage;
+		let collision = storage.get_comp::<CollisionComponent>(entity).unwrap();
+		assert!(collision.hit_callback.is_some());
+		let falling = storage.get_comp::<FallingBlockComponent>(entity).unwrap();
+		assert!(falling.block_id == unsafe { Id::new(2) });
+	}
+}
@@ -0,0 +1,94 @@
+use std::collections::BTreeMap;
+
+use eyre::{ContextCompat, Result};
+use hecs::Entity;
+use tracing::warn;
+
+use crate::{
+	api::Api,
+	ty::{id::Id, identifier::Identifier},
+	world::entity::{
+		component::{PositionComponent, PrototypeComponent},
+		prototype::EntityDesc,
+		registry::PersistentComponent,
+		EntityStorage,
+	},
+};
+
+/// The persisted state of a single entity.
+/// Components are saved by name through the [`ComponentRegistry`], anything they leave out is
+/// restored from the prototype.
+///
+/// [`ComponentRegistry`]: crate::world::entity::registry::ComponentRegistry
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct EntitySnapshot {
+	pub entity: Entity,
+	pub prototype: Identifier,
+	pub components: BTreeMap<String, Vec<u8>>,
+}
+
+impl EntitySnapshot {
+	pub fn collect(api: &Api, storage: &EntityStorage) -> Vec<EntitySnapshot> {
+		let mut out = Vec::new();
+		for (entity, _) in storage.query::<&PrototypeComponent>().iter() {
+			out.extend(EntitySnapshot::new(api, storage, entity));
+		}
+
+		out
+	}
+
+	/// [`None`] if the entity does not exist, has no prototype or could not be saved.
+	pub fn new(api: &Api, storage: &EntityStorage, entity: Entity) -> Option<EntitySnapshot> {
+		let entity_ref = storage.get(entity)?;
+		let prototype = entity_ref.get::<PrototypeComponent>()?.id;
+		let components = match storage.components().save(api, &entity_ref) {
+			Ok(components) => components,
+			Err(err) => {
+				warn!("Could not save entity {entity:?}: {err}");
+				return None;
+			}
+		};
+
+		Some(EntitySnapshot {
+			entity,
+			prototype: api.carrier.entity.get_identifier(prototype).clone(),
+			components,
+		})
+	}
+
+	pub fn prototype_id(&self, api: &Api) -> Result<Id<EntityDesc>> {
+		api.carrier
+			.entity
+			.get_id(&self.prototype)
+			.wrap_err_with(|| format!("Entity prototype {} does not exist", self.prototype))
+	}
+
+	pub fn position(&self) -> Option<PositionComponent> {
+		let data = self.components.get(PositionComponent::NAME)?;
+		bincode::deserialize(data).ok()
+	}
+
+	pub fn set_position(&mut self, position: PositionComponent) {
+		if let Ok(data) = bincode::serialize(&position) {
+			self.components.insert(PositionComponent::NAME.to_string(), data);
+		}
+	}
+
+	pub fn spawn(self, api: &Api, storage: &mut EntityStorage) -> Result<Entity> {
+		let id = self.prototype_id(api)?;
+		let entity = self.entity;
+		storage.insert(api, entity, id);
+		self.restore(api, storage, entity);
+		Ok(entity)
+	}
+
+	/// Puts the saved components onto an existing entity, skipping the ones which can not be
+	/// restored.
+	pub fn restore(&self, api: &Api, storage: &mut EntityStorage, entity: Entity) {
+		for (name, data) in &self.components {
+			if let Err(err) = storage.restore_component(api, entity, name, data) {
+				warn!("Could not restore {name} of entity {}: {err}", self.prototype);
+			}
+		}
+	}
+}
@@ -0,0 +1,59 @@
+use std::{fs, path::Path};
+
+use eyre::{Result, WrapErr};
+
+use crate::{
+	world::{chunk::portable::PortableChunk, save::write_atomic},
+	ChunkPos,
+};
+
+/// The amount of chunks in each axis that are grouped into a single region file.
+pub const REGION_SIZE: i32 = 8;
+
+#[derive(Copy, Clone, PartialOrd, Ord, PartialEq, Eq, Hash, Debug)]
+pub struct RegionPos {
+	pub x: i32,
+	pub y: i32,
+}
+
+impl RegionPos {
+	pub fn contains(self, pos: ChunkPos) -> bool { RegionPos::from(pos) == self }
+
+	pub fn file_name(self) -> String { format!("r.{}.{}.bin", self.x, self.y) }
+
+	pub fn from_file_name(name: &str) -> Option<RegionPos> {
+		let (x, y) = name
+			.strip_prefix("r.")?
+			.strip_suffix(".bin")?
+			.split_once('.')?;
+		Some(RegionPos {
+			x: x.parse().ok()?,
+			y: y.parse().ok()?,
+		})
+	}
+}
+
+impl From<ChunkPos> for RegionPos {
+	fn from(pos: ChunkPos) -> Self {
+		RegionPos {
+			x: pos.x.div_euclid(REGION_SIZE),
+			y: pos.y.div_euclid(REGION_SIZE),
+		}
+	}
+}
+
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct Region {
+	pub chunks: Vec<(ChunkPos, PortableChunk)>,
+}
+
+impl Region {
+	pub fn read(path: &Path) -> Result<Region> {
+		let data = fs::read(path).wrap_err("Could not read region file.")?;
+		bincode::deserialize(&data).wrap_err("Could not decode region.")
+	}
+
+	pub fn write(&self, path: &Path) -> Result<()> {
+		write_atomic(path, &bincode::serialize(self)?).wrap_err("Could not write region file.")
+	}
+}
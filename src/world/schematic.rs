@@ -0,0 +1,390 @@
+//! Structures which can be copied out of a world and pasted somewhere else.
+//!
+//! Like a [`PortableChunk`] a schematic stores identifiers instead of raw ids, so it keeps working
+//! across registry changes and can ship with a plugin. It holds a rectangle of one or more block
+//! layers together with their block entities and the entities standing inside it.
+//! Pasting can mirror and rotate the schematic, everything else like world generators,
+//! plugins and admin tools use the same format for their houses and dungeons.
+//!
+//! [`PortableChunk`]: crate::world::chunk::portable::PortableChunk
+use std::{fs, path::Path};
+
+use euclid::{vec2, Vector2D};
+use eyre::{bail, ContextCompat, Result, WrapErr};
+use fxhash::FxHashMap;
+use tracing::warn;
+
+use crate::{
+	api::Api,
+	ty::{block_pos::BlockPos, direction::Direction, id::Id, identifier::Identifier, WS},
+	world::{
+		chunk::{
+			block::BlockDesc, block_entity::BlockEntity, layer::BlockLayer,
+			portable::PortableBlock, state::BlockState,
+		},
+		entity::component::{HumanoidComponent, PositionComponent, PrototypeComponent},
+		save::entity::EntitySnapshot,
+		World,
+	},
+};
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct Schematic {
+	pub width: u32,
+	pub height: u32,
+	pub layers: Vec<SchematicLayer>,
+	/// Positions are relative to the bottom left corner.
+	pub entities: Vec<EntitySnapshot>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct SchematicLayer {
+	pub layer: Identifier,
+	pub palette: Vec<PortableBlock>,
+	/// Indexes into the palette, row by row starting at the bottom left corner.
+	pub blocks: Vec<u16>,
+	pub block_entities: Vec<((u32, u32), BlockEntity)>,
+}
+
+/// How a schematic gets mirrored and rotated when pasting.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Transform {
+	/// Where the top of the schematic ends up, [`Direction::Up`] keeps it upright.
+	pub facing: Direction,
+	/// Mirrors the schematic horizontally before rotating it.
+	pub flip: bool,
+}
+
+impl Default for Transform {
+	fn default() -> Self {
+		Transform {
+			facing: Direction::Up,
+			flip: false,
+		}
+	}
+}
+
+impl Transform {
+	/// Every turn takes the top of the schematic one [`Direction::clockwise`] step further.
+	fn turns(self) -> usize {
+		let mut dir = Direction::Up;
+		let mut turns = 0;
+		while dir != self.facing {
+			dir = dir.clockwise();
+			turns += 1;
+		}
+		turns
+	}
+
+	/// The size of the pasted area.
+	pub fn size(self, (width, height): (u32, u32)) -> (u32, u32) {
+		if self.turns() % 2 == 0 {
+			(width, height)
+		} else {
+			(height, width)
+		}
+	}
+
+	/// Where the block at `x`, `y` of a schematic with `size` ends up, relative to the paste origin.
+	pub fn block(self, (width, height): (u32, u32), x: u32, y: u32) -> (u32, u32) {
+		let (mut x, mut y) = (if self.flip { width - 1 - x } else { x }, y);
+		let (mut width, mut height) = (width, height);
+		for _ in 0..self.turns() {
+			(x, y) = (height - 1 - y, x);
+			(width, height) = (height, width);
+		}
+		(x, y)
+	}
+
+	/// Like [`Transform::block`] for a position inside the schematic.
+	pub fn point(self, (width, height): (u32, u32), pos: Vector2D<f32, WS>) -> Vector2D<f32, WS> {
+		let (mut width, mut height) = (width as f32, height as f32);
+		let mut pos = if self.flip { vec2(width - pos.x, pos.y) } else { pos };
+		for _ in 0..self.turns() {
+			pos = vec2(height - pos.y, pos.x);
+			(width, height) = (height, width);
+		}
+		pos
+	}
+}
+
+impl Schematic {
+	/// Copies `layers` in the area starting at `origin`, which has to be loaded.
+	/// Humanoids like players are left out.
+	pub fn copy(
+		api: &Api,
+		world: &World,
+		origin: BlockPos,
+		(width, height): (u32, u32),
+		layers: &[Id<BlockLayer>],
+	) -> Result<Schematic> {
+		let pos = |x: u32, y: u32| {
+			let (x, y) = (origin.x() + x as i64, origin.y() + y as i64);
+			BlockPos::from_block(x, y).wrap_err_with(|| format!("{x}, {y} is outside of the world"))
+		};
+
+		let mut out = Vec::new();
+		for &layer_id in layers {
+			let prototype = api.carrier.block_layer.get(layer_id);
+			let mut palette = Vec::new();
+			let mut lookup: FxHashMap<(Id<BlockDesc>, BlockState), u16> = FxHashMap::default();
+			let mut blocks = Vec::with_capacity((width * height) as usize);
+			let mut block_entities = Vec::new();
+			for y in 0..height {
+				for x in 0..width {
+					let pos = pos(x, y)?;
+					let chunk = world
+						.chunks
+						.get(pos.chunk)
+						.wrap_err_with(|| format!("Chunk {:?} is not loaded", pos.chunk))?;
+					let block = chunk.layers.get(layer_id)[pos.entry];
+					blocks.push(*lookup.entry((block.id, block.state)).or_insert_with(|| {
+						palette.push(PortableBlock::new(prototype, block));
+						(palette.len() - 1) as u16
+					}));
+					if let Some(block_entity) = world.block_entity(pos, layer_id) {
+						block_entities.push(((x, y), block_entity.clone()));
+					}
+				}
+			}
+
+			out.push(SchematicLayer {
+				layer: api.carrier.block_layer.get_identifier(layer_id).clone(),
+				palette,
+				blocks,
+				block_entities,
+			});
+		}
+
+		let min = vec2(origin.x() as f32, origin.y() as f32);
+		let max = min + vec2(width as f32, height as f32);
+		let storage = &world.entities.storage;
+		let mut entities = Vec::new();
+		for (entity, (position, _, humanoid)) in storage
+			.query::<(&PositionComponent, &PrototypeComponent, Option<&HumanoidComponent>)>()
+			.iter()
+		{
+			let inside = humanoid.is_none()
+				&& position.pos.x >= min.x
+				&& position.pos.y >= min.y
+				&& position.pos.x < max.x
+				&& position.pos.y < max.y;
+			if !inside {
+				continue;
+			}
+
+			if let Some(mut snapshot) = EntitySnapshot::new(api, storage, entity) {
+				snapshot.set_position(PositionComponent {
+					pos: position.pos - min,
+				});
+				entities.push(snapshot);
+			}
+		}
+
+		Ok(Schematic {
+			width,
+			height,
+			layers: out,
+			entities,
+		})
+	}
+
+	/// Pastes the schematic with its bottom left corner at `origin`.
+	/// Layers, blocks and entities which no longer exist get skipped, as do unloaded chunks.
+	pub fn paste(&self, api: &Api, world: &mut World, origin: BlockPos, transform: Transform) {
+		let size = (self.width, self.height);
+		let pos = |(x, y): (u32, u32)| {
+			let (x, y) = transform.block(size, x, y);
+			BlockPos::from_block(origin.x() + x as i64, origin.y() + y as i64)
+		};
+
+		for layer in &self.layers {
+			let layer_id = match api.carrier.block_layer.get_id(&layer.layer) {
+				Some(layer_id) => layer_id,
+				None => {
+					warn!("Schematic layer {} no longer exists, skipping it", layer.layer);
+					continue;
+				}
+			};
+			let prototype = api.carrier.block_layer.get(layer_id);
+			let palette: Vec<_> = layer
+				.palette
+				.iter()
+				.map(|entry| {
+					let block = entry.bake(prototype, |err| {
+						warn!("Schematic block {} has an invalid property: {err}", entry.block)
+					});
+					if block.is_none() {
+						warn!("Schematic block {} no longer exists, skipping it", entry.block);
+					}
+					block
+				})
+				.collect();
+
+			for (index, entry) in layer.blocks.iter().enumerate() {
+				let index = index as u32;
+				let block = match palette.get(*entry as usize) {
+					Some(Some(block)) => *block,
+					_ => continue,
+				};
+				if let Some(pos) = pos((index % self.width, index / self.width)) {
+					world.place_block_state(api, pos, layer_id, block.id, block.state);
+				}
+			}
+
+			for (local, saved) in &layer.block_entities {
+				let block_entity = pos(*local)
+					.and_then(|pos| world.block_entity_mut(pos, layer_id));
+				if let Some(block_entity) = block_entity {
+					*block_entity = saved.clone();
+				}
+			}
+		}
+
+		let origin = vec2(origin.x() as f32, origin.y() as f32);
+		for snapshot in &self.entities {
+			let id = match snapshot.prototype_id(api) {
+				Ok(id) => id,
+				Err(err) => {
+					warn!("Skipping schematic entity: {err}");
+					continue;
+				}
+			};
+
+			let local = snapshot.position().map_or(Vector2D::zero(), |comp| comp.pos);
+			let pos = origin + transform.point(size, local);
+			let mut snapshot = snapshot.clone();
+			snapshot.set_position(PositionComponent { pos });
+			let entity = world.spawn_entity(api, id, pos);
+			snapshot.restore(api, &mut world.entities.storage, entity);
+		}
+	}
+
+	pub fn read(path: &Path) -> Result<Schematic> {
+		let data = fs::read(path).wrap_err("Could not read schematic.")?;
+		let schematic: Schematic =
+			bincode::deserialize(&data).wrap_err("Could not decode schematic.")?;
+		schematic.validate().wrap_err("Schematic is broken.")?;
+		Ok(schematic)
+	}
+
+	/// Checks that every layer fills the whole rectangle and only points at things inside it,
+	/// which pasting relies on.
+	pub fn validate(&self) -> Result<()> {
+		if self.width == 0 || self.height == 0 {
+			bail!("Schematic is {}x{} blocks", self.width, self.height);
+		}
+
+		let area = self.width as u64 * self.height as u64;
+		for layer in &self.layers {
+			if layer.blocks.len() as u64 != area {
+				bail!(
+					"Layer {} has {} blocks instead of {area}",
+					layer.layer,
+					layer.blocks.len()
+				);
+			}
+			if let Some(index) = layer
+				.blocks
+				.iter()
+				.find(|index| **index as usize >= layer.palette.len())
+			{
+				bail!(
+					"Layer {} uses block {index} of a palette with {} blocks",
+					layer.layer,
+					layer.palette.len()
+				);
+			}
+			for ((x, y), _) in &layer.block_entities {
+				if *x >= self.width || *y >= self.height {
+					bail!("Layer {} has a block entity outside of it at {x}, {y}", layer.layer);
+				}
+			}
+		}
+		Ok(())
+	}
+
+	pub fn write(&self, path: &Path) -> Result<()> {
+		fs::write(path, bincode::serialize(self)?).wrap_err("Could not write schematic.")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::world::chunk::block_entity::DataValue;
+
+	fn schematic() -> Schematic {
+		Schematic {
+			width: 2,
+			height: 2,
+			layers: vec![SchematicLayer {
+				layer: Identifier::new("tile"),
+				palette: vec![PortableBlock {
+					block: Identifier::new("stone"),
+					properties: vec![],
+				}],
+				blocks: vec![0; 4],
+				block_entities: vec![(
+					(1, 1),
+					BlockEntity {
+						data: DataValue::Nil,
+					},
+				)],
+			}],
+			entities: vec![],
+		}
+	}
+
+	#[test]
+	fn rejects_broken_schematics() {
+		assert!(schematic().validate().is_ok());
+
+		let mut empty = schematic();
+		empty.width = 0;
+		empty.layers[0].blocks.clear();
+		empty.layers[0].block_entities.clear();
+		assert!(empty.validate().is_err());
+
+		let mut short = schematic();
+		short.layers[0].blocks.pop();
+		assert!(short.validate().is_err());
+
+		let mut palette = schematic();
+		palette.layers[0].blocks[3] = 1;
+		assert!(palette.validate().is_err());
+
+		let mut block_entity = schematic();
+		block_entity.layers[0].block_entities[0].0 = (2, 0);
+		assert!(block_entity.validate().is_err());
+	}
+
+	#[test]
+	fn transform_rotates_and_flips() {
+		let size = (3, 2);
+		let upright = Transform::default();
+		assert_eq!(upright.block(size, 2, 1), (2, 1));
+
+		let left = Transform {
+			facing: Direction::Left,
+			flip: false,
+		};
+		assert_eq!(left.size(size), (2, 3));
+		// The bottom right corner ends up at the top right, the top left at the bottom left.
+		assert_eq!(left.block(size, 2, 0), (1, 2));
+		assert_eq!(left.block(size, 0, 1), (0, 0));
+
+		let flipped = Transform {
+			facing: Direction::Up,
+			flip: true,
+		};
+		assert_eq!(flipped.block(size, 0, 0), (2, 0));
+
+		let down = Transform {
+			facing: Direction::Down,
+			flip: false,
+		};
+		assert_eq!(down.block(size, 0, 0), (2, 1));
+		assert_eq!(down.point(size, vec2(0.5, 0.5)), vec2(2.5, 1.5));
+	}
+}
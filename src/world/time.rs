@@ -0,0 +1,210 @@
+//! The clock of a world, driving the day and night cycle.
+//!
+//! Time counts in ticks since the world was created. A day starts at midnight, the sun rises at
+//! a quarter of the day and sets at three quarters. The server advances the clock and clients
+//! advance their own copy in between the [`TIME_SYNC_INTERVAL`] syncs, so they stay smooth.
+//! Lua reads and sets it through the `time` global, a [`WorldClock`] shared with the world. It
+//! belongs to the world which was created or ticked last, and stays valid between ticks.
+//! ```lua
+//! if time:is_day() and time:get_moon_phase() == 0 then
+//!     time:set_time(0.0)
+//! end
+//! ```
+use std::{f32::consts::TAU, sync::Arc};
+
+use apollo::impl_macro::*;
+use eyre::{bail, Report, Result};
+use parking_lot::{Mutex, MutexGuard};
+
+use crate::TPS;
+
+/// Twenty minutes per day.
+pub const DEFAULT_DAY_LENGTH: u64 = 20 * 60 * TPS as u64;
+/// The shortest day allowed, anything below makes the sky flicker.
+pub const MIN_DAY_LENGTH: u64 = TPS as u64;
+pub const MOON_PHASES: u64 = 8;
+/// How often the server sends the time to the clients.
+pub const TIME_SYNC_INTERVAL: u64 = 5 * TPS as u64;
+/// How bright the sky stays at midnight.
+pub const MIN_SKY_LIGHT: f32 = 0.15;
+
+#[derive(Clone, Copy, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "RawWorldTime")]
+pub struct WorldTime {
+	/// Ticks since the world was created.
+	ticks: u64,
+	/// Ticks in a full day.
+	day_length: u64,
+	/// If the time got set instead of advancing, so the server syncs it right away.
+	#[serde(skip)]
+	changed: bool,
+}
+
+/// A [`WorldTime`] as it was saved or synced, before its day length is checked.
+#[derive(serde::Deserialize)]
+struct RawWorldTime {
+	ticks: u64,
+	day_length: u64,
+}
+
+impl TryFrom<RawWorldTime> for WorldTime {
+	type Error = Report;
+
+	fn try_from(raw: RawWorldTime) -> Result<Self> {
+		if raw.day_length < MIN_DAY_LENGTH {
+			bail!("A day of {} ticks is shorter than {MIN_DAY_LENGTH}", raw.day_length);
+		}
+
+		Ok(WorldTime {
+			ticks: raw.ticks,
+			day_length: raw.day_length,
+			changed: false,
+		})
+	}
+}
+
+impl Default for WorldTime {
+	/// New worlds start at sunrise.
+	fn default() -> Self {
+		WorldTime {
+			ticks: DEFAULT_DAY_LENGTH / 4,
+			day_length: DEFAULT_DAY_LENGTH,
+			changed: false,
+		}
+	}
+}
+
+impl WorldTime {
+	pub fn tick(&mut self) { self.ticks = self.ticks.wrapping_add(1); }
+
+	pub fn ticks(&self) -> u64 { self.ticks }
+
+	pub fn day_length(&self) -> u64 { self.day_length }
+
+	/// How many full days passed.
+	pub fn day(&self) -> u64 { self.ticks / self.day_length }
+
+	/// How far into the day it is, from `0.0` at midnight to `0.5` at noon.
+	pub fn time_of_day(&self) -> f32 {
+		(self.ticks % self.day_length) as f32 / self.day_length as f32
+	}
+
+	/// Starts at full moon and goes through [`MOON_PHASES`] phases, one every day.
+	pub fn moon_phase(&self) -> u8 { (self.day() % MOON_PHASES) as u8 }
+
+	pub fn is_day(&self) -> bool { (0.25..0.75).contains(&self.time_of_day()) }
+
+	/// How bright the sky is, from [`MIN_SKY_LIGHT`] at midnight to `1.0` at noon.
+	pub fn sky_light(&self) -> f32 {
+		let sun = 0.5 - (self.time_of_day() * TAU).cos() * 0.5;
+		MIN_SKY_LIGHT + sun * (1.0 - MIN_SKY_LIGHT)
+	}
+
+	/// Moves the clock within the current day, the day and moon phase stay the same.
+	pub fn set_time_of_day(&mut self, time: f32) {
+		let time = time.rem_euclid(1.0);
+		let offset = ((time * self.day_length as f32) as u64).min(self.day_length - 1);
+		self.ticks = self.day() * self.day_length + offset;
+		self.changed = true;
+	}
+
+	/// Changes the length of a day while keeping the day and time of day.
+	pub fn set_day_length(&mut self, day_length: u64) {
+		let (day, time) = (self.day(), self.time_of_day());
+		self.day_length = day_length.max(MIN_DAY_LENGTH);
+		self.ticks = day * self.day_length;
+		self.set_time_of_day(time);
+	}
+
+	/// If the time got set since the last call.
+	pub(crate) fn take_changed(&mut self) -> bool { std::mem::take(&mut self.changed) }
+}
+
+/// The [`WorldTime`] of a world, shared with Lua.
+#[derive(Clone, Default)]
+pub struct WorldClock(Arc<Mutex<WorldTime>>);
+
+impl WorldClock {
+	pub fn get(&self) -> WorldTime { *self.0.lock() }
+
+	pub fn lock(&self) -> MutexGuard<'_, WorldTime> { self.0.lock() }
+}
+
+#[lua_impl]
+impl WorldClock {
+	/// From `0.0` at midnight to `0.5` at noon.
+	#[lua_method]
+	pub fn get_time(&self) -> f32 { self.get().time_of_day() }
+
+	#[lua_method]
+	pub fn set_time(&mut self, time: f32) { self.lock().set_time_of_day(time); }
+
+	#[lua_method]
+	pub fn get_day(&self) -> u64 { self.get().day() }
+
+	/// The length of a day in ticks.
+	#[lua_method]
+	pub fn get_day_length(&self) -> u64 { self.get().day_length() }
+
+	#[lua_method]
+	pub fn set_day_length(&mut self, day_length: u64) { self.lock().set_day_length(day_length); }
+
+	#[lua_method]
+	pub fn get_moon_phase(&self) -> u8 { self.get().moon_phase() }
+
+	#[lua_method]
+	pub fn is_day(&self) -> bool { self.get().is_day() }
+
+	#[lua_method]
+	pub fn get_sky_light(&self) -> f32 { self.get().sky_light() }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn days_and_moon_phases_advance() {
+		let mut time = WorldTime::default();
+		assert!(time.is_day());
+		assert_eq!(time.day(), 0);
+
+		for _ in 0..DEFAULT_DAY_LENGTH {
+			time.tick();
+		}
+		assert_eq!(time.day(), 1);
+		assert_eq!(time.moon_phase(), 1);
+		assert!((time.time_of_day() - 0.25).abs() < 1e-5);
+		assert!(!time.take_changed());
+	}
+
+	#[test]
+	fn setting_time_keeps_the_day() {
+		let mut time = WorldTime::default();
+		time.set_day_length(DEFAULT_DAY_LENGTH * 2);
+		time.set_time_of_day(2.5);
+		assert_eq!(time.day(), 0);
+		assert!((time.time_of_day() - 0.5).abs() < 1e-5);
+		assert!((time.sky_light() - 1.0).abs() < 1e-5);
+		assert!(time.take_changed());
+
+		time.set_time_of_day(0.0);
+		assert!((time.sky_light() - MIN_SKY_LIGHT).abs() < 1e-5);
+		assert!(!time.is_day());
+	}
+
+	#[test]
+	fn rejects_short_days() {
+		let mut time = WorldTime::default();
+		time.set_day_length(MIN_DAY_LENGTH);
+		let data = bincode::serialize(&time).unwrap();
+		assert_eq!(bincode::deserialize::<WorldTime>(&data).unwrap(), WorldTime {
+			changed: false,
+			..time
+		});
+
+		time.day_length = MIN_DAY_LENGTH - 1;
+		let data = bincode::serialize(&time).unwrap();
+		assert!(bincode::deserialize::<WorldTime>(&data).is_err());
+	}
+}